@@ -0,0 +1,176 @@
+//! Relays text between a Meshtastic channel and the game chat through the
+//! backend's `/api/mesh` endpoints. The backend picks what goes out and
+//! spaces it to respect the duty cycle; this only moves the messages.
+
+use std::{collections::HashMap, error::Error, fmt, time::Duration};
+
+use futures_util::StreamExt;
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use meshtastic::{
+    api::{ConnectedStreamApi, state},
+    packet::{PacketDestination, PacketRouter},
+    protobufs::{FromRadio, MeshPacket, PortNum, from_radio, mesh_packet},
+    types::{MeshChannel, NodeId},
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// How long to wait before reconnecting to the outbox stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Same shape as the backend's `mesh::MeshText`.
+#[derive(Debug, Serialize, Deserialize)]
+struct MeshText {
+    from: String,
+    text: String,
+}
+
+/// We only send, so there is nothing to route back.
+struct Router {
+    node_id: NodeId,
+}
+
+#[derive(Debug)]
+struct RouterError;
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("packet router error")
+    }
+}
+
+impl Error for RouterError {}
+
+impl PacketRouter<(), RouterError> for Router {
+    fn handle_packet_from_radio(&mut self, _packet: FromRadio) -> Result<(), RouterError> {
+        Ok(())
+    }
+
+    fn handle_mesh_packet(&mut self, _packet: MeshPacket) -> Result<(), RouterError> {
+        Ok(())
+    }
+
+    fn source_node_id(&self) -> NodeId {
+        self.node_id
+    }
+}
+
+/// Bridges `channel` to the game at `server` (e.g. `http://127.0.0.1:3000`)
+/// until the radio goes away. `token` is the server's `APEX_BRIDGE_TOKEN`.
+pub async fn run(
+    mut decoded: UnboundedReceiver<FromRadio>,
+    api: &mut ConnectedStreamApi<state::Configured>,
+    server: &str,
+    token: Option<&str>,
+    channel: u32,
+) -> Result<(), Box<dyn Error>> {
+    let http = client(token)?;
+    let mesh_channel = MeshChannel::new(channel)?;
+    let mut router = Router { node_id: NodeId::new(0) };
+    let mut my_node = None;
+    let mut names: HashMap<u32, String> = HashMap::new();
+
+    let (outbox_tx, mut outbox) = mpsc::unbounded_channel();
+    tokio::spawn(follow_outbox(http.clone(), format!("{server}/api/mesh/outbox"), outbox_tx));
+    let inbox = format!("{server}/api/mesh/messages");
+
+    loop {
+        tokio::select! {
+            packet = decoded.recv() => {
+                let Some(packet) = packet else {
+                    println!("Radio disconnected");
+                    return Ok(());
+                };
+                match packet.payload_variant {
+                    Some(from_radio::PayloadVariant::MyInfo(info)) => {
+                        my_node = Some(info.my_node_num);
+                        router.node_id = NodeId::new(info.my_node_num);
+                    }
+                    Some(from_radio::PayloadVariant::NodeInfo(node)) => {
+                        if let Some(user) = node.user {
+                            names.insert(node.num, user.long_name);
+                        }
+                    }
+                    Some(from_radio::PayloadVariant::Packet(packet)) if Some(packet.from) != my_node => {
+                        let Some(text) = channel_text(&packet, channel) else {
+                            continue;
+                        };
+                        let from = names.get(&packet.from).cloned().unwrap_or_else(|| format!("!{:08x}", packet.from));
+                        println!("Mesh -> game: {from}: {text}");
+                        let request = http.post(&inbox).json(&MeshText { from, text });
+                        tokio::spawn(async move {
+                            if let Err(e) = request.send().await.and_then(|r| r.error_for_status()) {
+                                println!("Could not post mesh message to the game: {e}");
+                            }
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            Some(message) = outbox.recv() => {
+                let text = format!("{}: {}", message.from, message.text);
+                println!("Game -> mesh: {text}");
+                api.send_text(&mut router, text, PacketDestination::Broadcast, false, mesh_channel).await?;
+            }
+        }
+    }
+}
+
+/// An HTTP client that sends `token` as a bearer token with every request.
+fn client(token: Option<&str>) -> Result<reqwest::Client, Box<dyn Error>> {
+    let mut headers = HeaderMap::new();
+    if let Some(token) = token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {token}"))?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(reqwest::Client::builder().default_headers(headers).build()?)
+}
+
+/// Text message payload of `packet` if it was sent on `channel`.
+fn channel_text(packet: &MeshPacket, channel: u32) -> Option<String> {
+    if packet.channel != channel {
+        return None;
+    }
+    let Some(mesh_packet::PayloadVariant::Decoded(data)) = &packet.payload_variant else {
+        return None;
+    };
+    if data.portnum != PortNum::TextMessageApp as i32 {
+        return None;
+    }
+    String::from_utf8(data.payload.clone()).ok()
+}
+
+/// Forwards the backend's server-sent outbox events, reconnecting whenever
+/// the stream drops.
+async fn follow_outbox(http: reqwest::Client, url: String, tx: mpsc::UnboundedSender<MeshText>) {
+    while !tx.is_closed() {
+        if let Err(e) = read_outbox(&http, &url, &tx).await {
+            println!("Outbox stream failed: {e}");
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+async fn read_outbox(
+    http: &reqwest::Client,
+    url: &str,
+    tx: &mpsc::UnboundedSender<MeshText>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut body = http.get(url).send().await?.error_for_status()?.bytes_stream();
+    let mut buffer = String::new();
+    while let Some(chunk) = body.next().await {
+        buffer.push_str(&String::from_utf8_lossy(&chunk?));
+        // Events end with a blank line; keep any partial one for the next chunk.
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            for data in event.lines().filter_map(|line| line.strip_prefix("data:")) {
+                match serde_json::from_str::<MeshText>(data.trim()) {
+                    Ok(message) => tx.send(message)?,
+                    Err(e) => println!("Skipping unreadable outbox event: {e}"),
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,11 +1,38 @@
+mod bridge;
 
 use std::io::{self, BufRead};
 
 use meshtastic::api::StreamApi;
 use meshtastic::utils;
 
+/// `balloon [--bridge http://127.0.0.1:3000] [--token <APEX_BRIDGE_TOKEN>] [--channel 0]`.
+/// Without `--bridge` received packets are only printed.
+struct Options {
+    bridge: Option<String>,
+    token: Option<String>,
+    channel: u32,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut opts = Options { bridge: None, token: None, channel: 0 };
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("missing value for {flag}"))?;
+            match flag.as_str() {
+                "--bridge" => opts.bridge = Some(value.trim_end_matches('/').to_string()),
+                "--token" => opts.token = Some(value),
+                "--channel" => opts.channel = value.parse().map_err(|_| format!("invalid channel: {value}"))?,
+                _ => return Err(format!("unknown flag {flag}")),
+            }
+        }
+        Ok(opts)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opts = Options::from_args()?;
     let stream_api = StreamApi::new();
 
     let available_ports = utils::stream::available_serial_ports()?;
@@ -24,10 +51,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (mut decoded_listener, stream_api) = stream_api.connect(serial_stream).await;
 
     let config_id = utils::generate_rand_id();
-    let stream_api = stream_api.configure(config_id).await?;
+    let mut stream_api = stream_api.configure(config_id).await?;
 
-    while let Some(decoded) = decoded_listener.recv().await {
-        println!("Received: {:?}", decoded);
+    if let Some(server) = &opts.bridge {
+        println!("Bridging mesh channel {} with {}", opts.channel, server);
+        bridge::run(decoded_listener, &mut stream_api, server, opts.token.as_deref(), opts.channel).await?;
+    } else {
+        while let Some(decoded) = decoded_listener.recv().await {
+            println!("Received: {:?}", decoded);
+        }
     }
     let _stream_api = stream_api.disconnect().await?;
 
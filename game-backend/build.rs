@@ -0,0 +1,16 @@
+//! With `embed-assets`, rebuild whenever the frontend build changes: the
+//! embedding macro can't tell cargo about files it reads.
+
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_EMBED_ASSETS").is_none() {
+        return;
+    }
+    if Path::new("assets").exists() {
+        println!("cargo:rerun-if-changed=assets");
+    } else {
+        println!("cargo:warning=embed-assets is on but assets/ doesn't exist, so no frontend is embedded");
+    }
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use apex_protocol::{ClientMessage, ServerMessage};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    let _ = ClientMessage::decode(text);
+    if let Ok(message) = ServerMessage::decode(text) {
+        // Whatever decodes must settle after one re-encode.
+        let canonical = message.encode();
+        let again = ServerMessage::decode(&canonical).expect("encoded message should decode");
+        assert_eq!(again.encode(), canonical);
+    }
+});
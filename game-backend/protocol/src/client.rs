@@ -0,0 +1,102 @@
+use crate::DecodeError;
+
+/// What a client can send.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    /// `move x z`: where the player wants to be.
+    Move { x: f32, z: f32 },
+    /// `chat text`, never blank.
+    Chat(String),
+    /// `teamchat text`: chat only the player's team sees, never blank.
+    TeamChat(String),
+    /// `follow name` points a spectator's camera at a player; bare
+    /// `follow` stops following.
+    Follow(Option<String>),
+    /// `mark x z name` places a waypoint everyone sees; the name may be
+    /// left out for a numbered one.
+    Mark { x: f32, z: f32, name: String },
+    /// `unmark id` removes one of the player's own waypoints.
+    Unmark(u32),
+}
+
+impl ClientMessage {
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        let (command, rest) = text.split_once(' ').unwrap_or((text, ""));
+        match command {
+            "" => Err(DecodeError::Empty),
+            "move" => {
+                let coords: Vec<&str> = rest.split_whitespace().collect();
+                let [x, z] = coords[..] else {
+                    return Err(DecodeError::Missing("move coordinates"));
+                };
+                match (x.parse(), z.parse()) {
+                    (Ok(x), Ok(z)) => Ok(ClientMessage::Move { x, z }),
+                    _ => Err(DecodeError::InvalidNumber(rest.to_string())),
+                }
+            }
+            "chat" if rest.trim().is_empty() => Err(DecodeError::EmptyChat),
+            "chat" => Ok(ClientMessage::Chat(rest.to_string())),
+            "teamchat" if rest.trim().is_empty() => Err(DecodeError::EmptyChat),
+            "teamchat" => Ok(ClientMessage::TeamChat(rest.to_string())),
+            "mark" => {
+                let mut parts = rest.trim_start().splitn(3, ' ');
+                let (Some(x), Some(z)) = (parts.next().filter(|x| !x.is_empty()), parts.next()) else {
+                    return Err(DecodeError::Missing("waypoint position"));
+                };
+                let name = parts.next().unwrap_or("").trim().to_string();
+                match (x.parse(), z.parse()) {
+                    (Ok(x), Ok(z)) => Ok(ClientMessage::Mark { x, z, name }),
+                    _ => Err(DecodeError::InvalidNumber(rest.to_string())),
+                }
+            }
+            "unmark" => rest.trim().parse().map(ClientMessage::Unmark).map_err(|_| DecodeError::InvalidNumber(rest.to_string())),
+            "follow" => Ok(ClientMessage::Follow(Some(rest.trim()).filter(|t| !t.is_empty()).map(str::to_string))),
+            _ => Err(DecodeError::UnknownCommand(command.to_string())),
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        match self {
+            ClientMessage::Move { x, z } => format!("move {} {}", x, z),
+            ClientMessage::Chat(text) => format!("chat {}", text),
+            ClientMessage::TeamChat(text) => format!("teamchat {}", text),
+            ClientMessage::Follow(Some(target)) => format!("follow {}", target),
+            ClientMessage::Follow(None) => "follow".to_string(),
+            ClientMessage::Mark { x, z, name } => format!("mark {} {} {}", x, z, name).trim_end().to_string(),
+            ClientMessage::Unmark(id) => format!("unmark {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_commands() {
+        assert_eq!(ClientMessage::decode("move 1.5 -2"), Ok(ClientMessage::Move { x: 1.5, z: -2.0 }));
+        assert_eq!(ClientMessage::decode("chat hi there"), Ok(ClientMessage::Chat("hi there".into())));
+        assert_eq!(ClientMessage::decode("teamchat go north"), Ok(ClientMessage::TeamChat("go north".into())));
+        assert_eq!(ClientMessage::decode("follow  bob "), Ok(ClientMessage::Follow(Some("bob".into()))));
+        assert_eq!(ClientMessage::decode("follow"), Ok(ClientMessage::Follow(None)));
+        assert_eq!(
+            ClientMessage::decode("mark 10 -4.5 crater  rim "),
+            Ok(ClientMessage::Mark { x: 10.0, z: -4.5, name: "crater  rim".into() })
+        );
+        assert_eq!(ClientMessage::decode("mark 1 2"), Ok(ClientMessage::Mark { x: 1.0, z: 2.0, name: String::new() }));
+        assert_eq!(ClientMessage::decode("unmark 7"), Ok(ClientMessage::Unmark(7)));
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert_eq!(ClientMessage::decode(""), Err(DecodeError::Empty));
+        assert_eq!(ClientMessage::decode("move 1"), Err(DecodeError::Missing("move coordinates")));
+        assert_eq!(ClientMessage::decode("move a b"), Err(DecodeError::InvalidNumber("a b".into())));
+        assert_eq!(ClientMessage::decode("chat   "), Err(DecodeError::EmptyChat));
+        assert_eq!(ClientMessage::decode("teamchat"), Err(DecodeError::EmptyChat));
+        assert_eq!(ClientMessage::decode("jump"), Err(DecodeError::UnknownCommand("jump".into())));
+        assert_eq!(ClientMessage::decode("mark 1"), Err(DecodeError::Missing("waypoint position")));
+        assert_eq!(ClientMessage::decode("unmark -1"), Err(DecodeError::InvalidNumber("-1".into())));
+        assert_eq!(ClientMessage::decode("followme"), Err(DecodeError::UnknownCommand("followme".into())));
+    }
+}
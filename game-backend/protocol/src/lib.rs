@@ -0,0 +1,54 @@
+//! The text protocol spoken over `/ws`.
+//!
+//! Clients send one command per frame (`move x z`, `chat text`,
+//! `teamchat text`, `follow name`, `mark x z name`, `unmark id`). The
+//! server sends the world's trees once (`Trees:...`) and then
+//! `Key:value;...` snapshots whenever something changes, chat always last.
+//! Decoding never panics, whatever the input.
+
+mod client;
+mod server;
+
+use std::fmt;
+
+pub use client::ClientMessage;
+pub use server::{ChatLine, Objective, PlayerPosition, ServerMessage, Snapshot, TeamScore, Tree, Waypoint, Weather, WorldTime};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    Empty,
+    UnknownCommand(String),
+    /// A command or key that needs a value didn't get one.
+    Missing(&'static str),
+    /// A number, or the right count of them, was expected.
+    InvalidNumber(String),
+    EmptyChat,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Empty => f.write_str("empty message"),
+            DecodeError::UnknownCommand(command) => write!(f, "unknown command {command:?}"),
+            DecodeError::Missing(what) => write!(f, "missing {what}"),
+            DecodeError::InvalidNumber(value) => write!(f, "invalid number in {value:?}"),
+            DecodeError::EmptyChat => f.write_str("empty chat message"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Parses exactly `N` comma-separated numbers.
+fn numbers<const N: usize>(value: &str) -> Result<[f32; N], DecodeError> {
+    let invalid = || DecodeError::InvalidNumber(value.to_string());
+    let mut out = [0.0; N];
+    let mut parts = value.split(',');
+    for slot in &mut out {
+        *slot = parts.next().and_then(|p| p.trim().parse().ok()).ok_or_else(invalid)?;
+    }
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(out)
+}
@@ -0,0 +1,434 @@
+use crate::{DecodeError, numbers};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    pub x: f32,
+    pub z: f32,
+    pub rotation: f32,
+    pub scale: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub x: f32,
+    pub z: f32,
+    pub seconds_left: u64,
+}
+
+/// The shared time of day: `seconds` since midnight at Unix time `at_ms`,
+/// running on at `speed` game seconds per second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WorldTime {
+    pub seconds: f32,
+    pub speed: f32,
+    pub at_ms: u64,
+}
+
+/// Fog and cloud cover from 0 (none) to 1, wind in m/s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+    pub fog: f32,
+    pub clouds: f32,
+    pub wind: f32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlayerPosition {
+    pub name: String,
+    pub x: f32,
+    pub z: f32,
+    /// `0xrrggbb`. Sent for other players only, not for `follow`.
+    pub color: Option<u32>,
+    pub team: Option<String>,
+}
+
+/// A team's points from game modes so far.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TeamScore {
+    pub name: String,
+    /// `0xrrggbb`.
+    pub color: u32,
+    pub score: u32,
+    pub players: usize,
+}
+
+/// A marker a player placed for everyone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub id: u32,
+    pub x: f32,
+    pub z: f32,
+    /// Who placed it.
+    pub owner: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatLine {
+    pub sender: String,
+    pub message: String,
+}
+
+/// One state update. The first one a connection gets carries `spawn`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Where the player starts, only in the first snapshot.
+    pub spawn: Option<[f32; 2]>,
+    pub seed: u32,
+    /// Feet, as the HUD shows it.
+    pub balloon_height: f32,
+    pub signal: f32,
+    pub avg_ping: f32,
+    /// Everyone else online.
+    pub players: usize,
+    pub time: Option<WorldTime>,
+    /// The server's Unix ms clock, only in the first snapshot, so clients
+    /// can run `time` on without trusting their own clock.
+    pub now_ms: Option<u64>,
+    pub weather: Option<Weather>,
+    /// World position of the balloon, once it has one.
+    pub balloon: Option<[f32; 3]>,
+    pub phase: Option<String>,
+    /// Gallery id of the newest balloon picture.
+    pub image: Option<u64>,
+    pub objective: Option<Objective>,
+    /// All-time leaderboard place.
+    pub rank: Option<u32>,
+    /// What everyone else sees the player as, only in the first snapshot.
+    pub name: Option<String>,
+    /// The player's own team and color, `0xrrggbb`.
+    pub team: Option<String>,
+    pub color: Option<u32>,
+    /// Raw telemetry as JSON, for spectators.
+    pub telemetry: Option<String>,
+    /// The player a spectator follows.
+    pub follow: Option<PlayerPosition>,
+    pub teams: Vec<TeamScore>,
+    pub others: Vec<PlayerPosition>,
+    pub waypoints: Vec<Waypoint>,
+    pub chat: Vec<ChatLine>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    Trees(Vec<Tree>),
+    State(Box<Snapshot>),
+}
+
+/// Values can't contain the part separator.
+fn clean(value: &str) -> String {
+    value.replace(';', "")
+}
+
+/// Team names also can't close a `[name]` key.
+fn clean_team(team: &str) -> String {
+    clean(team).replace(']', "")
+}
+
+fn color(value: &str) -> Result<u32, DecodeError> {
+    u32::from_str_radix(value, 16)
+        .ok()
+        .filter(|c| *c <= 0xffffff)
+        .ok_or_else(|| DecodeError::InvalidNumber(value.to_string()))
+}
+
+impl Snapshot {
+    pub fn encode(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some([x, z]) = self.spawn {
+            parts.push(format!("Spawn:{:.2},{:.2}", x, z));
+        }
+        parts.push(format!("Seed:{}", self.seed));
+        parts.push(format!("BalloonHeight:{}", self.balloon_height));
+        parts.push(format!("Signal:{}", self.signal));
+        parts.push(format!("AvgPing:{:.2}", self.avg_ping));
+        parts.push(format!("Players:{}", self.players));
+        if let Some(t) = self.time {
+            parts.push(format!("Time:{:.1},{},{}", t.seconds, t.speed, t.at_ms));
+        }
+        if let Some(now_ms) = self.now_ms {
+            parts.push(format!("Now:{}", now_ms));
+        }
+        if let Some(w) = self.weather {
+            parts.push(format!("Weather:{:.2},{:.2},{:.1}", w.fog, w.clouds, w.wind));
+        }
+        if let Some([x, y, z]) = self.balloon {
+            parts.push(format!("Balloon:{:.2},{:.2},{:.2}", x, y, z));
+        }
+        if let Some(phase) = &self.phase {
+            parts.push(format!("Phase:{}", clean(phase)));
+        }
+        if let Some(id) = self.image {
+            parts.push(format!("Image:{}", id));
+        }
+        if let Some(o) = &self.objective {
+            parts.push(format!("Objective:{:.2},{:.2},{}", o.x, o.z, o.seconds_left));
+        }
+        if let Some(rank) = self.rank {
+            parts.push(format!("Rank:{}", rank));
+        }
+        if let Some(name) = &self.name {
+            parts.push(format!("Name:{}", clean(name)));
+        }
+        if let Some(team) = &self.team {
+            parts.push(format!("Team:{}", clean_team(team)));
+        }
+        if let Some(color) = self.color {
+            parts.push(format!("Color:{:06x}", color));
+        }
+        if let Some(telemetry) = &self.telemetry {
+            parts.push(format!("Telemetry:{}", clean(telemetry)));
+        }
+        if let Some(f) = &self.follow {
+            // The name goes first and may contain commas, so it's split off from the right.
+            parts.push(format!("Follow:{},{:.2},{:.2}", clean(&f.name), f.x, f.z));
+        }
+        for t in &self.teams {
+            parts.push(format!("TeamScore[{}]:{:06x},{},{}", clean_team(&t.name), t.color, t.score, t.players));
+        }
+        for p in &self.others {
+            parts.push(format!("P[{}]:{:.2},{:.2}", clean(&p.name), p.x, p.z));
+            // Colors follow their player's position, which older clients
+            // can't tell from a bare position.
+            match (p.color, &p.team) {
+                (Some(color), Some(team)) => parts.push(format!("C[{}]:{:06x},{}", clean(&p.name), color, clean_team(team))),
+                (Some(color), None) => parts.push(format!("C[{}]:{:06x}", clean(&p.name), color)),
+                (None, _) => {}
+            }
+        }
+        for w in &self.waypoints {
+            // Owner and name are split on the first `>`, as in chat.
+            parts.push(format!("W[{}]:{:.2},{:.2},{}>{}", w.id, w.x, w.z, clean(&w.owner).replace('>', ""), clean(&w.name)));
+        }
+        if !self.chat.is_empty() {
+            let lines = self
+                .chat
+                .iter()
+                .map(|line| format!("{}>{}", clean(&line.sender).replace('>', ""), clean(&line.message)))
+                .collect::<Vec<String>>()
+                .join(";");
+            parts.push(format!("Chat:{}", lines));
+        }
+        parts.join(";")
+    }
+
+    /// Unknown keys are skipped so older clients keep working as keys are added.
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        let mut snapshot = Snapshot::default();
+        let (head, chat) = match text.strip_prefix("Chat:") {
+            Some(chat) => ("", Some(chat)),
+            None => match text.split_once(";Chat:") {
+                Some((head, chat)) => (head, Some(chat)),
+                None => (text, None),
+            },
+        };
+
+        for part in head.split(';').filter(|p| !p.is_empty()) {
+            if let Some(player) = part.strip_prefix("P[") {
+                let (name, coords) = player.rsplit_once("]:").ok_or(DecodeError::Missing("player position"))?;
+                let [x, z] = numbers(coords)?;
+                snapshot.others.push(PlayerPosition { name: name.to_string(), x, z, ..PlayerPosition::default() });
+                continue;
+            }
+            if let Some(player) = part.strip_prefix("C[") {
+                let (name, value) = player.rsplit_once("]:").ok_or(DecodeError::Missing("player color"))?;
+                let (hex, team) = match value.split_once(',') {
+                    Some((hex, team)) => (hex, Some(team.to_string())),
+                    None => (value, None),
+                };
+                let color = color(hex)?;
+                // Only meaningful right after that player's position.
+                if let Some(last) = snapshot.others.last_mut().filter(|p| p.name == name && p.color.is_none()) {
+                    last.color = Some(color);
+                    last.team = team;
+                }
+                continue;
+            }
+            if let Some(team) = part.strip_prefix("TeamScore[") {
+                let (name, value) = team.split_once("]:").ok_or(DecodeError::Missing("team score"))?;
+                let mut fields = value.split(',');
+                let (Some(hex), Some(score), Some(players), None) = (fields.next(), fields.next(), fields.next(), fields.next())
+                else {
+                    return Err(DecodeError::Missing("team score"));
+                };
+                let invalid = || DecodeError::InvalidNumber(value.to_string());
+                snapshot.teams.push(TeamScore {
+                    name: name.to_string(),
+                    color: color(hex)?,
+                    score: score.parse().map_err(|_| invalid())?,
+                    players: players.parse().map_err(|_| invalid())?,
+                });
+                continue;
+            }
+            if let Some(waypoint) = part.strip_prefix("W[") {
+                let (id, value) = waypoint.split_once("]:").ok_or(DecodeError::Missing("waypoint"))?;
+                let id = id.parse().map_err(|_| DecodeError::InvalidNumber(id.to_string()))?;
+                let mut fields = value.splitn(3, ',');
+                let (Some(x), Some(z), Some(label)) = (fields.next(), fields.next(), fields.next()) else {
+                    return Err(DecodeError::Missing("waypoint position"));
+                };
+                let [x, z] = numbers(&format!("{},{}", x, z))?;
+                let (owner, name) = label.split_once('>').ok_or(DecodeError::Missing("waypoint owner"))?;
+                snapshot.waypoints.push(Waypoint { id, x, z, owner: owner.to_string(), name: name.to_string() });
+                continue;
+            }
+            let Some((key, value)) = part.split_once(':') else {
+                continue;
+            };
+            let int = || value.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()));
+            match key {
+                "Spawn" => snapshot.spawn = Some(numbers(value)?),
+                "Seed" => snapshot.seed = int()?,
+                "BalloonHeight" => snapshot.balloon_height = numbers::<1>(value)?[0],
+                "Signal" => snapshot.signal = numbers::<1>(value)?[0],
+                "AvgPing" => snapshot.avg_ping = numbers::<1>(value)?[0],
+                "Players" => snapshot.players = value.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()))?,
+                "Time" => {
+                    let (clock, at_ms) = value.rsplit_once(',').ok_or(DecodeError::Missing("time anchor"))?;
+                    let [seconds, speed] = numbers(clock)?;
+                    let at_ms = at_ms.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()))?;
+                    snapshot.time = Some(WorldTime { seconds, speed, at_ms });
+                }
+                "Now" => snapshot.now_ms = Some(value.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()))?),
+                "Weather" => {
+                    let [fog, clouds, wind] = numbers(value)?;
+                    snapshot.weather = Some(Weather { fog, clouds, wind });
+                }
+                "Balloon" => snapshot.balloon = Some(numbers(value)?),
+                "Phase" => snapshot.phase = Some(value.to_string()),
+                "Image" => snapshot.image = Some(value.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()))?),
+                "Objective" => {
+                    let (position, seconds) = value.rsplit_once(',').ok_or(DecodeError::Missing("objective time"))?;
+                    let [x, z] = numbers(position)?;
+                    let seconds_left = seconds.parse().map_err(|_| DecodeError::InvalidNumber(value.to_string()))?;
+                    snapshot.objective = Some(Objective { x, z, seconds_left });
+                }
+                "Rank" => snapshot.rank = Some(int()?),
+                "Name" => snapshot.name = Some(value.to_string()),
+                "Team" => snapshot.team = Some(value.to_string()),
+                "Color" => snapshot.color = Some(color(value)?),
+                "Telemetry" => snapshot.telemetry = Some(value.to_string()),
+                "Follow" => {
+                    let (rest, z) = value.rsplit_once(',').ok_or(DecodeError::Missing("follow position"))?;
+                    let (name, x) = rest.rsplit_once(',').ok_or(DecodeError::Missing("follow position"))?;
+                    let [x, z] = numbers(&format!("{},{}", x, z))?;
+                    snapshot.follow = Some(PlayerPosition { name: name.to_string(), x, z, ..PlayerPosition::default() });
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(chat) = chat {
+            snapshot.chat = chat
+                .split(';')
+                .map(|line| {
+                    let (sender, message) = line.split_once('>').unwrap_or(("", line));
+                    ChatLine { sender: sender.to_string(), message: message.to_string() }
+                })
+                .collect();
+        }
+        Ok(snapshot)
+    }
+}
+
+impl ServerMessage {
+    pub fn encode(&self) -> String {
+        match self {
+            ServerMessage::Trees(trees) => {
+                let trees = trees
+                    .iter()
+                    .map(|t| format!("{:.2},{:.2},{:.3},{:.3}", t.x, t.z, t.rotation, t.scale))
+                    .collect::<Vec<String>>()
+                    .join(";");
+                format!("Trees:{}", trees)
+            }
+            ServerMessage::State(snapshot) => snapshot.encode(),
+        }
+    }
+
+    pub fn decode(text: &str) -> Result<Self, DecodeError> {
+        let Some(trees) = text.strip_prefix("Trees:") else {
+            return Snapshot::decode(text).map(|s| ServerMessage::State(Box::new(s)));
+        };
+        trees
+            .split(';')
+            .filter(|t| !t.is_empty())
+            .map(|t| numbers(t).map(|[x, z, rotation, scale]| Tree { x, z, rotation, scale }))
+            .collect::<Result<_, _>>()
+            .map(ServerMessage::Trees)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_in_wire_order() {
+        let snapshot = Snapshot {
+            spawn: Some([1.0, 2.0]),
+            seed: 7,
+            balloon_height: 3281.0,
+            signal: -90.0,
+            avg_ping: 12.345,
+            players: 1,
+            balloon: Some([0.0, 10.0, -5.0]),
+            objective: Some(Objective { x: 1.0, z: 2.0, seconds_left: 30 }),
+            team: Some("red".into()),
+            color: Some(0xff4040),
+            teams: vec![TeamScore { name: "red".into(), color: 0xff4040, score: 12, players: 2 }],
+            others: vec![PlayerPosition {
+                name: "[::1]:5000".into(),
+                x: 3.0,
+                z: 4.0,
+                color: Some(0x0000bf),
+                team: Some("red".into()),
+            }],
+            waypoints: vec![Waypoint { id: 2, x: 5.0, z: 6.0, owner: "a>b".into(), name: "rim, north>east".into() }],
+            chat: vec![ChatLine { sender: "Server".into(), message: "hi;there".into() }],
+            ..Snapshot::default()
+        };
+        let text = snapshot.encode();
+        assert_eq!(
+            text,
+            "Spawn:1.00,2.00;Seed:7;BalloonHeight:3281;Signal:-90;AvgPing:12.35;Players:1;\
+             Balloon:0.00,10.00,-5.00;Objective:1.00,2.00,30;Team:red;Color:ff4040;TeamScore[red]:ff4040,12,2;\
+             P[[::1]:5000]:3.00,4.00;C[[::1]:5000]:0000bf,red;\
+             W[2]:5.00,6.00,ab>rim, north>east;Chat:Server>hithere"
+        );
+        let decoded = Snapshot::decode(&text).unwrap();
+        assert_eq!(decoded.others, snapshot.others);
+        assert_eq!(decoded.teams, snapshot.teams);
+        assert_eq!((decoded.team, decoded.color), (snapshot.team, snapshot.color));
+        assert_eq!(decoded.waypoints[0].owner, "ab");
+        assert_eq!(decoded.waypoints[0].name, "rim, north>east");
+        assert_eq!(decoded.chat[0].message, "hithere");
+    }
+
+    #[test]
+    fn colors_only_attach_to_the_player_before_them() {
+        let decoded = Snapshot::decode("C[a]:ff0000;P[a]:1,2;P[b]:3,4;C[a]:00ff00;C[b]:0000ff").unwrap();
+        assert_eq!(decoded.others[0].color, None);
+        assert_eq!(decoded.others[1].color, Some(0x0000ff));
+        assert_eq!(decoded.others[1].team, None);
+        assert!(Snapshot::decode("P[a]:1,2;C[a]:1000000").is_err());
+    }
+
+    #[test]
+    fn chat_may_contain_separators_of_other_parts() {
+        let decoded = Snapshot::decode("Seed:1;Chat:a>b:c;d>P[x]:1,2").unwrap();
+        assert!(decoded.others.is_empty());
+        assert_eq!(decoded.chat[1], ChatLine { sender: "d".into(), message: "P[x]:1,2".into() });
+    }
+
+    #[test]
+    fn decodes_trees() {
+        let message = ServerMessage::decode("Trees:1.00,2.00,0.500,1.250;-3.00,4.00,0.000,1.000").unwrap();
+        let ServerMessage::Trees(trees) = &message else {
+            panic!("expected trees");
+        };
+        assert_eq!(trees[1], Tree { x: -3.0, z: 4.0, rotation: 0.0, scale: 1.0 });
+        assert_eq!(message.encode(), "Trees:1.00,2.00,0.500,1.250;-3.00,4.00,0.000,1.000");
+        assert!(ServerMessage::decode("Trees:1,2,3").is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use apex_protocol::{ChatLine, ClientMessage, Objective, PlayerPosition, ServerMessage, Snapshot, TeamScore, Tree, Waypoint, Weather, WorldTime};
+use proptest::prelude::*;
+
+fn client_message() -> impl Strategy<Value = ClientMessage> {
+    prop_oneof![
+        (-1e6f32..1e6, -1e6f32..1e6).prop_map(|(x, z)| ClientMessage::Move { x, z }),
+        "[^\\s].*".prop_map(ClientMessage::Chat),
+        "[^\\s].*".prop_map(ClientMessage::TeamChat),
+        proptest::option::of("[^\\s]([^\\n]*[^\\s])?").prop_map(ClientMessage::Follow),
+        (-1e6f32..1e6, -1e6f32..1e6, "([^\\s]([^\\n]*[^\\s])?)?")
+            .prop_map(|(x, z, name)| ClientMessage::Mark { x, z, name }),
+        any::<u32>().prop_map(ClientMessage::Unmark),
+    ]
+}
+
+fn position() -> impl Strategy<Value = PlayerPosition> {
+    (".*", any::<f32>(), any::<f32>(), proptest::option::of(0..=0xffffffu32), proptest::option::of(".*"))
+        .prop_map(|(name, x, z, color, team)| PlayerPosition { name, x, z, color, team })
+}
+
+prop_compose! {
+    fn snapshot()(
+        spawn in proptest::option::of(any::<[f32; 2]>()),
+        seed in any::<u32>(),
+        balloon_height in any::<f32>(),
+        signal in any::<f32>(),
+        avg_ping in any::<f32>(),
+        players in any::<usize>(),
+        time in proptest::option::of((any::<f32>(), any::<f32>(), any::<u64>())),
+        now_ms in proptest::option::of(any::<u64>()),
+        weather in proptest::option::of(any::<[f32; 3]>()),
+        balloon in proptest::option::of(any::<[f32; 3]>()),
+        phase in proptest::option::of(".*"),
+        image in proptest::option::of(any::<u64>()),
+        objective in proptest::option::of((any::<f32>(), any::<f32>(), any::<u64>())),
+        rank in proptest::option::of(any::<u32>()),
+        name in proptest::option::of(".*"),
+        team in proptest::option::of(".*"),
+        color in proptest::option::of(0..=0xffffffu32),
+        teams in proptest::collection::vec((".*", 0..=0xffffffu32, any::<u32>(), any::<usize>()), 0..3),
+        telemetry in proptest::option::of(".*"),
+        follow in proptest::option::of(position()),
+        others in proptest::collection::vec(position(), 0..4),
+        waypoints in proptest::collection::vec((any::<u32>(), any::<[f32; 2]>(), ".*", ".*"), 0..4),
+        chat in proptest::collection::vec((".*", ".*"), 0..4),
+    ) -> Snapshot {
+        Snapshot {
+            spawn,
+            seed,
+            balloon_height,
+            signal,
+            avg_ping,
+            players,
+            time: time.map(|(seconds, speed, at_ms)| WorldTime { seconds, speed, at_ms }),
+            now_ms,
+            weather: weather.map(|[fog, clouds, wind]| Weather { fog, clouds, wind }),
+            balloon,
+            phase,
+            image,
+            objective: objective.map(|(x, z, seconds_left)| Objective { x, z, seconds_left }),
+            rank,
+            name,
+            team,
+            color,
+            teams: teams
+                .into_iter()
+                .map(|(name, color, score, players)| TeamScore { name, color, score, players })
+                .collect(),
+            telemetry,
+            follow,
+            others,
+            waypoints: waypoints
+                .into_iter()
+                .map(|(id, [x, z], owner, name)| Waypoint { id, x, z, owner, name })
+                .collect(),
+            chat: chat.into_iter().map(|(sender, message)| ChatLine { sender, message }).collect(),
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn client_messages_round_trip(message in client_message()) {
+        prop_assert_eq!(ClientMessage::decode(&message.encode()), Ok(message));
+    }
+
+    /// Encoding rounds numbers and drops separators, so compare the wire text
+    /// after one decode instead of the values.
+    #[test]
+    fn snapshots_reencode_identically(snapshot in snapshot()) {
+        let text = snapshot.encode();
+        let decoded = Snapshot::decode(&text).unwrap();
+        prop_assert_eq!(decoded.encode(), text);
+    }
+
+    #[test]
+    fn trees_reencode_identically(trees in proptest::collection::vec(any::<[f32; 4]>(), 0..8)) {
+        let trees = trees.into_iter().map(|[x, z, rotation, scale]| Tree { x, z, rotation, scale }).collect();
+        let text = ServerMessage::Trees(trees).encode();
+        prop_assert_eq!(ServerMessage::decode(&text).unwrap().encode(), text);
+    }
+
+    #[test]
+    fn decoding_never_panics(text in ".*") {
+        let _ = ClientMessage::decode(&text);
+        let _ = ServerMessage::decode(&text);
+    }
+
+    #[test]
+    fn decoding_snapshot_like_text_never_panics(
+        parts in proptest::collection::vec("(Spawn|Seed|Balloon|Objective|Follow|P\\[[^;]*\\]|W\\[[0-9]*\\]|C\\[[^;]*\\]|TeamScore\\[[^;]*\\]|Team|Color|Chat)?:[-0-9.,;>a-z]*", 0..8)
+    ) {
+        let _ = ServerMessage::decode(&parts.join(";"));
+    }
+}
@@ -0,0 +1,262 @@
+//! Telemetry alerts: rules checked on every update (thresholds and rates
+//! of change) and every tick (no data for too long), with hysteresis so a
+//! value hovering at the threshold doesn't flap.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::telemetry::Telemetry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Altitude,
+    Signal,
+    Temperature,
+    Pressure,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Altitude, Field::Signal, Field::Temperature, Field::Pressure];
+
+    fn read(self, telemetry: &Telemetry) -> Option<f64> {
+        match self {
+            Field::Altitude => telemetry.altitude,
+            Field::Signal => telemetry.signal.map(f64::from),
+            Field::Temperature => telemetry.temperature,
+            Field::Pressure => telemetry.pressure,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Altitude => "altitude",
+            Field::Signal => "signal",
+            Field::Temperature => "temperature",
+            Field::Pressure => "pressure",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "when", rename_all = "snake_case")]
+pub enum Condition {
+    Below { value: f64 },
+    Above { value: f64 },
+    /// Per second.
+    RisingFaster { rate: f64 },
+    /// Per second, as a positive number.
+    FallingFaster { rate: f64 },
+    /// The field hasn't been reported for this long since it last was.
+    NoDataFor { secs: u64 },
+}
+
+/// One rule, e.g. `{"name": "cold payload", "field": "temperature",
+/// "when": "below", "value": -20, "hysteresis": 2}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    pub field: Field,
+    #[serde(flatten)]
+    pub condition: Condition,
+    /// How far back past the threshold a value has to go to clear the alert.
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+/// What the rules watch out of the box.
+pub fn default_rules() -> Vec<AlertRule> {
+    vec![
+        AlertRule {
+            name: "payload cold".to_string(),
+            field: Field::Temperature,
+            condition: Condition::Below { value: -20.0 },
+            hysteresis: 2.0,
+        },
+        AlertRule {
+            name: "weak signal".to_string(),
+            field: Field::Signal,
+            condition: Condition::Below { value: -115.0 },
+            hysteresis: 3.0,
+        },
+        AlertRule {
+            name: "signal lost".to_string(),
+            field: Field::Signal,
+            condition: Condition::NoDataFor { secs: 120 },
+            hysteresis: 0.0,
+        },
+    ]
+}
+
+/// A rule starting or stopping to fire.
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct AlertEvent {
+    pub rule: String,
+    /// False when the alert cleared.
+    pub firing: bool,
+    /// The reading that tripped or cleared it; none for no-data alerts.
+    pub value: Option<f64>,
+    pub message: String,
+    /// Unix time in milliseconds.
+    pub at_ms: i64,
+}
+
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    firing: Vec<bool>,
+    /// Latest reading of each field and when it came in.
+    last: HashMap<Field, (Instant, f64)>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let firing = vec![false; rules.len()];
+        Self { rules, firing, last: HashMap::new() }
+    }
+
+    /// Checks threshold and rate rules against an update.
+    pub fn on_telemetry(&mut self, telemetry: &Telemetry, now: Instant, at_ms: i64) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for field in Field::ALL {
+            let Some(value) = field.read(telemetry) else {
+                continue;
+            };
+            let rate = self
+                .last
+                .get(&field)
+                .map(|&(then, previous)| (now - then, previous))
+                .filter(|(elapsed, _)| !elapsed.is_zero())
+                .map(|(elapsed, previous)| (value - previous) / elapsed.as_secs_f64());
+            self.last.insert(field, (now, value));
+
+            for (i, rule) in self.rules.iter().enumerate().filter(|(_, r)| r.field == field) {
+                let firing = self.firing[i];
+                let h = rule.hysteresis;
+                let (now_firing, shown) = match rule.condition {
+                    Condition::Below { value: limit } => (value < if firing { limit + h } else { limit }, value),
+                    Condition::Above { value: limit } => (value > if firing { limit - h } else { limit }, value),
+                    Condition::RisingFaster { rate: limit } => match rate {
+                        Some(rate) => (rate > if firing { limit - h } else { limit }, rate),
+                        None => continue,
+                    },
+                    Condition::FallingFaster { rate: limit } => match rate {
+                        Some(rate) => (-rate > if firing { limit - h } else { limit }, rate),
+                        None => continue,
+                    },
+                    // Any reading ends a data gap.
+                    Condition::NoDataFor { .. } => (false, value),
+                };
+                if now_firing != firing {
+                    self.firing[i] = now_firing;
+                    events.push(event(rule, now_firing, Some(shown), at_ms));
+                }
+            }
+        }
+        events
+    }
+
+    /// Checks no-data rules. Fields never reported don't count as missing.
+    pub fn tick(&mut self, now: Instant, at_ms: i64) -> Vec<AlertEvent> {
+        let mut events = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let Condition::NoDataFor { secs } = rule.condition else {
+                continue;
+            };
+            let Some(&(seen, _)) = self.last.get(&rule.field) else {
+                continue;
+            };
+            if !self.firing[i] && now - seen >= Duration::from_secs(secs) {
+                self.firing[i] = true;
+                events.push(event(rule, true, None, at_ms));
+            }
+        }
+        events
+    }
+}
+
+fn event(rule: &AlertRule, firing: bool, value: Option<f64>, at_ms: i64) -> AlertEvent {
+    let field = rule.field.name();
+    let message = match (&rule.condition, value) {
+        _ if !firing => format!("{} cleared", rule.name),
+        (Condition::Below { value: limit }, Some(v)) => format!("{}: {} {:.1} below {}", rule.name, field, v, limit),
+        (Condition::Above { value: limit }, Some(v)) => format!("{}: {} {:.1} above {}", rule.name, field, v, limit),
+        (Condition::RisingFaster { .. } | Condition::FallingFaster { .. }, Some(v)) => {
+            format!("{}: {} changing {:.1}/s", rule.name, field, v)
+        }
+        (Condition::NoDataFor { secs }, _) => format!("{}: no {} data for {} s", rule.name, field, secs),
+        (_, None) => rule.name.clone(),
+    };
+    AlertEvent { rule: rule.name.clone(), firing, value, message, at_ms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(field: Field, condition: Condition, hysteresis: f64) -> AlertRule {
+        AlertRule { name: "test".to_string(), field, condition, hysteresis }
+    }
+
+    fn temperature(value: f64) -> Telemetry {
+        Telemetry { temperature: Some(value), ..Telemetry::default() }
+    }
+
+    #[test]
+    fn threshold_has_hysteresis() {
+        let mut alerts = Alerts::new(vec![rule(Field::Temperature, Condition::Below { value: 0.0 }, 2.0)]);
+        let now = Instant::now();
+        let mut feed = |v| alerts.on_telemetry(&temperature(v), now, 0).iter().map(|e| e.firing).collect::<Vec<_>>();
+
+        assert!(feed(1.0).is_empty());
+        assert_eq!(feed(-1.0), [true]);
+        assert!(feed(0.5).is_empty(), "still within the hysteresis band");
+        assert!(feed(-3.0).is_empty(), "already firing");
+        assert_eq!(feed(2.5), [false]);
+    }
+
+    #[test]
+    fn rate_of_change() {
+        let mut alerts = Alerts::new(vec![rule(Field::Temperature, Condition::FallingFaster { rate: 1.0 }, 0.0)]);
+        let start = Instant::now();
+        assert!(alerts.on_telemetry(&temperature(10.0), start, 0).is_empty());
+        assert!(alerts.on_telemetry(&temperature(9.5), start + Duration::from_secs(1), 0).is_empty());
+        let events = alerts.on_telemetry(&temperature(7.0), start + Duration::from_secs(2), 0);
+        assert_eq!(events[0].message, "test: temperature changing -2.5/s");
+    }
+
+    #[test]
+    fn no_data_after_first_reading() {
+        let mut alerts = Alerts::new(vec![rule(Field::Signal, Condition::NoDataFor { secs: 10 }, 0.0)]);
+        let start = Instant::now();
+        assert!(alerts.tick(start + Duration::from_secs(60), 0).is_empty(), "never reported");
+
+        let signal = Telemetry { signal: Some(-90.0), ..Telemetry::default() };
+        alerts.on_telemetry(&signal, start, 0);
+        assert!(alerts.tick(start + Duration::from_secs(5), 0).is_empty());
+        assert_eq!(alerts.tick(start + Duration::from_secs(10), 0)[0].message, "test: no signal data for 10 s");
+        assert!(alerts.tick(start + Duration::from_secs(20), 0).is_empty(), "fires once");
+        assert!(!alerts.on_telemetry(&signal, start + Duration::from_secs(21), 0)[0].firing);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rules: Vec<AlertRule> = serde_json::from_str(
+            r#"[{"name": "cold", "field": "temperature", "when": "below", "value": -20, "hysteresis": 2},
+                {"name": "lost", "field": "signal", "when": "no_data_for", "secs": 60}]"#,
+        )
+        .unwrap();
+        assert_eq!(rules[0], rule(Field::Temperature, Condition::Below { value: -20.0 }, 2.0).named("cold"));
+        assert_eq!(rules[1].condition, Condition::NoDataFor { secs: 60 });
+    }
+
+    impl AlertRule {
+        fn named(mut self, name: &str) -> Self {
+            self.name = name.to_string();
+            self
+        }
+    }
+}
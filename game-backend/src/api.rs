@@ -0,0 +1,302 @@
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    body::Bytes,
+    extract::{FromRequestParts, Path, Query, State},
+    http::{StatusCode, header, request::Parts},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{get, post, put},
+};
+use futures::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info, warn};
+
+use crate::{
+    alerts::AlertEvent,
+    clock::{ClockChange, ClockStatus},
+    db::{HuntStanding, PlayerStats, SortBy, Window},
+    flight::MissionEvent,
+    gallery::{ImageEntry, decode_png, parse_palette},
+    game::GameHandle,
+    mesh::MeshText,
+    metrics::MetricsSnapshot,
+    teams::TeamScore,
+    telemetry::Telemetry,
+    websockets::AppState,
+};
+
+/// JSON endpoints under `/api`.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/telemetry", post(post_telemetry))
+        .route("/metrics", get(get_metrics))
+        .route("/images", post(post_image).get(list_images))
+        .route("/images/{id}", get(get_image))
+        .route("/hunt/leaderboard", get(get_hunt_leaderboard))
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/events", get(get_events))
+        .route("/alerts", get(get_alerts))
+        .route("/alerts/stream", get(stream_alerts))
+        .route("/mesh/messages", post(post_mesh_message))
+        .route("/mesh/outbox", get(stream_mesh_outbox))
+        .route("/teams", get(get_teams))
+        .route("/time", get(get_time))
+        .route("/admin/time", put(put_time))
+}
+
+/// Proof that a request carried `Authorization: Bearer <APEX_ADMIN_TOKEN>`.
+/// Without a configured token every admin endpoint answers 404.
+struct Admin;
+
+impl FromRequestParts<AppState> for Admin {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        bearer(parts, state.config.admin_token.as_deref(), "admin").map(|()| Admin)
+    }
+}
+
+/// Proof that a request came from the ground station, carrying
+/// `Authorization: Bearer <APEX_STATION_TOKEN>`. Without a configured token
+/// its endpoints answer 404.
+struct Station;
+
+impl FromRequestParts<AppState> for Station {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        bearer(parts, state.config.station_token.as_deref(), "station").map(|()| Station)
+    }
+}
+
+/// Proof that a request came from the mesh radio bridge, carrying
+/// `Authorization: Bearer <APEX_BRIDGE_TOKEN>`. Without a configured token
+/// its endpoints answer 404.
+struct Bridge;
+
+impl FromRequestParts<AppState> for Bridge {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        bearer(parts, state.config.bridge_token.as_deref(), "bridge").map(|()| Bridge)
+    }
+}
+
+/// Checks the request's bearer token against `expected`, the token a `role`
+/// is configured with.
+fn bearer(parts: &Parts, expected: Option<&str>, role: &'static str) -> Result<(), StatusCode> {
+    let Some(expected) = expected else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let given = parts
+        .headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match given {
+        Some(given) if tokens_match(given, expected) => Ok(()),
+        _ => {
+            warn!(role, "rejected request without a valid token");
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+/// Compares without stopping at the first difference, so response times
+/// don't leak how much of the token was right.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len() && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+async fn post_telemetry(_: Station, State(state): State<GameHandle>, Json(telemetry): Json<Telemetry>) -> StatusCode {
+    state.apply_telemetry(telemetry).await;
+    StatusCode::NO_CONTENT
+}
+
+async fn get_metrics(State(state): State<AppState>) -> Json<MetricsSnapshot> {
+    Json(state.metrics.snapshot())
+}
+
+#[derive(Deserialize)]
+struct UploadParams {
+    /// `rrggbb,...` in the order the encoder produced it; without it the
+    /// image is decoded in grayscale.
+    palette: Option<String>,
+}
+
+/// Takes a raw `compressed_image.bin` body, decodes it and tells the game.
+async fn post_image(
+    _: Station,
+    State(state): State<AppState>,
+    Query(params): Query<UploadParams>,
+    payload: Bytes,
+) -> Result<(StatusCode, Json<ImageEntry>), (StatusCode, String)> {
+    if payload.len() > state.config.max_image_bytes {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, "image too large".to_string()));
+    }
+    let palette = params
+        .palette
+        .as_deref()
+        .map(parse_palette)
+        .transpose()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let telemetry = state.game.last_telemetry().await;
+
+    let gallery = state.gallery.clone();
+    let entry = tokio::task::spawn_blocking(move || {
+        let png = decode_png(&payload, palette.as_deref()).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        gallery
+            .add(&payload, &png, palette.is_some(), telemetry)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    })
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))??;
+
+    info!(id = entry.id, bytes = entry.compressed_bytes, "stored balloon image");
+    state.game.image_added(entry.id).await;
+    Ok((StatusCode::CREATED, Json(entry)))
+}
+
+async fn list_images(State(state): State<AppState>) -> Json<Vec<ImageEntry>> {
+    Json(state.gallery.list())
+}
+
+async fn get_image(State(state): State<AppState>, Path(id): Path<u64>) -> Response {
+    match state.gallery.png(id) {
+        Some(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+const HUNT_LEADERBOARD_SIZE: i64 = 50;
+
+async fn get_hunt_leaderboard(State(state): State<AppState>) -> Result<Json<Vec<HuntStanding>>, StatusCode> {
+    state.db.hunt_leaderboard(HUNT_LEADERBOARD_SIZE).await.map(Json).map_err(|e| {
+        error!("failed to read hunt leaderboard: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+const LEADERBOARD_DEFAULT: i64 = 50;
+const LEADERBOARD_MAX: i64 = 100;
+
+#[derive(Deserialize)]
+struct LeaderboardParams {
+    /// `score` (default), `wins`, `distance`, `time` or `chats`.
+    #[serde(default)]
+    sort: SortBy,
+    /// `24h`, `7d`, `30d` or `all` (default).
+    #[serde(default)]
+    window: Window,
+    limit: Option<i64>,
+}
+
+async fn get_leaderboard(
+    State(state): State<AppState>,
+    Query(params): Query<LeaderboardParams>,
+) -> Result<Json<Vec<PlayerStats>>, StatusCode> {
+    let limit = params.limit.unwrap_or(LEADERBOARD_DEFAULT).clamp(1, LEADERBOARD_MAX);
+    state.db.leaderboard(params.sort, params.window, limit).await.map(Json).map_err(|e| {
+        error!("failed to read leaderboard: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+const EVENTS_DEFAULT: i64 = 100;
+const EVENTS_MAX: i64 = 1000;
+
+#[derive(Deserialize)]
+struct EventsParams {
+    /// Only events after this Unix ms time, e.g. the last one already seen.
+    #[serde(default)]
+    since: i64,
+    limit: Option<i64>,
+}
+
+/// The mission timeline: launch, max altitude, burst and landing, oldest first.
+async fn get_events(
+    State(state): State<AppState>,
+    Query(params): Query<EventsParams>,
+) -> Result<Json<Vec<MissionEvent>>, StatusCode> {
+    let limit = params.limit.unwrap_or(EVENTS_DEFAULT).clamp(1, EVENTS_MAX);
+    state.db.mission_events(params.since, limit).await.map(Json).map_err(|e| {
+        error!("failed to read mission events: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+const ALERT_HISTORY_SIZE: i64 = 100;
+
+async fn get_alerts(State(state): State<AppState>) -> Result<Json<Vec<AlertEvent>>, StatusCode> {
+    state.db.alert_history(ALERT_HISTORY_SIZE).await.map(Json).map_err(|e| {
+        error!("failed to read alert history: {e}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+/// Server-sent events: `alert` when a rule starts firing, `cleared` when it stops.
+async fn stream_alerts(State(state): State<GameHandle>) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    sse(state.subscribe_alerts(), |alert| if alert.firing { "alert" } else { "cleared" })
+}
+
+/// Text the ground station heard on the mesh channel.
+async fn post_mesh_message(_: Bridge, State(state): State<GameHandle>, Json(message): Json<MeshText>) -> StatusCode {
+    if message.text.trim().is_empty() {
+        return StatusCode::BAD_REQUEST;
+    }
+    state.mesh_message(message).await;
+    StatusCode::NO_CONTENT
+}
+
+/// Server-sent `message` events with game chat for the ground station to transmit.
+async fn stream_mesh_outbox(
+    _: Bridge,
+    State(state): State<GameHandle>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    sse(state.subscribe_mesh(), |_| "message")
+}
+
+async fn get_teams(State(state): State<GameHandle>) -> Result<Json<Vec<TeamScore>>, StatusCode> {
+    state.teams().await.map(Json).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+async fn get_time(State(state): State<GameHandle>) -> Result<Json<ClockStatus>, StatusCode> {
+    state.clock().await.map(Json).ok_or(StatusCode::SERVICE_UNAVAILABLE)
+}
+
+/// Sets, speeds up or freezes the world clock, e.g.
+/// `{"time": "18:30", "speed": 60, "frozen": false}`.
+async fn put_time(
+    _: Admin,
+    State(state): State<GameHandle>,
+    Json(change): Json<ClockChange>,
+) -> Result<Json<ClockStatus>, (StatusCode, String)> {
+    if change.speed.is_some_and(|speed| !speed.is_finite() || speed < 0.0) {
+        return Err((StatusCode::BAD_REQUEST, "speed must be zero or more".to_string()));
+    }
+    info!(?change, "admin changed the world clock");
+    let status = state.set_clock(change).await;
+    status.map(Json).ok_or((StatusCode::SERVICE_UNAVAILABLE, "game stopped".to_string()))
+}
+
+/// Streams everything sent on `rx` as JSON events named by `event_name`.
+fn sse<T: Clone + Serialize + Send + 'static>(
+    rx: broadcast::Receiver<T>,
+    event_name: fn(&T) -> &'static str,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let events = stream::unfold(rx, move |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(item) => return Some((Event::default().event(event_name(&item)).json_data(&item), rx)),
+                Err(RecvError::Lagged(missed)) => warn!(missed, "event stream subscriber fell behind"),
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(events).keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+}
@@ -0,0 +1,196 @@
+//! The frontend. Built with the `embed-assets` feature, the SvelteKit build
+//! in `assets/` is compiled into the binary so it runs from anywhere, with
+//! ETags and any `.br`/`.gz` variants the build produced. Paths that aren't
+//! embedded (all of them, without the feature) are served from
+//! `APEX_ASSETS_DIR` when one is configured.
+
+use std::{borrow::Cow, path::Path};
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tower::ServiceExt;
+use tower_http::services::ServeDir;
+
+use crate::websockets::AppState;
+
+/// Build output under here has content hashes in its names and never changes.
+const IMMUTABLE_PREFIX: &str = "_app/immutable/";
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Everything else is revalidated with its ETag (or Last-Modified on disk).
+const REVALIDATE: &str = "no-cache";
+
+/// Pre-compressed variants by file extension, most preferred first.
+const ENCODINGS: [(&str, &str); 2] = [("br", "br"), ("gz", "gzip")];
+
+#[cfg(feature = "embed-assets")]
+#[derive(rust_embed::RustEmbed)]
+#[folder = "assets/"]
+#[allow_missing = true]
+struct Embedded;
+
+struct Asset {
+    data: Cow<'static, [u8]>,
+    sha256: [u8; 32],
+    mime: String,
+}
+
+#[cfg(feature = "embed-assets")]
+fn embedded(path: &str) -> Option<Asset> {
+    let file = Embedded::get(path)?;
+    Some(Asset { mime: file.metadata.mimetype().to_string(), sha256: file.metadata.sha256_hash(), data: file.data })
+}
+
+#[cfg(not(feature = "embed-assets"))]
+fn embedded(_path: &str) -> Option<Asset> {
+    None
+}
+
+/// Serves a directory the way the embedded files are, minus the ETags.
+pub fn disk(dir: &Path) -> ServeDir {
+    ServeDir::new(dir)
+        .append_index_html_on_directories(true)
+        .precompressed_br()
+        .precompressed_gzip()
+}
+
+/// Router fallback: embedded files first, then the disk directory.
+pub async fn serve(State(state): State<AppState>, request: Request) -> Response {
+    let path = request.uri().path().trim_start_matches('/').to_string();
+    if let Some(response) = respond(request.method(), &path, request.headers(), embedded) {
+        return response;
+    }
+    let Some(dir) = state.assets else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let mut response = dir.oneshot(request).await.into_response();
+    if response.status().is_success() {
+        response.headers_mut().entry(header::CACHE_CONTROL).or_insert(HeaderValue::from_static(cache_control(&path)));
+    }
+    response
+}
+
+/// Builds the response for `path` from whatever `lookup` finds, or `None`
+/// if it has nothing there.
+fn respond(method: &Method, path: &str, headers: &HeaderMap, lookup: impl Fn(&str) -> Option<Asset>) -> Option<Response> {
+    if method != Method::GET && method != Method::HEAD {
+        return None;
+    }
+    let (path, asset) = candidates(path).into_iter().find_map(|p| lookup(&p).map(|asset| (p, asset)))?;
+    let mime = asset.mime.clone();
+    let (asset, encoding) = accepted_encodings(headers)
+        .find_map(|(ext, name)| lookup(&format!("{path}.{ext}")).map(|variant| (variant, Some(name))))
+        .unwrap_or((asset, None));
+    let etag = etag(&asset.sha256);
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control(&path))
+        .header(header::VARY, "Accept-Encoding");
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding);
+    }
+    let response = if not_modified(headers, &etag) {
+        response.status(StatusCode::NOT_MODIFIED).body(Body::empty())
+    } else {
+        let body = if method == Method::HEAD { Body::empty() } else { Body::from(asset.data.clone()) };
+        response.header(header::CONTENT_LENGTH, asset.data.len()).body(body)
+    };
+    Some(response.unwrap_or_else(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response()))
+}
+
+/// Files that can answer `path`: directories mean their `index.html`, and
+/// SvelteKit writes pages as `page.html`.
+fn candidates(path: &str) -> Vec<String> {
+    if path.is_empty() || path.ends_with('/') {
+        return vec![format!("{path}index.html")];
+    }
+    vec![path.to_string(), format!("{path}.html"), format!("{path}/index.html")]
+}
+
+/// Variants the client accepts, most preferred first.
+fn accepted_encodings(headers: &HeaderMap) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+    let accepted: Vec<&str> = headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next()?;
+            let refused = params.any(|p| p.strip_prefix("q=").and_then(|q| q.parse::<f32>().ok()) == Some(0.0));
+            (!refused).then_some(name)
+        })
+        .collect();
+    ENCODINGS.into_iter().filter(move |(_, name)| accepted.contains(name))
+}
+
+fn etag(sha256: &[u8; 32]) -> String {
+    let hex: String = sha256[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!("\"{hex}\"")
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.split(',').map(|t| t.trim().trim_start_matches("W/")).any(|t| t == etag || t == "*"))
+}
+
+fn cache_control(path: &str) -> &'static str {
+    if path.starts_with(IMMUTABLE_PREFIX) { IMMUTABLE } else { REVALIDATE }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(path: &str) -> Option<Asset> {
+        let (data, mime): (&'static [u8], _) = match path {
+            "index.html" => (b"<html>", "text/html"),
+            "_app/immutable/app.js" => (b"console.log(1)", "text/javascript"),
+            "_app/immutable/app.js.gz" => (b"gzipped", "application/gzip"),
+            _ => return None,
+        };
+        Some(Asset { data: Cow::Borrowed(data), sha256: [data.len() as u8; 32], mime: mime.to_string() })
+    }
+
+    fn headers(pairs: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        pairs.iter().map(|(k, v)| (k.clone(), HeaderValue::from_static(v))).collect()
+    }
+
+    #[test]
+    fn directories_serve_their_index() {
+        let response = respond(&Method::GET, "", &HeaderMap::new(), lookup).unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], REVALIDATE);
+        assert!(respond(&Method::GET, "missing.js", &HeaderMap::new(), lookup).is_none());
+        assert!(respond(&Method::POST, "", &HeaderMap::new(), lookup).is_none());
+    }
+
+    #[test]
+    fn picks_an_accepted_precompressed_variant() {
+        let gzip = headers(&[(header::ACCEPT_ENCODING, "br;q=0, gzip, deflate")]);
+        let response = respond(&Method::GET, "_app/immutable/app.js", &gzip, lookup).unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], IMMUTABLE);
+
+        let plain = respond(&Method::GET, "_app/immutable/app.js", &HeaderMap::new(), lookup).unwrap();
+        assert!(plain.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_ne!(plain.headers()[header::ETAG], response.headers()[header::ETAG]);
+    }
+
+    #[test]
+    fn matching_etag_is_not_modified() {
+        let etag = respond(&Method::GET, "", &HeaderMap::new(), lookup).unwrap().headers()[header::ETAG].clone();
+        let mut conditional = HeaderMap::new();
+        conditional.insert(header::IF_NONE_MATCH, etag);
+        let response = respond(&Method::GET, "index.html", &conditional, lookup).unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+}
@@ -0,0 +1,226 @@
+//! The real payload as a world entity: GPS fixes mapped onto world
+//! coordinates and smoothed between telemetry updates.
+
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use tracing::info;
+
+use crate::config::Config;
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+/// Bounds for how long the balloon takes to glide to a new fix.
+const MIN_GLIDE: Duration = Duration::from_millis(200);
+const MAX_GLIDE: Duration = Duration::from_secs(30);
+/// Slower sinking than this (m/s) is drift, not a descent worth predicting.
+const MIN_DESCENT_RATE: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: f64,
+}
+
+impl FromStr for GeoPoint {
+    type Err = String;
+
+    /// Parses `lat,lon,alt`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<f64> = s
+            .split(',')
+            .map(|p| p.trim().parse::<f64>().map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [lat, lon, alt] => Ok(GeoPoint { lat: *lat, lon: *lon, alt: *alt }),
+            _ => Err(format!("expected lat,lon,alt, got {s:?}")),
+        }
+    }
+}
+
+pub struct Balloon {
+    anchor: Option<GeoPoint>,
+    scale: f64,
+    vertical_scale: f64,
+    last_fix: Option<GeoPoint>,
+    /// The fix before `last_fix` and when it arrived, for velocity estimates.
+    prev_fix: Option<(GeoPoint, Instant)>,
+    /// Where the glide towards `to` started and how long it takes.
+    from: [f32; 3],
+    to: [f32; 3],
+    glide_start: Instant,
+    glide: Duration,
+    last_update: Option<Instant>,
+}
+
+impl Balloon {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            anchor: config.geo_anchor,
+            scale: config.geo_scale,
+            vertical_scale: config.geo_vertical_scale,
+            last_fix: None,
+            prev_fix: None,
+            from: [0.0; 3],
+            to: [0.0; 3],
+            glide_start: Instant::now(),
+            glide: Duration::ZERO,
+            last_update: None,
+        }
+    }
+
+    /// Maps a fix onto world coordinates with an equirectangular projection
+    /// around the anchor; north is -z like the frontend camera's forward.
+    pub fn to_world(&self, fix: GeoPoint) -> Option<[f32; 3]> {
+        let anchor = self.anchor?;
+        let metres_per_deg = EARTH_RADIUS_M.to_radians();
+        let east = (fix.lon - anchor.lon) * metres_per_deg * anchor.lat.to_radians().cos();
+        let north = (fix.lat - anchor.lat) * metres_per_deg;
+        let up = fix.alt - anchor.alt;
+        Some([
+            (east * self.scale) as f32,
+            (up * self.vertical_scale) as f32,
+            (-north * self.scale) as f32,
+        ])
+    }
+
+    /// Takes a new GPS fix and starts gliding from the current position to it
+    /// over the interval seen between the last two updates.
+    pub fn update(&mut self, fix: GeoPoint, now: Instant) {
+        if self.anchor.is_none() {
+            info!(?fix, "no geo anchor configured, anchoring the world origin at the first balloon fix");
+            self.anchor = Some(fix);
+        }
+        let Some(target) = self.to_world(fix) else {
+            return;
+        };
+
+        match self.last_update {
+            Some(last) => {
+                self.from = self.position_at(now).unwrap_or(target);
+                self.glide = (now - last).clamp(MIN_GLIDE, MAX_GLIDE);
+            }
+            None => {
+                self.from = target;
+                self.glide = Duration::ZERO;
+            }
+        }
+        self.prev_fix = self.last_fix.zip(self.last_update);
+        self.to = target;
+        self.glide_start = now;
+        self.last_update = Some(now);
+        self.last_fix = Some(fix);
+    }
+
+    pub fn last_fix(&self) -> Option<GeoPoint> {
+        self.last_fix
+    }
+
+    /// Where the balloon will come down in world (x, z), extrapolating the
+    /// last two fixes until it is back at the anchor's altitude. `None` unless
+    /// it is clearly descending.
+    pub fn predicted_landing(&self) -> Option<[f32; 2]> {
+        let anchor = self.anchor?;
+        let (prev, prev_at) = self.prev_fix?;
+        let (last, last_at) = (self.last_fix?, self.last_update?);
+        let dt = (last_at - prev_at).as_secs_f64();
+        if dt <= 0.0 {
+            return None;
+        }
+        let descent_rate = (prev.alt - last.alt) / dt;
+        if descent_rate < MIN_DESCENT_RATE {
+            return None;
+        }
+        let time_to_ground = ((last.alt - anchor.alt) / descent_rate).max(0.0) as f32;
+        let ([px, _, pz], [lx, _, lz]) = (self.to_world(prev)?, self.to_world(last)?);
+        let dt = dt as f32;
+        Some([
+            lx + (lx - px) / dt * time_to_ground,
+            lz + (lz - pz) / dt * time_to_ground,
+        ])
+    }
+
+    /// Interpolated world position, or `None` before the first fix.
+    pub fn position_at(&self, now: Instant) -> Option<[f32; 3]> {
+        self.last_update?;
+        let t = if self.glide.is_zero() {
+            1.0
+        } else {
+            ((now - self.glide_start).as_secs_f32() / self.glide.as_secs_f32()).min(1.0)
+        };
+        Some(std::array::from_fn(|i| self.from[i] + (self.to[i] - self.from[i]) * t))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(anchor: Option<GeoPoint>) -> Config {
+        Config { geo_anchor: anchor, geo_scale: 1.0, geo_vertical_scale: 1.0, ..Config::default() }
+    }
+
+    #[test]
+    fn maps_relative_to_anchor() {
+        let anchor = GeoPoint { lat: 45.0, lon: 9.0, alt: 100.0 };
+        let balloon = Balloon::new(&config(Some(anchor)));
+
+        assert_eq!(balloon.to_world(anchor), Some([0.0, 0.0, 0.0]));
+
+        // 0.01° north is ~1112 m and maps to -z.
+        let [x, y, z] = balloon.to_world(GeoPoint { lat: 45.01, ..anchor }).unwrap();
+        assert!(x.abs() < 1e-3 && y == 0.0);
+        assert!((z + 1111.95).abs() < 0.1, "z = {z}");
+
+        // East distances shrink with cos(latitude).
+        let [x, _, _] = balloon.to_world(GeoPoint { lon: 9.01, ..anchor }).unwrap();
+        assert!((x - 786.26).abs() < 0.1, "x = {x}");
+
+        let [_, y, _] = balloon.to_world(GeoPoint { alt: 1100.0, ..anchor }).unwrap();
+        assert_eq!(y, 1000.0);
+    }
+
+    #[test]
+    fn first_fix_becomes_anchor() {
+        let mut balloon = Balloon::new(&config(None));
+        let now = Instant::now();
+        assert_eq!(balloon.position_at(now), None);
+
+        balloon.update(GeoPoint { lat: 45.0, lon: 9.0, alt: 300.0 }, now);
+        assert_eq!(balloon.position_at(now), Some([0.0, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn glides_between_fixes() {
+        let mut balloon = Balloon::new(&config(None));
+        let start = Instant::now();
+        balloon.update(GeoPoint { lat: 45.0, lon: 9.0, alt: 0.0 }, start);
+
+        let next = start + Duration::from_secs(2);
+        balloon.update(GeoPoint { lat: 45.0, lon: 9.0, alt: 100.0 }, next);
+        assert_eq!(balloon.position_at(next).unwrap()[1], 0.0);
+        assert_eq!(balloon.position_at(next + Duration::from_secs(1)).unwrap()[1], 50.0);
+        assert_eq!(balloon.position_at(next + Duration::from_secs(5)).unwrap()[1], 100.0);
+    }
+
+    #[test]
+    fn predicts_landing_while_descending() {
+        let anchor = GeoPoint { lat: 45.0, lon: 9.0, alt: 0.0 };
+        let mut balloon = Balloon::new(&config(Some(anchor)));
+        let start = Instant::now();
+
+        balloon.update(GeoPoint { alt: 1000.0, ..anchor }, start);
+        balloon.update(GeoPoint { alt: 1100.0, ..anchor }, start + Duration::from_secs(10));
+        assert_eq!(balloon.predicted_landing(), None, "still ascending");
+
+        // Falling 10 m/s from 1000 m while drifting east: 100 s to go.
+        let east = balloon.to_world(GeoPoint { lon: 9.001, ..anchor }).unwrap()[0];
+        balloon.update(GeoPoint { alt: 1100.0, ..anchor }, start + Duration::from_secs(20));
+        balloon.update(GeoPoint { lon: 9.001, alt: 1000.0, ..anchor }, start + Duration::from_secs(30));
+        let [x, z] = balloon.predicted_landing().unwrap();
+        assert!((x - east * 11.0).abs() < 0.01, "x = {x}");
+        assert!(z.abs() < 1e-3);
+    }
+}
@@ -0,0 +1,293 @@
+//! Headless load-test client: spawns simulated players against `/ws`.
+//!
+//! Usage: `cargo run --release --bin loadtest -- [--url ws://127.0.0.1:3000/ws]
+//! [--bots 50] [--duration 30] [--ramp-ms 20] [--move-hz 30] [--chat-interval 10]`
+//!
+//! Every bot connects from the same address, so start the server with
+//! `APEX_MAX_CONNECTIONS_PER_IP=0 APEX_CONNECTIONS_PER_MINUTE=0`
+//! (`just load-test-server` does).
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use apex_protocol::{ClientMessage, ServerMessage};
+use futures::{sink::SinkExt, stream::StreamExt};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+struct Options {
+    url: String,
+    bots: usize,
+    duration: Duration,
+    ramp: Duration,
+    move_hz: f32,
+    chat_interval: Duration,
+}
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut opts = Options {
+            url: "ws://127.0.0.1:3000/ws".to_string(),
+            bots: 50,
+            duration: Duration::from_secs(30),
+            ramp: Duration::from_millis(20),
+            move_hz: 30.0,
+            chat_interval: Duration::from_secs(10),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(flag) = args.next() {
+            let value = args.next().ok_or_else(|| format!("missing value for {flag}"))?;
+            let bad = || format!("invalid value for {flag}: {value}");
+            match flag.as_str() {
+                "--url" => opts.url = value.clone(),
+                "--bots" => opts.bots = value.parse().map_err(|_| bad())?,
+                "--duration" => opts.duration = Duration::from_secs(value.parse().map_err(|_| bad())?),
+                "--ramp-ms" => opts.ramp = Duration::from_millis(value.parse().map_err(|_| bad())?),
+                "--move-hz" => opts.move_hz = value.parse().map_err(|_| bad())?,
+                "--chat-interval" => opts.chat_interval = Duration::from_secs(value.parse().map_err(|_| bad())?),
+                _ => return Err(format!("unknown flag {flag}")),
+            }
+        }
+        if opts.move_hz <= 0.0 {
+            return Err("--move-hz must be positive".to_string());
+        }
+        Ok(opts)
+    }
+}
+
+/// Tiny xorshift so every bot wanders differently without pulling in `rand`.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Default)]
+struct BotReport {
+    connected: bool,
+    connect_time: Option<Duration>,
+    error: Option<String>,
+    moves_sent: u64,
+    chats_sent: u64,
+    snapshots: u64,
+    snapshot_bytes: u64,
+    snapshot_gaps: Vec<Duration>,
+    chat_latencies: Vec<Duration>,
+}
+
+async fn run_bot(id: usize, opts: &Options) -> BotReport {
+    let mut report = BotReport::default();
+    let started = Instant::now();
+    let (ws, _) = match connect_async(opts.url.as_str()).await {
+        Ok(ok) => ok,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+    report.connected = true;
+    report.connect_time = Some(started.elapsed());
+
+    let (mut sink, mut stream) = ws.split();
+    let mut rng = Rng::new(id as u64 + 1);
+
+    // Walk at the frontend's player speed, occasionally stopping to look around.
+    let speed = 5.0;
+    let mut x = (rng.next_f32() - 0.5) * 100.0;
+    let mut z = (rng.next_f32() - 0.5) * 100.0;
+    let mut heading = rng.next_f32() * std::f32::consts::TAU;
+    let mut idle_ticks = 0u32;
+
+    let tick = Duration::from_secs_f32(1.0 / opts.move_hz);
+    let mut move_timer = tokio::time::interval(tick);
+    let first_chat = tokio::time::Instant::now() + opts.chat_interval.mul_f32(rng.next_f32());
+    let mut chat_timer = tokio::time::interval_at(first_chat, opts.chat_interval);
+    let deadline = tokio::time::sleep(opts.duration);
+    tokio::pin!(deadline);
+
+    let mut pending_chats: HashMap<String, Instant> = HashMap::new();
+    let mut chat_seq = 0u64;
+    let mut last_snapshot: Option<Instant> = None;
+
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = move_timer.tick() => {
+                if idle_ticks > 0 {
+                    idle_ticks -= 1;
+                    continue;
+                }
+                if rng.next_f32() < 0.005 {
+                    idle_ticks = (opts.move_hz * (1.0 + rng.next_f32() * 3.0)) as u32;
+                    continue;
+                }
+                heading += (rng.next_f32() - 0.5) * 0.3;
+                x += heading.cos() * speed * tick.as_secs_f32();
+                z += heading.sin() * speed * tick.as_secs_f32();
+                if sink.send(Message::Text(ClientMessage::Move { x, z }.encode().into())).await.is_err() {
+                    report.error = Some("send failed".to_string());
+                    break;
+                }
+                report.moves_sent += 1;
+            }
+            _ = chat_timer.tick() => {
+                chat_seq += 1;
+                let token = format!("lt{id}-{chat_seq}");
+                if sink.send(Message::Text(ClientMessage::Chat(token.clone()).encode().into())).await.is_err() {
+                    report.error = Some("send failed".to_string());
+                    break;
+                }
+                pending_chats.insert(token, Instant::now());
+                report.chats_sent += 1;
+            }
+            msg = stream.next() => {
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let now = Instant::now();
+                        report.snapshots += 1;
+                        report.snapshot_bytes += text.len() as u64;
+                        if let Some(last) = last_snapshot {
+                            report.snapshot_gaps.push(now - last);
+                        }
+                        last_snapshot = Some(now);
+                        if let Ok(ServerMessage::State(snapshot)) = ServerMessage::decode(text.as_str()) {
+                            pending_chats.retain(|token, sent| {
+                                let seen = snapshot.chat.iter().any(|line| line.message == *token);
+                                if seen {
+                                    report.chat_latencies.push(now - *sent);
+                                }
+                                !seen
+                            });
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        report.error = Some("server closed connection".to_string());
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        report.error = Some(e.to_string());
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let _ = sink.send(Message::Close(None)).await;
+    report
+}
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let idx = ((sorted.len() - 1) as f64 * p).round() as usize;
+    sorted[idx]
+}
+
+fn print_distribution(label: &str, mut samples: Vec<Duration>) {
+    if samples.is_empty() {
+        println!("{label}: no samples");
+        return;
+    }
+    samples.sort();
+    println!(
+        "{label}: n={} p50={:.1}ms p90={:.1}ms p99={:.1}ms max={:.1}ms",
+        samples.len(),
+        percentile(&samples, 0.50).as_secs_f64() * 1000.0,
+        percentile(&samples, 0.90).as_secs_f64() * 1000.0,
+        percentile(&samples, 0.99).as_secs_f64() * 1000.0,
+        samples.last().unwrap().as_secs_f64() * 1000.0,
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let opts = match Options::from_args() {
+        Ok(opts) => std::sync::Arc::new(opts),
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    println!(
+        "Starting {} bots against {} for {}s (move {} Hz, chat every {}s)",
+        opts.bots,
+        opts.url,
+        opts.duration.as_secs(),
+        opts.move_hz,
+        opts.chat_interval.as_secs()
+    );
+
+    let started = Instant::now();
+    let mut handles = Vec::with_capacity(opts.bots);
+    for id in 0..opts.bots {
+        let bot_opts = opts.clone();
+        handles.push(tokio::spawn(async move { run_bot(id, &bot_opts).await }));
+        tokio::time::sleep(opts.ramp).await;
+    }
+
+    let mut reports = Vec::with_capacity(handles.len());
+    for handle in handles {
+        match handle.await {
+            Ok(report) => reports.push(report),
+            Err(e) => eprintln!("bot task panicked: {e}"),
+        }
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let connected = reports.iter().filter(|r| r.connected).count();
+    let errors: Vec<&String> = reports.iter().filter_map(|r| r.error.as_ref()).collect();
+    let snapshots: u64 = reports.iter().map(|r| r.snapshots).sum();
+    let bytes: u64 = reports.iter().map(|r| r.snapshot_bytes).sum();
+    let moves: u64 = reports.iter().map(|r| r.moves_sent).sum();
+    let chats: u64 = reports.iter().map(|r| r.chats_sent).sum();
+    let chats_seen: usize = reports.iter().map(|r| r.chat_latencies.len()).sum();
+
+    println!("\n--- Load test report ---");
+    println!("Connections: {connected}/{} succeeded", opts.bots);
+    for (error, count) in errors.iter().fold(HashMap::<&str, usize>::new(), |mut acc, e| {
+        *acc.entry(e.as_str()).or_default() += 1;
+        acc
+    }) {
+        println!("  {count}x {error}");
+    }
+    if errors.iter().any(|e| e.contains("429")) {
+        println!("  (rate limited: run the server with APEX_MAX_CONNECTIONS_PER_IP=0 APEX_CONNECTIONS_PER_MINUTE=0)");
+    }
+    print_distribution(
+        "Connect time",
+        reports.iter().filter_map(|r| r.connect_time).collect(),
+    );
+    println!(
+        "Sent: {moves} moves ({:.0}/s), {chats} chats",
+        moves as f64 / elapsed
+    );
+    println!(
+        "Received: {snapshots} snapshots ({:.0}/s), {:.1} KiB/s",
+        snapshots as f64 / elapsed,
+        bytes as f64 / 1024.0 / elapsed
+    );
+    print_distribution(
+        "Snapshot interval",
+        reports.iter().flat_map(|r| r.snapshot_gaps.iter().copied()).collect(),
+    );
+    print_distribution(
+        "Chat -> snapshot latency",
+        reports.iter().flat_map(|r| r.chat_latencies.iter().copied()).collect(),
+    );
+    println!("Chats observed in snapshots: {chats_seen}/{chats}");
+    println!("--- End of report ---");
+}
@@ -0,0 +1,235 @@
+//! The authoritative time of day, so every client puts the sun in the same
+//! place. Snapshots carry the clock's anchor (a time of day, the wall-clock
+//! moment it was read and how fast it runs) rather than the current time,
+//! so they only change when the clock itself is changed.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+const DAY_SECS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Seconds since midnight, written `HH:MM` or `HH:MM:SS`.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct TimeOfDay(f64);
+
+impl TimeOfDay {
+    /// Wraps any number of seconds into a day.
+    pub fn from_secs(secs: f64) -> Self {
+        TimeOfDay(secs.rem_euclid(DAY_SECS))
+    }
+
+    pub fn secs(self) -> f64 {
+        self.0
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0 as u32;
+        write!(f, "{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid time of day {s:?}, expected HH:MM or HH:MM:SS");
+        let fields = s.split(':').map(|f| f.parse::<u32>().map_err(|_| invalid())).collect::<Result<Vec<_>, _>>()?;
+        let (h, m, sec) = match fields[..] {
+            [h, m] => (h, m, 0),
+            [h, m, sec] => (h, m, sec),
+            _ => return Err(invalid()),
+        };
+        if h > 23 || m > 59 || sec > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay((h * 3600 + m * 60 + sec) as f64))
+    }
+}
+
+impl Serialize for TimeOfDay {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeOfDay {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Where the clock takes its time from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockMode {
+    /// Starts at the server's UTC time of day.
+    #[default]
+    RealTime,
+    /// Holds the start time until launch, then counts mission time from it.
+    Mission,
+}
+
+impl FromStr for ClockMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "real_time" | "realtime" | "real" => Ok(ClockMode::RealTime),
+            "mission" => Ok(ClockMode::Mission),
+            _ => Err(format!("unknown clock mode {s:?}, expected real_time or mission")),
+        }
+    }
+}
+
+/// What an admin can change; anything left out stays as it is.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ClockChange {
+    pub time: Option<TimeOfDay>,
+    pub speed: Option<f64>,
+    pub frozen: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClockStatus {
+    pub mode: ClockMode,
+    pub time: TimeOfDay,
+    /// Game seconds per real second while running.
+    pub speed: f64,
+    pub frozen: bool,
+    /// False for a mission clock before launch.
+    pub started: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorldClock {
+    mode: ClockMode,
+    speed: f64,
+    start: TimeOfDay,
+    /// The time of day at `since_ms` (Unix ms).
+    base: TimeOfDay,
+    since_ms: i64,
+    started: bool,
+    frozen: bool,
+}
+
+impl WorldClock {
+    /// All times are Unix ms; a real-time clock starts at `now_ms` in UTC.
+    pub fn new(mode: ClockMode, speed: f64, start: TimeOfDay, now_ms: i64) -> Self {
+        let (base, started) = match mode {
+            ClockMode::RealTime => (TimeOfDay::from_secs(now_ms as f64 / 1000.0), true),
+            ClockMode::Mission => (start, false),
+        };
+        Self { mode, speed, start, base, since_ms: now_ms, started, frozen: false }
+    }
+
+    /// Game seconds per real second right now: zero while frozen or
+    /// waiting for launch.
+    pub fn rate(&self) -> f64 {
+        if self.started && !self.frozen { self.speed } else { 0.0 }
+    }
+
+    pub fn time(&self, now_ms: i64) -> TimeOfDay {
+        let elapsed = (now_ms - self.since_ms).max(0) as f64 / 1000.0;
+        TimeOfDay::from_secs(self.base.secs() + elapsed * self.rate())
+    }
+
+    /// The time of day at a moment (Unix ms) from which it runs on at `rate`.
+    pub fn anchor(&self) -> (TimeOfDay, i64) {
+        (self.base, self.since_ms)
+    }
+
+    /// Starts a mission clock from its start time. Later launches, and
+    /// real-time clocks, are ignored.
+    pub fn launched(&mut self, now_ms: i64) {
+        if self.mode == ClockMode::Mission && !self.started {
+            self.base = self.start;
+            self.since_ms = now_ms;
+            self.started = true;
+        }
+    }
+
+    /// Applies an admin change. Setting the time also starts a mission
+    /// clock that was still waiting for launch.
+    pub fn apply(&mut self, change: &ClockChange, now_ms: i64) {
+        // Re-anchor so what has run so far keeps its old rate.
+        self.base = self.time(now_ms);
+        self.since_ms = now_ms;
+        if let Some(time) = change.time {
+            self.base = time;
+            self.started = true;
+        }
+        if let Some(speed) = change.speed {
+            self.speed = speed;
+        }
+        if let Some(frozen) = change.frozen {
+            self.frozen = frozen;
+        }
+    }
+
+    pub fn status(&self, now_ms: i64) -> ClockStatus {
+        ClockStatus {
+            mode: self.mode,
+            time: self.time(now_ms),
+            speed: self.speed,
+            frozen: self.frozen,
+            started: self.started,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> TimeOfDay {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_prints_times() {
+        assert_eq!(at("06:30").secs(), 6.5 * 3600.0);
+        assert_eq!(at("23:59:59").to_string(), "23:59:59");
+        assert!("24:00".parse::<TimeOfDay>().is_err());
+        assert!("6".parse::<TimeOfDay>().is_err());
+        assert_eq!(TimeOfDay::from_secs(DAY_SECS + 60.0).to_string(), "00:01:00");
+    }
+
+    #[test]
+    fn real_time_starts_at_utc_and_runs_at_speed() {
+        // 1970-01-02 12:00 UTC
+        let now = (DAY_SECS as i64 + 43_200) * 1000;
+        let clock = WorldClock::new(ClockMode::RealTime, 60.0, at("06:00"), now);
+        assert_eq!(clock.time(now), at("12:00"));
+        assert_eq!(clock.time(now + 60_000), at("13:00"));
+        assert_eq!(clock.anchor(), (at("12:00"), now));
+    }
+
+    #[test]
+    fn mission_clock_waits_for_launch() {
+        let mut clock = WorldClock::new(ClockMode::Mission, 10.0, at("06:00"), 0);
+        assert_eq!(clock.rate(), 0.0);
+        assert_eq!(clock.time(600_000), at("06:00"));
+
+        clock.launched(600_000);
+        assert_eq!(clock.time(636_000), at("06:06"));
+        clock.launched(636_000);
+        assert_eq!(clock.time(636_000), at("06:06"), "later launches change nothing");
+    }
+
+    #[test]
+    fn admin_changes_keep_elapsed_time() {
+        let mut clock = WorldClock::new(ClockMode::Mission, 1.0, at("06:00"), 0);
+        clock.apply(&ClockChange { time: Some(at("18:00")), ..ClockChange::default() }, 0);
+        assert_eq!(clock.time(60_000), at("18:01"));
+
+        clock.apply(&ClockChange { frozen: Some(true), ..ClockChange::default() }, 60_000);
+        assert_eq!(clock.time(660_000), at("18:01"));
+        assert_eq!(clock.rate(), 0.0);
+
+        clock.apply(&ClockChange { frozen: Some(false), speed: Some(120.0), ..ClockChange::default() }, 660_000);
+        assert_eq!(clock.time(690_000), at("19:01"));
+    }
+}
@@ -0,0 +1,290 @@
+//! Runtime configuration, read from `APEX_*` environment variables.
+//! Anything unset or unparsable falls back to its default.
+
+use std::{net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use crate::{
+    alerts::{AlertRule, default_rules},
+    balloon::GeoPoint,
+    clock::{ClockMode, TimeOfDay},
+    logging::LogFormat,
+    teams::{Team, parse_teams},
+};
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// `APEX_BIND`: address the HTTP/WebSocket server listens on.
+    pub bind: SocketAddr,
+    /// `APEX_SEED`: terrain and world generation seed.
+    pub seed: u32,
+    /// `APEX_GEO_ANCHOR` as `lat,lon,alt`: the real-world point mapped to the
+    /// world origin. Without it the balloon's first fix becomes the origin.
+    pub geo_anchor: Option<GeoPoint>,
+    /// `APEX_GEO_SCALE`: world units per metre of horizontal distance.
+    pub geo_scale: f64,
+    /// `APEX_GEO_VERTICAL_SCALE`: world units per metre of altitude.
+    pub geo_vertical_scale: f64,
+    /// `APEX_HEARTBEAT_INTERVAL_MS`: how often each connection is pinged.
+    pub heartbeat_interval: Duration,
+    /// `APEX_HEARTBEAT_TIMEOUT_MS`: silence (no frames at all, pongs included)
+    /// after which a connection is considered dead.
+    pub heartbeat_timeout: Duration,
+    /// `APEX_IDLE_TIMEOUT_SECS`: how long a player may send no input before
+    /// being removed. Zero disables the idle timeout.
+    pub idle_timeout: Duration,
+    /// `APEX_MAX_FRAME_BYTES`: largest WebSocket frame/message accepted from
+    /// a client; anything bigger closes the connection.
+    pub max_frame_bytes: usize,
+    /// `APEX_OUTBOUND_QUEUE`: frames buffered per client before snapshots
+    /// start being coalesced.
+    pub outbound_queue: usize,
+    /// `APEX_SLOW_CLIENT_TIMEOUT_MS`: how long a client's queue may stay full
+    /// before it is disconnected.
+    pub slow_client_timeout: Duration,
+    /// `APEX_TLS_CERT` / `APEX_TLS_KEY`: PEM certificate chain and private
+    /// key. With both set the server speaks HTTPS/wss:// on `bind`.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// `APEX_TLS_RELOAD_SECS`: how often the certificate files are checked
+    /// for changes.
+    pub tls_reload_interval: Duration,
+    /// `APEX_HTTP_REDIRECT_BIND`: plain HTTP listener redirecting to HTTPS.
+    /// Only used with TLS enabled.
+    pub http_redirect_bind: Option<SocketAddr>,
+    /// `APEX_ALLOWED_ORIGINS`: comma-separated origins allowed to open a
+    /// WebSocket, e.g. `https://apex.example`. Empty allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// `APEX_MAX_CONNECTIONS_PER_IP`: concurrent WebSockets per client IP.
+    /// Zero disables the cap.
+    pub max_connections_per_ip: usize,
+    /// `APEX_CONNECTIONS_PER_MINUTE`: WebSocket attempts per client IP per
+    /// minute. Zero disables the limit.
+    pub connections_per_minute: usize,
+    /// `APEX_MAX_SPECTATORS`: concurrent read-only spectator connections,
+    /// on top of players. Zero disables the cap.
+    pub max_spectators: usize,
+    /// `APEX_ASSETS_DIR`: frontend files to serve from disk, for anything not
+    /// embedded in the binary. Defaults to `assets` in the working directory
+    /// unless built with `embed-assets`; set it empty to turn it off.
+    pub assets_dir: Option<PathBuf>,
+    /// `APEX_GALLERY_DIR`: where uploaded balloon images and their index live.
+    pub gallery_dir: PathBuf,
+    /// `APEX_MAX_IMAGE_BYTES`: largest compressed image payload accepted.
+    pub max_image_bytes: usize,
+    /// `APEX_MAX_IMAGES`: images kept in the gallery; the oldest are deleted
+    /// to make room for new ones.
+    pub max_images: usize,
+    /// `APEX_ALERT_RULES`: path to a JSON array of telemetry alert rules,
+    /// replacing the built-in ones.
+    pub alert_rules: Vec<AlertRule>,
+    /// `APEX_MESH_MIN_INTERVAL_SECS`: least time between game chat messages
+    /// relayed over the Meshtastic radio, to stay within the duty cycle.
+    pub mesh_min_interval: Duration,
+    /// `APEX_TEAMS` as `name:rrggbb,...`, e.g. `red:ff4040,blue:4080ff`:
+    /// teams players are balanced into or pick with `/ws?team=name`. No
+    /// teams unless set.
+    pub teams: Vec<Team>,
+    /// `APEX_WAYPOINTS_PER_PLAYER`: markers each player may have placed at
+    /// once. Zero turns waypoints off.
+    pub waypoints_per_player: usize,
+    /// `APEX_MAX_WAYPOINTS`: markers kept across all players, the oldest
+    /// making way for new ones.
+    pub max_waypoints: usize,
+    /// `APEX_WAYPOINT_TTL_SECS`: how long a marker stays up.
+    pub waypoint_ttl: Duration,
+    /// `APEX_CLOCK_MODE`: `real_time` follows the server's UTC time of day,
+    /// `mission` starts at `clock_start` when the balloon launches.
+    pub clock_mode: ClockMode,
+    /// `APEX_CLOCK_SPEED`: game seconds per real second.
+    pub clock_speed: f64,
+    /// `APEX_CLOCK_START` as `HH:MM`: time of day at launch in mission mode.
+    pub clock_start: TimeOfDay,
+    /// `APEX_ADMIN_TOKEN`: bearer token for the `/api/admin` endpoints,
+    /// which are disabled without one.
+    pub admin_token: Option<String>,
+    /// `APEX_STATION_TOKEN`: bearer token the ground station posts telemetry
+    /// and balloon images with. Both are refused without one.
+    pub station_token: Option<String>,
+    /// `APEX_BRIDGE_TOKEN`: bearer token the mesh radio bridge relays chat
+    /// with. The `/api/mesh` endpoints are disabled without one.
+    pub bridge_token: Option<String>,
+    /// `APEX_CONSOLE`: read admin commands from stdin when it is a terminal.
+    /// Off by default.
+    pub console: bool,
+    /// `APEX_CONSOLE_HISTORY`: file keeping the console's command history
+    /// across restarts. Empty keeps it in memory only.
+    pub console_history: Option<PathBuf>,
+    /// `APEX_LOG_FORMAT`: `text` or `json`.
+    pub log_format: LogFormat,
+    /// `APEX_MOVE_LOG_SAMPLE`: log one in this many moves per connection.
+    pub move_log_sample: u64,
+    /// `APEX_DATABASE_URL`: SQLite database for leaderboards.
+    pub database_url: String,
+    /// `APEX_HUNT_ROUND_SECS`: how long a landing-zone hunt round lasts.
+    pub hunt_round: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: SocketAddr::from(([127, 0, 0, 1], 3000)),
+            seed: 31415988,
+            geo_anchor: None,
+            geo_scale: 0.05,
+            geo_vertical_scale: 0.005,
+            heartbeat_interval: Duration::from_secs(5),
+            heartbeat_timeout: Duration::from_secs(15),
+            idle_timeout: Duration::from_secs(300),
+            max_frame_bytes: 16 * 1024,
+            outbound_queue: 16,
+            slow_client_timeout: Duration::from_secs(5),
+            tls_cert: None,
+            tls_key: None,
+            tls_reload_interval: Duration::from_secs(10),
+            http_redirect_bind: None,
+            allowed_origins: Vec::new(),
+            max_connections_per_ip: 16,
+            connections_per_minute: 60,
+            max_spectators: 8,
+            assets_dir: (!cfg!(feature = "embed-assets")).then(|| PathBuf::from("assets")),
+            gallery_dir: PathBuf::from("gallery"),
+            max_image_bytes: 256 * 1024,
+            max_images: 1000,
+            alert_rules: default_rules(),
+            mesh_min_interval: Duration::from_secs(30),
+            teams: Vec::new(),
+            waypoints_per_player: 3,
+            max_waypoints: 60,
+            waypoint_ttl: Duration::from_secs(900),
+            clock_mode: ClockMode::RealTime,
+            clock_speed: 1.0,
+            clock_start: TimeOfDay::from_secs(6.0 * 60.0 * 60.0),
+            admin_token: None,
+            station_token: None,
+            bridge_token: None,
+            console: false,
+            console_history: Some(PathBuf::from(".apex_history")),
+            log_format: LogFormat::Text,
+            move_log_sample: 100,
+            database_url: "sqlite://apex.db".to_string(),
+            hunt_round: Duration::from_secs(600),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            bind: env_or("APEX_BIND", defaults.bind),
+            seed: env_or("APEX_SEED", defaults.seed),
+            geo_anchor: env_parse("APEX_GEO_ANCHOR").or(defaults.geo_anchor),
+            geo_scale: env_or("APEX_GEO_SCALE", defaults.geo_scale),
+            geo_vertical_scale: env_or("APEX_GEO_VERTICAL_SCALE", defaults.geo_vertical_scale),
+            heartbeat_interval: env_parse("APEX_HEARTBEAT_INTERVAL_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.heartbeat_interval),
+            heartbeat_timeout: env_parse("APEX_HEARTBEAT_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.heartbeat_timeout),
+            idle_timeout: env_parse("APEX_IDLE_TIMEOUT_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.idle_timeout),
+            max_frame_bytes: env_or("APEX_MAX_FRAME_BYTES", defaults.max_frame_bytes),
+            outbound_queue: env_or("APEX_OUTBOUND_QUEUE", defaults.outbound_queue),
+            slow_client_timeout: env_parse("APEX_SLOW_CLIENT_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.slow_client_timeout),
+            tls_cert: env_parse("APEX_TLS_CERT").or(defaults.tls_cert),
+            tls_key: env_parse("APEX_TLS_KEY").or(defaults.tls_key),
+            tls_reload_interval: env_parse("APEX_TLS_RELOAD_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.tls_reload_interval),
+            http_redirect_bind: env_parse("APEX_HTTP_REDIRECT_BIND").or(defaults.http_redirect_bind),
+            allowed_origins: env_list("APEX_ALLOWED_ORIGINS").unwrap_or(defaults.allowed_origins),
+            max_connections_per_ip: env_or("APEX_MAX_CONNECTIONS_PER_IP", defaults.max_connections_per_ip),
+            connections_per_minute: env_or("APEX_CONNECTIONS_PER_MINUTE", defaults.connections_per_minute),
+            max_spectators: env_or("APEX_MAX_SPECTATORS", defaults.max_spectators),
+            assets_dir: env_parse::<PathBuf>("APEX_ASSETS_DIR")
+                .map(|dir| (!dir.as_os_str().is_empty()).then_some(dir))
+                .unwrap_or(defaults.assets_dir),
+            gallery_dir: env_or("APEX_GALLERY_DIR", defaults.gallery_dir),
+            max_image_bytes: env_or("APEX_MAX_IMAGE_BYTES", defaults.max_image_bytes),
+            max_images: env_or("APEX_MAX_IMAGES", defaults.max_images),
+            alert_rules: env_json_file("APEX_ALERT_RULES").unwrap_or(defaults.alert_rules),
+            mesh_min_interval: env_parse("APEX_MESH_MIN_INTERVAL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.mesh_min_interval),
+            teams: env_parse_with("APEX_TEAMS", parse_teams).unwrap_or(defaults.teams),
+            waypoints_per_player: env_or("APEX_WAYPOINTS_PER_PLAYER", defaults.waypoints_per_player),
+            max_waypoints: env_or("APEX_MAX_WAYPOINTS", defaults.max_waypoints),
+            waypoint_ttl: env_parse("APEX_WAYPOINT_TTL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.waypoint_ttl),
+            clock_mode: env_or("APEX_CLOCK_MODE", defaults.clock_mode),
+            clock_speed: env_parse("APEX_CLOCK_SPEED")
+                .filter(|speed: &f64| speed.is_finite() && *speed >= 0.0)
+                .unwrap_or(defaults.clock_speed),
+            clock_start: env_or("APEX_CLOCK_START", defaults.clock_start),
+            admin_token: env_parse("APEX_ADMIN_TOKEN").filter(|token: &String| !token.is_empty()),
+            station_token: env_parse("APEX_STATION_TOKEN").filter(|token: &String| !token.is_empty()),
+            bridge_token: env_parse("APEX_BRIDGE_TOKEN").filter(|token: &String| !token.is_empty()),
+            console: env_or("APEX_CONSOLE", defaults.console),
+            console_history: env_parse::<PathBuf>("APEX_CONSOLE_HISTORY")
+                .map(|path| (!path.as_os_str().is_empty()).then_some(path))
+                .unwrap_or(defaults.console_history),
+            log_format: env_or("APEX_LOG_FORMAT", defaults.log_format),
+            move_log_sample: env_or("APEX_MOVE_LOG_SAMPLE", defaults.move_log_sample),
+            database_url: env_or("APEX_DATABASE_URL", defaults.database_url),
+            hunt_round: env_parse("APEX_HUNT_ROUND_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.hunt_round),
+        }
+    }
+}
+
+fn env_parse<T: FromStr>(key: &str) -> Option<T> {
+    env_parse_with(key, str::parse)
+}
+
+fn env_parse_with<T, E>(key: &str, parse: impl FnOnce(&str) -> Result<T, E>) -> Option<T> {
+    let value = std::env::var(key).ok()?;
+    match parse(&value) {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            // Config is read before logging is set up, so this goes to stdout directly.
+            println!("Ignoring invalid {key}={value:?}, using default.");
+            None
+        }
+    }
+}
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    env_parse(key).unwrap_or(default)
+}
+
+/// Reads the JSON file named by `key`.
+fn env_json_file<T: serde::de::DeserializeOwned>(key: &str) -> Option<T> {
+    let path = std::env::var(key).ok()?;
+    let parsed = std::fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice(&bytes).map_err(|e| e.to_string()));
+    match parsed {
+        Ok(parsed) => Some(parsed),
+        Err(e) => {
+            println!("Ignoring {key}={path:?} ({e}), using default.");
+            None
+        }
+    }
+}
+
+fn env_list(key: &str) -> Option<Vec<String>> {
+    let value = std::env::var(key).ok()?;
+    Some(
+        value
+            .split(',')
+            .map(|v| v.trim().trim_end_matches('/').to_string())
+            .filter(|v| !v.is_empty())
+            .collect(),
+    )
+}
@@ -0,0 +1,271 @@
+//! Admin commands typed into the server's terminal during events. Lines are
+//! read with rustyline (editing, history) on their own thread and run
+//! against the game through the same `GameHandle` calls as the HTTP API.
+
+use std::{fmt::Write, io::IsTerminal, path::PathBuf};
+
+use rustyline::{DefaultEditor, error::ReadlineError};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::{telemetry::Telemetry, websockets::AppState};
+
+const PROMPT: &str = "apex> ";
+const HELP: &str = "\
+list                        players online
+kick <player> [reason]      remove a player (by name or player id)
+say <message>               post in chat as the server
+seed                        the world seed
+telemetry <json|key=value>  apply a telemetry update, e.g. telemetry altitude=1200 pressure=870
+stats                       world, connection and team figures
+save                        write online players' sessions to the leaderboard
+help                        this list
+Ctrl-D closes the console, Ctrl-C stops the server.";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    List,
+    Kick { target: String, reason: Option<String> },
+    Say(String),
+    Seed,
+    Telemetry(Telemetry),
+    Stats,
+    Save,
+    Help,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Self, String> {
+        let (name, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        let rest = rest.trim();
+        match name {
+            "list" => Ok(Command::List),
+            "kick" => {
+                let (target, reason) = rest.split_once(' ').unwrap_or((rest, ""));
+                if target.is_empty() {
+                    return Err("usage: kick <player> [reason]".to_string());
+                }
+                let reason = reason.trim();
+                Ok(Command::Kick { target: target.to_string(), reason: (!reason.is_empty()).then(|| reason.to_string()) })
+            }
+            "say" if rest.is_empty() => Err("usage: say <message>".to_string()),
+            "say" => Ok(Command::Say(rest.to_string())),
+            "seed" => Ok(Command::Seed),
+            "telemetry" => parse_telemetry(rest).map(Command::Telemetry),
+            "stats" => Ok(Command::Stats),
+            "save" => Ok(Command::Save),
+            "help" | "?" => Ok(Command::Help),
+            _ => Err(format!("unknown command {name:?}, try help")),
+        }
+    }
+}
+
+/// The API's JSON body, or `key=value` pairs for the same fields.
+fn parse_telemetry(text: &str) -> Result<Telemetry, String> {
+    let value = if text.starts_with('{') {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        let mut fields = serde_json::Map::new();
+        for pair in text.split_whitespace() {
+            let (key, value) = pair.split_once('=').ok_or_else(|| format!("expected key=value, got {pair:?}"))?;
+            let value: f64 = value.parse().map_err(|_| format!("{key}: not a number: {value:?}"))?;
+            fields.insert(key.to_string(), value.into());
+        }
+        if fields.is_empty() {
+            return Err("usage: telemetry <json> or telemetry key=value ...".to_string());
+        }
+        serde_json::Value::Object(fields)
+    };
+    let telemetry: Telemetry = serde_json::from_value(value).map_err(|e| e.to_string())?;
+    if telemetry == Telemetry::default() {
+        return Err("no telemetry fields given (latitude, longitude, altitude, signal, pressure, temperature, outside_temperature)".to_string());
+    }
+    Ok(telemetry)
+}
+
+/// Starts the console if stdin is a terminal. Lines go to the async side one
+/// at a time, and their output is printed before the next prompt. Ctrl-C
+/// sends on `stop` so the server can shut down cleanly.
+pub fn spawn(state: AppState, stop: oneshot::Sender<()>) {
+    if !state.config.console || !std::io::stdin().is_terminal() {
+        return;
+    }
+    let (tx, mut rx) = mpsc::channel::<(String, oneshot::Sender<String>)>(1);
+    let history = state.config.console_history.clone();
+    std::thread::spawn(move || read_lines(tx, history, stop));
+    tokio::spawn(async move {
+        while let Some((line, reply)) = rx.recv().await {
+            let output = match Command::parse(&line) {
+                Ok(command) => run(&state, command).await,
+                Err(e) => e,
+            };
+            let _ = reply.send(output);
+        }
+    });
+    info!("admin console ready, type help for commands");
+}
+
+fn read_lines(tx: mpsc::Sender<(String, oneshot::Sender<String>)>, history: Option<PathBuf>, stop: oneshot::Sender<()>) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            warn!("admin console unavailable: {e}");
+            return;
+        }
+    };
+    if let Some(path) = &history {
+        // Missing on first run.
+        let _ = editor.load_history(path);
+    }
+    loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // The terminal is in raw mode while reading, so Ctrl-C lands
+            // here instead of stopping the server as it otherwise would.
+            Err(ReadlineError::Interrupted) => {
+                let _ = stop.send(());
+                return;
+            }
+            Err(ReadlineError::Eof) => {
+                info!("admin console closed");
+                return;
+            }
+            Err(e) => {
+                warn!("admin console stopped: {e}");
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+        if let Some(path) = &history
+            && let Err(e) = editor.save_history(path)
+        {
+            warn!(path = %path.display(), "could not save console history: {e}");
+        }
+        let (reply, output) = oneshot::channel();
+        if tx.blocking_send((line, reply)).is_err() {
+            return;
+        }
+        if let Ok(output) = output.blocking_recv()
+            && !output.is_empty()
+        {
+            println!("{output}");
+        }
+    }
+}
+
+async fn run(state: &AppState, command: Command) -> String {
+    const STOPPED: &str = "game task has stopped";
+    match command {
+        Command::List => {
+            let Some(players) = state.game.players().await else {
+                return STOPPED.to_string();
+            };
+            if players.is_empty() {
+                return "nobody online".to_string();
+            }
+            let mut out = String::new();
+            for p in &players {
+                let _ = writeln!(
+                    out,
+                    "{:<24} {:<16} {:<22} {:<8} at {:>7.1},{:>7.1}  {:>5}s online  {:>7.1} walked  {} chats",
+                    p.name,
+                    p.id.as_deref().unwrap_or("-"),
+                    p.addr,
+                    p.team.as_deref().unwrap_or("-"),
+                    p.x,
+                    p.z,
+                    p.online_secs,
+                    p.distance,
+                    p.chats
+                );
+            }
+            let _ = write!(out, "{} online", players.len());
+            out
+        }
+        Command::Kick { target, reason } => match state.game.kick(target.clone(), reason).await {
+            Some(addr) => format!("kicked {target} ({addr})"),
+            None => format!("no player {target:?} online"),
+        },
+        Command::Say(message) => {
+            state.game.announce(message).await;
+            String::new()
+        }
+        Command::Seed => match state.game.stats().await {
+            Some(stats) => format!("{} (set APEX_SEED and restart to change it)", stats.seed),
+            None => STOPPED.to_string(),
+        },
+        Command::Telemetry(telemetry) => {
+            state.game.apply_telemetry(telemetry).await;
+            "applied".to_string()
+        }
+        Command::Stats => {
+            let Some(stats) = state.game.stats().await else {
+                return STOPPED.to_string();
+            };
+            let metrics = state.metrics.snapshot();
+            let teams = stats.teams.iter().map(|t| format!("\n{}: {} points, {} online", t.name, t.score, t.players));
+            let teams: String = teams.collect();
+            format!(
+                "players {}, avg ping {:.1} ms, phase {}, hunt {}\n\
+                 time {} at {}x{}, fog {:.2}, clouds {:.2}, wind {:.1} m/s\n\
+                 connections {}, rejected {}, snapshots sent {} (dropped {}), slow disconnects {}, oversized frames {}{}",
+                stats.players,
+                stats.avg_ping,
+                stats.phase.as_deref().unwrap_or("none"),
+                if stats.hunting { "running" } else { "idle" },
+                stats.clock.time,
+                stats.clock.speed,
+                if stats.clock.frozen { " (frozen)" } else { "" },
+                stats.weather.fog,
+                stats.weather.clouds,
+                stats.weather.wind,
+                metrics.connections,
+                metrics.rejected_connections,
+                metrics.snapshots_sent,
+                metrics.snapshots_dropped,
+                metrics.slow_client_disconnects,
+                metrics.oversized_frames,
+                teams,
+            )
+        }
+        Command::Save => match state.game.save().await {
+            Some(sessions) => format!("saving {sessions} sessions to the leaderboard"),
+            None => STOPPED.to_string(),
+        },
+        Command::Help => HELP.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(Command::parse("list"), Ok(Command::List));
+        assert_eq!(
+            Command::parse("kick 127.0.0.1:5000  spamming chat "),
+            Ok(Command::Kick { target: "127.0.0.1:5000".into(), reason: Some("spamming chat".into()) })
+        );
+        assert_eq!(Command::parse("kick bob"), Ok(Command::Kick { target: "bob".into(), reason: None }));
+        assert!(Command::parse("kick").is_err());
+        assert_eq!(Command::parse("say  hello all"), Ok(Command::Say("hello all".into())));
+        assert!(Command::parse("say").is_err());
+        assert!(Command::parse("launch").is_err());
+    }
+
+    #[test]
+    fn telemetry_takes_json_or_pairs() {
+        let pairs = parse_telemetry("altitude=1200 pressure=870.5").unwrap();
+        assert_eq!(pairs, Telemetry { altitude: Some(1200.0), pressure: Some(870.5), ..Telemetry::default() });
+        let json = parse_telemetry(r#"{"altitude": 1200, "pressure": 870.5}"#).unwrap();
+        assert_eq!(json, pairs);
+        assert!(parse_telemetry("altitude").is_err());
+        assert!(parse_telemetry("altitude=high").is_err());
+        assert!(parse_telemetry("").is_err());
+        assert!(parse_telemetry("altitud=5").is_err());
+    }
+}
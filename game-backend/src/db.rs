@@ -0,0 +1,433 @@
+//! SQLite persistence for results that should outlive the process.
+
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    FromRow, SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+
+use crate::{alerts::AlertEvent, flight::MissionEvent, hunt::HuntResult, state::PlayerSession};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hunt_results (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    round INTEGER NOT NULL,
+    player TEXT NOT NULL,
+    place INTEGER,
+    distance REAL NOT NULL,
+    score INTEGER NOT NULL,
+    finished_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS hunt_results_player ON hunt_results (player);
+CREATE TABLE IF NOT EXISTS player_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    player TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    ended_at INTEGER NOT NULL,
+    distance REAL NOT NULL,
+    chats INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS player_sessions_ended ON player_sessions (ended_at);
+CREATE TABLE IF NOT EXISTS alerts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule TEXT NOT NULL,
+    firing INTEGER NOT NULL,
+    value REAL,
+    message TEXT NOT NULL,
+    at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS mission_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    at INTEGER NOT NULL,
+    altitude REAL NOT NULL
+);
+";
+
+/// How many places rank updates track.
+const RANKED_PLAYERS: i64 = 1000;
+
+/// Cheaply clonable handle to the database.
+#[derive(Clone)]
+pub struct Db {
+    pool: SqlitePool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct HuntStanding {
+    pub player: String,
+    pub rounds: i64,
+    pub wins: i64,
+    pub best_place: Option<i64>,
+    pub total_score: i64,
+}
+
+/// One row of `GET /api/leaderboard`.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct PlayerStats {
+    pub player: String,
+    pub distance: f64,
+    pub time_online_secs: i64,
+    pub chats: i64,
+    pub hunt_rounds: i64,
+    pub hunt_wins: i64,
+    pub hunt_score: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortBy {
+    #[default]
+    Score,
+    Wins,
+    Distance,
+    Time,
+    Chats,
+}
+
+impl SortBy {
+    /// Column to order by; also the ranking used for rank updates when `Score`.
+    fn order_by(self) -> &'static str {
+        match self {
+            SortBy::Score => "hunt_score DESC, hunt_wins DESC, distance DESC",
+            SortBy::Wins => "hunt_wins DESC, hunt_score DESC",
+            SortBy::Distance => "distance DESC",
+            SortBy::Time => "time_online_secs DESC",
+            SortBy::Chats => "chats DESC",
+        }
+    }
+}
+
+/// How far back the leaderboard looks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Window {
+    Day,
+    Week,
+    Month,
+    #[default]
+    All,
+}
+
+impl Window {
+    fn since_ms(self, now_ms: i64) -> i64 {
+        let days = match self {
+            Window::Day => 1,
+            Window::Week => 7,
+            Window::Month => 30,
+            Window::All => return 0,
+        };
+        now_ms - Duration::from_secs(days * 24 * 60 * 60).as_millis() as i64
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "24h" | "day" => Ok(Window::Day),
+            "7d" | "week" => Ok(Window::Week),
+            "30d" | "month" => Ok(Window::Month),
+            "all" => Ok(Window::All),
+            _ => Err(format!("unknown window {s:?}, expected 24h, 7d, 30d or all")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Window {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The id stored for a player who connected with `/ws?player=<token>`, or
+/// `None` if the token isn't one the frontend would have made.
+///
+/// The token is a secret kept by the browser, so only a hash of it is ever
+/// stored or shown on the leaderboards.
+pub fn player_id(token: &str) -> Option<String> {
+    let valid = (16..=128).contains(&token.len())
+        && token.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return None;
+    }
+    let hash = Sha256::digest(token.as_bytes());
+    Some(hash[..8].iter().map(|b| format!("{b:02x}")).collect())
+}
+
+pub fn unix_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64
+}
+
+impl Db {
+    /// Opens (creating if needed) the database at `url` and sets up the schema.
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options: SqliteConnectOptions = url.parse()?;
+        let options = options.create_if_missing(true);
+        // Every connection to an in-memory database is a database of its own,
+        // so keep exactly one alive for good.
+        let pool = if url.contains(":memory:") {
+            SqlitePoolOptions::new()
+                .max_connections(1)
+                .min_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect_with(options)
+                .await?
+        } else {
+            SqlitePoolOptions::new().connect_with(options).await?
+        };
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    /// A throwaway database, for tests.
+    pub async fn in_memory() -> Self {
+        Self::connect("sqlite::memory:").await.expect("in-memory sqlite")
+    }
+
+    /// Stores the results of players with an id; the rest aren't tracked.
+    pub async fn record_hunt(&self, results: &[HuntResult]) -> Result<(), sqlx::Error> {
+        let finished_at = unix_ms();
+        let mut tx = self.pool.begin().await?;
+        for result in results {
+            let Some(player) = &result.player_id else { continue };
+            sqlx::query(
+                "INSERT INTO hunt_results (round, player, place, distance, score, finished_at)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+            )
+            .bind(result.round as i64)
+            .bind(player)
+            .bind(result.place.map(i64::from))
+            .bind(result.distance)
+            .bind(result.score as i64)
+            .bind(finished_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    pub async fn record_sessions(&self, sessions: &[PlayerSession]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for session in sessions {
+            sqlx::query(
+                "INSERT INTO player_sessions (player, started_at, ended_at, distance, chats)
+                 VALUES (?, ?, ?, ?, ?)",
+            )
+            .bind(&session.player)
+            .bind(session.started_at_ms)
+            .bind(session.ended_at_ms)
+            .bind(session.distance)
+            .bind(session.chats as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Per-player totals over `window`, sessions counted by when they ended.
+    pub async fn leaderboard(&self, sort: SortBy, window: Window, limit: i64) -> Result<Vec<PlayerStats>, sqlx::Error> {
+        let sql = format!(
+            "WITH s AS (
+                 SELECT player, SUM(distance) AS distance, SUM(ended_at - started_at) / 1000 AS time_online_secs,
+                        SUM(chats) AS chats
+                 FROM player_sessions WHERE ended_at >= ?1 GROUP BY player
+             ),
+             h AS (
+                 SELECT player, COUNT(*) AS hunt_rounds, SUM(place = 1) AS hunt_wins, SUM(score) AS hunt_score
+                 FROM hunt_results WHERE finished_at >= ?1 GROUP BY player
+             ),
+             p AS (SELECT player FROM s UNION SELECT player FROM h)
+             SELECT p.player,
+                    COALESCE(s.distance, 0.0) AS distance,
+                    COALESCE(s.time_online_secs, 0) AS time_online_secs,
+                    COALESCE(s.chats, 0) AS chats,
+                    COALESCE(h.hunt_rounds, 0) AS hunt_rounds,
+                    COALESCE(h.hunt_wins, 0) AS hunt_wins,
+                    COALESCE(h.hunt_score, 0) AS hunt_score
+             FROM p LEFT JOIN s ON s.player = p.player LEFT JOIN h ON h.player = p.player
+             ORDER BY {}, p.player
+             LIMIT ?2",
+            sort.order_by()
+        );
+        sqlx::query_as(&sql)
+            .bind(window.since_ms(unix_ms()))
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Every player in all-time score order, the ranking pushed to clients.
+    pub async fn ranks(&self) -> Result<Vec<String>, sqlx::Error> {
+        Ok(self
+            .leaderboard(SortBy::Score, Window::All, RANKED_PLAYERS)
+            .await?
+            .into_iter()
+            .map(|s| s.player)
+            .collect())
+    }
+
+    pub async fn record_mission_events(&self, events: &[MissionEvent]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            sqlx::query("INSERT INTO mission_events (kind, at, altitude) VALUES (?, ?, ?)")
+                .bind(event.kind)
+                .bind(event.at_ms)
+                .bind(event.altitude)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Up to `limit` mission events recorded after `since_ms`, oldest first.
+    pub async fn mission_events(&self, since_ms: i64, limit: i64) -> Result<Vec<MissionEvent>, sqlx::Error> {
+        sqlx::query_as("SELECT kind, at AS at_ms, altitude FROM mission_events WHERE at > ? ORDER BY at, id LIMIT ?")
+            .bind(since_ms)
+            .bind(limit)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn record_alerts(&self, events: &[AlertEvent]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            sqlx::query("INSERT INTO alerts (rule, firing, value, message, at) VALUES (?, ?, ?, ?, ?)")
+                .bind(&event.rule)
+                .bind(event.firing)
+                .bind(event.value)
+                .bind(&event.message)
+                .bind(event.at_ms)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Alerts raised and cleared, newest first.
+    pub async fn alert_history(&self, limit: i64) -> Result<Vec<AlertEvent>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT rule, firing, value, message, at AS at_ms FROM alerts ORDER BY at DESC, id DESC LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Players by total hunt score, best first.
+    pub async fn hunt_leaderboard(&self, limit: i64) -> Result<Vec<HuntStanding>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT player,
+                    COUNT(*) AS rounds,
+                    SUM(place = 1) AS wins,
+                    MIN(place) AS best_place,
+                    SUM(score) AS total_score
+             FROM hunt_results
+             GROUP BY player
+             ORDER BY total_score DESC, wins DESC, player
+             LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flight::MissionEventKind;
+
+    fn result(player: &str, place: Option<u32>, score: u32) -> HuntResult {
+        let player_id = Some(player.to_string());
+        HuntResult { round: 1, player: player.to_string(), player_id, team: None, place, distance: 0.0, score }
+    }
+
+    #[tokio::test]
+    async fn hunt_leaderboard_totals_rounds() {
+        let db = Db::in_memory().await;
+        db.record_hunt(&[result("alice", Some(1), 100), result("bob", None, 20)]).await.unwrap();
+        db.record_hunt(&[result("bob", Some(1), 100), result("alice", Some(2), 90)]).await.unwrap();
+
+        let standings = db.hunt_leaderboard(10).await.unwrap();
+        assert_eq!(
+            standings,
+            [
+                HuntStanding { player: "alice".into(), rounds: 2, wins: 1, best_place: Some(1), total_score: 190 },
+                HuntStanding { player: "bob".into(), rounds: 2, wins: 1, best_place: Some(1), total_score: 120 },
+            ]
+        );
+        assert_eq!(db.hunt_leaderboard(1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn players_without_an_id_are_not_recorded() {
+        let db = Db::in_memory().await;
+        let guest = HuntResult { player_id: None, ..result("127.0.0.1:5000", Some(1), 100) };
+        db.record_hunt(&[guest]).await.unwrap();
+        assert!(db.hunt_leaderboard(10).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn player_ids_hash_the_token() {
+        let id = player_id("0123456789abcdef").unwrap();
+        assert_eq!(id.len(), 16);
+        assert!(!id.contains("0123456789abcdef"));
+        assert_eq!(player_id("0123456789abcdef"), Some(id));
+        assert_eq!(player_id("short"), None);
+        assert_eq!(player_id("not a valid token!"), None);
+    }
+
+    #[tokio::test]
+    async fn mission_events_round_trip() {
+        let db = Db::in_memory().await;
+        let events = [
+            MissionEvent { kind: MissionEventKind::MaxAltitude, at_ms: 10, altitude: 30_000.0 },
+            MissionEvent { kind: MissionEventKind::Burst, at_ms: 20, altitude: 29_900.0 },
+        ];
+        db.record_mission_events(&events).await.unwrap();
+        assert_eq!(db.mission_events(0, 10).await.unwrap(), events);
+        assert_eq!(db.mission_events(10, 10).await.unwrap(), events[1..]);
+        assert_eq!(db.mission_events(0, 1).await.unwrap(), events[..1]);
+    }
+
+    fn session(player: &str, ended_ago_ms: i64, distance: f64, chats: u32) -> PlayerSession {
+        let ended_at_ms = unix_ms() - ended_ago_ms;
+        PlayerSession { player: player.to_string(), started_at_ms: ended_at_ms - 60_000, ended_at_ms, distance, chats }
+    }
+
+    #[tokio::test]
+    async fn leaderboard_sorts_and_windows() {
+        let db = Db::in_memory().await;
+        let two_days = 2 * 24 * 60 * 60 * 1000;
+        db.record_sessions(&[
+            session("alice", 0, 100.0, 1),
+            session("bob", 0, 50.0, 5),
+            session("bob", two_days, 500.0, 0),
+        ])
+        .await
+        .unwrap();
+        db.record_hunt(&[result("carol", Some(1), 100)]).await.unwrap();
+
+        let all = db.leaderboard(SortBy::Distance, Window::All, 10).await.unwrap();
+        let order: Vec<_> = all.iter().map(|s| (s.player.as_str(), s.distance)).collect();
+        assert_eq!(order, [("bob", 550.0), ("alice", 100.0), ("carol", 0.0)]);
+        assert_eq!(all[0].time_online_secs, 120);
+
+        let day = db.leaderboard(SortBy::Distance, Window::Day, 10).await.unwrap();
+        assert_eq!(day[0].player, "alice");
+
+        let chats = db.leaderboard(SortBy::Chats, Window::All, 1).await.unwrap();
+        assert_eq!((chats[0].player.as_str(), chats[0].chats), ("bob", 5));
+
+        assert_eq!(db.ranks().await.unwrap()[0], "carol");
+        assert_eq!("7d".parse(), Ok(Window::Week));
+    }
+}
@@ -0,0 +1,208 @@
+//! Flight-phase detection from the altitude history: on the pad, climbing,
+//! floating, bursting, coming down and landed, plus the mission events
+//! marking the transitions.
+
+use std::{collections::VecDeque, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// Climb above the pad (m) and rate (m/s) that count as a launch rather
+/// than GPS jitter.
+const LAUNCH_CLIMB: f64 = 30.0;
+const LAUNCH_RATE: f64 = 1.0;
+/// Drop below the highest point (m) and sink rate (m/s) that count as a burst.
+const BURST_DROP: f64 = 50.0;
+const BURST_RATE: f64 = 3.0;
+/// Staying within `STILL_BAND` metres for `STILL_SPAN_MS` means floating
+/// (up high) or landed (on the way down).
+const STILL_BAND: f64 = 15.0;
+const STILL_SPAN_MS: i64 = 30_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlightPhase {
+    #[default]
+    OnPad,
+    Ascent,
+    Float,
+    Burst,
+    Descent,
+    Landed,
+}
+
+impl fmt::Display for FlightPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FlightPhase::OnPad => "on_pad",
+            FlightPhase::Ascent => "ascent",
+            FlightPhase::Float => "float",
+            FlightPhase::Burst => "burst",
+            FlightPhase::Descent => "descent",
+            FlightPhase::Landed => "landed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum MissionEventKind {
+    Launch,
+    MaxAltitude,
+    Burst,
+    Landing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct MissionEvent {
+    pub kind: MissionEventKind,
+    /// Unix time in milliseconds.
+    pub at_ms: i64,
+    /// Metres above sea level.
+    pub altitude: f64,
+}
+
+impl MissionEvent {
+    /// How the event is announced in chat.
+    pub fn message(&self) -> String {
+        match self.kind {
+            MissionEventKind::Launch => format!("Launch detected at {:.0} m", self.altitude),
+            MissionEventKind::MaxAltitude => format!("Maximum altitude {:.0} m", self.altitude),
+            MissionEventKind::Burst => format!("Burst at {:.0} m, the balloon is coming down", self.altitude),
+            MissionEventKind::Landing => format!("Landed at {:.0} m", self.altitude),
+        }
+    }
+}
+
+/// Altitude in metres for a pressure in hPa, by the standard atmosphere.
+pub fn pressure_altitude(hpa: f64) -> f64 {
+    44_330.0 * (1.0 - (hpa / 1013.25).powf(1.0 / 5.255))
+}
+
+#[derive(Default)]
+pub struct FlightTracker {
+    phase: FlightPhase,
+    /// `(unix ms, altitude)`, oldest first, trimmed to what the stillness
+    /// check needs.
+    history: VecDeque<(i64, f64)>,
+    pad_altitude: Option<f64>,
+    /// Highest point so far and when it was reached.
+    max: Option<(i64, f64)>,
+}
+
+impl FlightTracker {
+    pub fn phase(&self) -> FlightPhase {
+        self.phase
+    }
+
+    /// Feeds one altitude fix and returns the events it triggered, oldest first.
+    pub fn update(&mut self, at_ms: i64, altitude: f64) -> Vec<MissionEvent> {
+        let previous = self.history.back().copied();
+        self.history.push_back((at_ms, altitude));
+        while self.history.len() > 2 && self.history[1].0 <= at_ms - STILL_SPAN_MS {
+            self.history.pop_front();
+        }
+        let rate = previous
+            .filter(|(t, _)| at_ms > *t)
+            .map(|(t, a)| (altitude - a) / ((at_ms - t) as f64 / 1000.0))
+            .unwrap_or(0.0);
+        if self.phase != FlightPhase::OnPad && self.max.is_none_or(|(_, max)| altitude > max) {
+            self.max = Some((at_ms, altitude));
+        }
+
+        let event = |kind| MissionEvent { kind, at_ms, altitude };
+        let mut events = Vec::new();
+        match self.phase {
+            FlightPhase::OnPad => {
+                let pad = *self.pad_altitude.get_or_insert(altitude);
+                if altitude - pad >= LAUNCH_CLIMB && rate > LAUNCH_RATE {
+                    self.phase = FlightPhase::Ascent;
+                    self.max = Some((at_ms, altitude));
+                    events.push(event(MissionEventKind::Launch));
+                } else {
+                    self.pad_altitude = Some(pad.min(altitude));
+                }
+            }
+            FlightPhase::Ascent | FlightPhase::Float => {
+                let (max_at, max) = self.max.unwrap_or((at_ms, altitude));
+                if max - altitude >= BURST_DROP && rate < -BURST_RATE {
+                    self.phase = FlightPhase::Burst;
+                    events.push(MissionEvent { kind: MissionEventKind::MaxAltitude, at_ms: max_at, altitude: max });
+                    events.push(event(MissionEventKind::Burst));
+                } else if self.phase == FlightPhase::Ascent && self.still() {
+                    self.phase = FlightPhase::Float;
+                } else if self.phase == FlightPhase::Float && rate > LAUNCH_RATE {
+                    self.phase = FlightPhase::Ascent;
+                }
+            }
+            FlightPhase::Burst => self.phase = FlightPhase::Descent,
+            FlightPhase::Descent => {
+                if self.still() {
+                    self.phase = FlightPhase::Landed;
+                    events.push(event(MissionEventKind::Landing));
+                }
+            }
+            FlightPhase::Landed => {}
+        }
+        events
+    }
+
+    /// Whether every fix of the last `STILL_SPAN_MS` stayed within `STILL_BAND`.
+    fn still(&self) -> bool {
+        let (Some(&(oldest, _)), Some(&(newest, _))) = (self.history.front(), self.history.back()) else {
+            return false;
+        };
+        if newest - oldest < STILL_SPAN_MS {
+            return false;
+        }
+        let (low, high) = self
+            .history
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &(_, a)| (lo.min(a), hi.max(a)));
+        high - low <= STILL_BAND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `(seconds, altitude)` fixes, collecting the events.
+    fn fly(tracker: &mut FlightTracker, fixes: &[(i64, f64)]) -> Vec<(MissionEventKind, f64)> {
+        fixes
+            .iter()
+            .flat_map(|&(secs, alt)| tracker.update(secs * 1000, alt))
+            .map(|e| (e.kind, e.altitude))
+            .collect()
+    }
+
+    #[test]
+    fn follows_a_whole_flight() {
+        let mut tracker = FlightTracker::default();
+        assert!(fly(&mut tracker, &[(0, 100.0), (10, 104.0), (20, 98.0)]).is_empty(), "jitter on the pad");
+        assert_eq!(tracker.phase(), FlightPhase::OnPad);
+
+        assert_eq!(fly(&mut tracker, &[(30, 150.0)]), [(MissionEventKind::Launch, 150.0)]);
+        assert!(fly(&mut tracker, &[(40, 200.0), (1000, 30_000.0), (1010, 30_010.0)]).is_empty());
+        assert_eq!(tracker.phase(), FlightPhase::Ascent);
+
+        assert!(fly(&mut tracker, &[(1030, 30_005.0), (1045, 30_002.0)]).is_empty());
+        assert_eq!(tracker.phase(), FlightPhase::Float);
+
+        assert_eq!(
+            fly(&mut tracker, &[(1050, 29_900.0)]),
+            [(MissionEventKind::MaxAltitude, 30_010.0), (MissionEventKind::Burst, 29_900.0)]
+        );
+        assert!(fly(&mut tracker, &[(1060, 29_800.0), (6000, 300.0), (6010, 290.0)]).is_empty());
+        assert_eq!(tracker.phase(), FlightPhase::Descent);
+
+        assert_eq!(fly(&mut tracker, &[(6040, 288.0)]), [(MissionEventKind::Landing, 288.0)]);
+        assert_eq!(tracker.phase(), FlightPhase::Landed);
+    }
+
+    #[test]
+    fn pressure_maps_to_altitude() {
+        assert!(pressure_altitude(1013.25).abs() < 1e-6);
+        assert!((pressure_altitude(500.0) - 5574.0).abs() < 10.0);
+    }
+}
@@ -0,0 +1,168 @@
+//! Pictures downlinked from the balloon. Uploads are the image-compression
+//! codec's `.bin` payload; they are decoded to PNG on arrival and kept on disk
+//! next to a small JSON index.
+
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{ImageFormat, Rgb};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::telemetry::Telemetry;
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageEntry {
+    pub id: u64,
+    /// Unix time in milliseconds.
+    pub received_at_ms: u64,
+    pub compressed_bytes: usize,
+    /// False when no palette came with the upload and it was decoded in grayscale.
+    pub colour: bool,
+    /// What telemetry had last reported when the image arrived.
+    pub telemetry: Option<Telemetry>,
+}
+
+pub struct Gallery {
+    dir: PathBuf,
+    /// Oldest first.
+    images: Mutex<Vec<ImageEntry>>,
+    /// The oldest images go once there are more.
+    max_images: usize,
+}
+
+impl Gallery {
+    /// Loads the index in `dir` if there is one. The directory itself is only
+    /// created on the first upload.
+    pub fn open(dir: &Path, max_images: usize) -> Self {
+        let images = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!(dir = %dir.display(), "ignoring unreadable gallery index: {e}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        Self { dir: dir.to_path_buf(), images: Mutex::new(images), max_images: max_images.max(1) }
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<ImageEntry> {
+        let mut images = self.images.lock().unwrap().clone();
+        images.reverse();
+        images
+    }
+
+    /// Stores the original payload and its decoded PNG under a new id,
+    /// deleting the oldest images beyond the limit.
+    pub fn add(&self, payload: &[u8], png: &[u8], colour: bool, telemetry: Option<Telemetry>) -> io::Result<ImageEntry> {
+        let mut images = self.images.lock().unwrap();
+        let id = images.last().map_or(1, |last| last.id + 1);
+        let entry = ImageEntry {
+            id,
+            received_at_ms: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64,
+            compressed_bytes: payload.len(),
+            colour,
+            telemetry,
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{}.bin", id)), payload)?;
+        fs::write(self.png_path(id), png)?;
+        images.push(entry.clone());
+        let excess = images.len().saturating_sub(self.max_images);
+        let removed: Vec<ImageEntry> = images.drain(..excess).collect();
+        fs::write(self.dir.join(INDEX_FILE), serde_json::to_vec_pretty(&*images)?)?;
+        for old in removed {
+            // Already out of the index, so a leftover file is only wasted space.
+            let _ = fs::remove_file(self.dir.join(format!("{}.bin", old.id)));
+            let _ = fs::remove_file(self.png_path(old.id));
+        }
+        Ok(entry)
+    }
+
+    /// The decoded picture, if `id` exists.
+    pub fn png(&self, id: u64) -> Option<Vec<u8>> {
+        if !self.images.lock().unwrap().iter().any(|i| i.id == id) {
+            return None;
+        }
+        fs::read(self.png_path(id)).ok()
+    }
+
+    fn png_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{}.png", id))
+    }
+}
+
+/// Parses `rrggbb,rrggbb,...` (an optional leading `#` per colour is fine).
+pub fn parse_palette(s: &str) -> Result<Vec<Rgb<u8>>, String> {
+    s.split(',')
+        .map(|c| {
+            let hex = c.trim().trim_start_matches('#');
+            let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+            value
+                .map(|v| Rgb([(v >> 16) as u8, (v >> 8) as u8, v as u8]))
+                .ok_or_else(|| format!("invalid palette colour {:?}", c))
+        })
+        .collect()
+}
+
+/// Decodes a codec payload to PNG bytes, in grayscale without a palette.
+pub fn decode_png(payload: &[u8], palette: Option<&[Rgb<u8>]>) -> Result<Vec<u8>, String> {
+    let img = image_compression::decode_image(payload, palette).map_err(|e| e.to_string())?;
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_palette() {
+        assert_eq!(parse_palette("ff8000,#000001"), Ok(vec![Rgb([255, 128, 0]), Rgb([0, 0, 1])]));
+        assert!(parse_palette("ff80").is_err());
+        assert!(parse_palette("gggggg").is_err());
+    }
+
+    #[test]
+    fn keeps_index_across_reopen() {
+        let dir = std::env::temp_dir().join(format!("apex-gallery-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let gallery = Gallery::open(&dir, 10);
+        assert!(gallery.list().is_empty());
+        let first = gallery.add(b"one", b"png1", false, None).unwrap();
+        let second = gallery.add(b"two", b"png2", true, None).unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+
+        let reopened = Gallery::open(&dir, 10);
+        assert_eq!(reopened.list(), vec![second, first]);
+        assert_eq!(reopened.png(1).as_deref(), Some(&b"png1"[..]));
+        assert_eq!(reopened.png(3), None);
+    }
+
+    #[test]
+    fn drops_the_oldest_beyond_the_limit() {
+        let dir = std::env::temp_dir().join(format!("apex-gallery-limit-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let gallery = Gallery::open(&dir, 2);
+        for payload in [b"one", b"two", b"six"] {
+            gallery.add(payload, b"png", false, None).unwrap();
+        }
+        let ids: Vec<u64> = gallery.list().iter().map(|i| i.id).collect();
+        assert_eq!(ids, [3, 2]);
+        assert_eq!(gallery.png(1), None);
+        assert!(!dir.join("1.png").exists() && !dir.join("1.bin").exists());
+        // Ids keep counting up, never reusing a dropped one.
+        assert_eq!(gallery.add(b"ten", b"png", false, None).unwrap().id, 4);
+    }
+}
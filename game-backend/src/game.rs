@@ -0,0 +1,395 @@
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{broadcast, mpsc, oneshot},
+    task::JoinSet,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    alerts::AlertEvent,
+    clock::{ClockChange, ClockStatus},
+    db::Db,
+    mesh::MeshText,
+    state::{GameState, GameStats, PlayerInfo},
+    teams::TeamScore,
+    telemetry::Telemetry,
+};
+
+const COMMAND_QUEUE_SIZE: usize = 1024;
+/// Alerts a slow subscriber may fall behind by before missing some.
+const ALERT_BACKLOG: usize = 64;
+const MESH_BACKLOG: usize = 16;
+const KICK_BACKLOG: usize = 16;
+
+/// What the task that owns `GameState` takes, one at a time in arrival order.
+enum Command {
+    Game(GameCommand),
+    /// Handled by `run` itself, since it waits for the database writes.
+    Save { reply: oneshot::Sender<usize> },
+}
+
+/// Everything connections can ask of the game.
+enum GameCommand {
+    AddPlayer { addr: SocketAddr, name: Option<String>, team: Option<String>, id: Option<String> },
+    RemovePlayer { addr: SocketAddr },
+    PlayerLeft { addr: SocketAddr, reason: &'static str },
+    UpdatePlayer { addr: SocketAddr, x: f32, z: f32 },
+    PlaceWaypoint { addr: SocketAddr, x: f32, z: f32, name: String },
+    RemoveWaypoint { addr: SocketAddr, id: u32 },
+    AddPing { ping: f32 },
+    Chat { addr: SocketAddr, message: String },
+    TeamChat { addr: SocketAddr, message: String },
+    Telemetry { telemetry: Telemetry },
+    LastTelemetry { reply: oneshot::Sender<Option<Telemetry>> },
+    ImageAdded { id: u64 },
+    MeshMessage { message: MeshText },
+    Ranks { order: Vec<String> },
+    SetClock { change: ClockChange, reply: oneshot::Sender<ClockStatus> },
+    Clock { reply: oneshot::Sender<ClockStatus> },
+    Kick { target: String, reason: Option<String>, reply: oneshot::Sender<Option<SocketAddr>> },
+    Announce { message: String },
+    Players { reply: oneshot::Sender<Vec<PlayerInfo>> },
+    IsPlayer { addr: SocketAddr, reply: oneshot::Sender<bool> },
+    Stats { reply: oneshot::Sender<GameStats> },
+    Teams { reply: oneshot::Sender<Vec<TeamScore>> },
+    Trees { reply: oneshot::Sender<String> },
+    InitState { who: SocketAddr, reply: oneshot::Sender<String> },
+    State { who: SocketAddr, reply: oneshot::Sender<String> },
+    SpectatorState { follow: Option<String>, reply: oneshot::Sender<String> },
+}
+
+/// Cheaply clonable handle to the game-state task.
+#[derive(Clone)]
+pub struct GameHandle {
+    tx: mpsc::Sender<Command>,
+    alerts: broadcast::Sender<AlertEvent>,
+    mesh: broadcast::Sender<MeshText>,
+    kicks: broadcast::Sender<SocketAddr>,
+}
+
+/// How often time-based rules (round timeouts) are checked.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Moves `state` into its own task and returns a handle to talk to it.
+/// Mission events, alerts, finished hunt rounds and player sessions are
+/// written to `db`, and the resulting standings fed back in. Alerts are also
+/// published to `GameHandle::subscribe_alerts`, chat for the radio to
+/// `GameHandle::subscribe_mesh`. The task stops once every handle has been
+/// dropped.
+pub fn spawn(state: GameState, db: Db) -> GameHandle {
+    let (tx, rx) = mpsc::channel(COMMAND_QUEUE_SIZE);
+    let (alerts, _) = broadcast::channel(ALERT_BACKLOG);
+    let (mesh, _) = broadcast::channel(MESH_BACKLOG);
+    let (kicks, _) = broadcast::channel(KICK_BACKLOG);
+    tokio::spawn(run(state, rx, tx.downgrade(), alerts.clone(), mesh.clone(), db));
+    GameHandle { tx, alerts, mesh, kicks }
+}
+
+async fn run(
+    mut state: GameState,
+    mut rx: mpsc::Receiver<Command>,
+    this: mpsc::WeakSender<Command>,
+    alerts: broadcast::Sender<AlertEvent>,
+    mesh: broadcast::Sender<MeshText>,
+    db: Db,
+) {
+    tokio::spawn(push_ranks(db.clone(), this.clone()));
+    let mut tick = tokio::time::interval(TICK_INTERVAL);
+    // Database writes still in flight, so saving can wait for them.
+    let mut writes = JoinSet::new();
+    loop {
+        let mut saved = None;
+        tokio::select! {
+            command = rx.recv() => match command {
+                Some(Command::Save { reply }) => saved = Some((reply, state.checkpoint_sessions())),
+                Some(Command::Game(command)) => apply(&mut state, command),
+                None => break,
+            },
+            _ = tick.tick() => state.tick(Instant::now()),
+        }
+        while writes.try_join_next().is_some() {}
+        let events = state.take_mission_events();
+        if !events.is_empty() {
+            let db = db.clone();
+            writes.spawn(async move {
+                if let Err(e) = db.record_mission_events(&events).await {
+                    error!("failed to record mission events: {e}");
+                }
+            });
+        }
+        for message in state.take_mesh_outbox() {
+            match mesh.send(message) {
+                Ok(_) => state.mesh_sent(Instant::now()),
+                Err(_) => warn!("no ground station listening, mesh message dropped"),
+            }
+        }
+        let raised = state.take_alert_events();
+        if !raised.is_empty() {
+            for event in &raised {
+                // Nobody listening is fine.
+                let _ = alerts.send(event.clone());
+            }
+            let db = db.clone();
+            writes.spawn(async move {
+                if let Err(e) = db.record_alerts(&raised).await {
+                    error!("failed to record alerts: {e}");
+                }
+            });
+        }
+        let hunts = state.take_finished_hunts();
+        let sessions = state.take_finished_sessions();
+        if !hunts.is_empty() || !sessions.is_empty() {
+            let (db, this) = (db.clone(), this.clone());
+            writes.spawn(async move {
+                for results in hunts {
+                    if let Err(e) = db.record_hunt(&results).await {
+                        error!("failed to record hunt results: {e}");
+                    }
+                }
+                if let Err(e) = db.record_sessions(&sessions).await {
+                    error!("failed to record player sessions: {e}");
+                }
+                // On its own, since it waits on this task's queue.
+                tokio::spawn(push_ranks(db, this));
+            });
+        }
+        if let Some((reply, count)) = saved {
+            while writes.join_next().await.is_some() {}
+            let _ = reply.send(count);
+        }
+    }
+    while writes.join_next().await.is_some() {}
+    info!("game state task stopped");
+}
+
+/// Reads the standings and hands them to the game task, if it still runs.
+async fn push_ranks(db: Db, game: mpsc::WeakSender<Command>) {
+    match db.ranks().await {
+        Ok(order) => {
+            if let Some(tx) = game.upgrade() {
+                let _ = tx.send(Command::Game(GameCommand::Ranks { order })).await;
+            }
+        }
+        Err(e) => error!("failed to read leaderboard ranks: {e}"),
+    }
+}
+
+fn apply(state: &mut GameState, command: GameCommand) {
+    match command {
+        GameCommand::AddPlayer { addr, name, team, id } => state.add_player(addr, name, team, id),
+        GameCommand::RemovePlayer { addr } => state.remove_player(addr),
+        GameCommand::PlayerLeft { addr, reason } => state.player_left(addr, reason),
+        GameCommand::UpdatePlayer { addr, x, z } => state.update_player(addr, x, z),
+        GameCommand::PlaceWaypoint { addr, x, z, name } => state.place_waypoint(addr, x, z, &name),
+        GameCommand::RemoveWaypoint { addr, id } => state.remove_waypoint(addr, id),
+        GameCommand::AddPing { ping } => state.add_ping(ping),
+        GameCommand::Chat { addr, message } => state.add_chat_message(addr, message),
+        GameCommand::TeamChat { addr, message } => state.add_team_chat_message(addr, message),
+        GameCommand::Telemetry { telemetry } => state.apply_telemetry(&telemetry),
+        GameCommand::LastTelemetry { reply } => {
+            let _ = reply.send(state.last_telemetry());
+        }
+        GameCommand::ImageAdded { id } => state.image_added(id),
+        GameCommand::MeshMessage { message } => state.add_mesh_message(message),
+        GameCommand::Ranks { order } => state.set_ranks(order),
+        GameCommand::SetClock { change, reply } => {
+            let _ = reply.send(state.set_clock(&change));
+        }
+        GameCommand::Clock { reply } => {
+            let _ = reply.send(state.clock_status());
+        }
+        GameCommand::Kick { target, reason, reply } => {
+            let _ = reply.send(state.kick(&target, reason.as_deref()));
+        }
+        GameCommand::Announce { message } => state.add_system_message(message),
+        GameCommand::Players { reply } => {
+            let _ = reply.send(state.players());
+        }
+        GameCommand::IsPlayer { addr, reply } => {
+            let _ = reply.send(state.is_player(addr));
+        }
+        GameCommand::Stats { reply } => {
+            let _ = reply.send(state.stats());
+        }
+        GameCommand::Teams { reply } => {
+            let _ = reply.send(state.team_standings());
+        }
+        GameCommand::Trees { reply } => {
+            let _ = reply.send(state.get_trees_string());
+        }
+        GameCommand::InitState { who, reply } => {
+            let _ = reply.send(state.get_init_state_string(who));
+        }
+        GameCommand::State { who, reply } => {
+            let _ = reply.send(state.get_state_string(who));
+        }
+        GameCommand::SpectatorState { follow, reply } => {
+            let _ = reply.send(state.get_spectator_state_string(follow.as_deref()));
+        }
+    }
+}
+
+impl GameHandle {
+    /// Alerts as they are raised and cleared.
+    pub fn subscribe_alerts(&self) -> broadcast::Receiver<AlertEvent> {
+        self.alerts.subscribe()
+    }
+
+    /// Game chat to transmit over the mesh.
+    pub fn subscribe_mesh(&self) -> broadcast::Receiver<MeshText> {
+        self.mesh.subscribe()
+    }
+
+    /// Addresses of kicked players, whose connections should close.
+    pub fn subscribe_kicks(&self) -> broadcast::Receiver<SocketAddr> {
+        self.kicks.subscribe()
+    }
+
+    async fn send(&self, command: GameCommand) {
+        // Only fails once the game task is gone, at which point there is
+        // nothing left to update.
+        let _ = self.tx.send(Command::Game(command)).await;
+    }
+
+    async fn query<T>(&self, make: impl FnOnce(oneshot::Sender<T>) -> GameCommand) -> Option<T> {
+        self.ask(|reply| Command::Game(make(reply))).await
+    }
+
+    async fn ask<T>(&self, make: impl FnOnce(oneshot::Sender<T>) -> Command) -> Option<T> {
+        let (reply, rx) = oneshot::channel();
+        self.tx.send(make(reply)).await.ok()?;
+        rx.await.ok()
+    }
+
+    /// Adds a player on `team`, or the smallest team if that names none.
+    pub async fn add_player(&self, addr: SocketAddr, name: Option<String>, team: Option<String>, id: Option<String>) {
+        self.send(GameCommand::AddPlayer { addr, name, team, id }).await;
+    }
+
+    pub async fn remove_player(&self, addr: SocketAddr) {
+        self.send(GameCommand::RemovePlayer { addr }).await;
+    }
+
+    /// Removes the player and announces `reason` in chat.
+    pub async fn player_left(&self, addr: SocketAddr, reason: &'static str) {
+        self.send(GameCommand::PlayerLeft { addr, reason }).await;
+    }
+
+    pub async fn update_player(&self, addr: SocketAddr, x: f32, z: f32) {
+        self.send(GameCommand::UpdatePlayer { addr, x, z }).await;
+    }
+
+    pub async fn place_waypoint(&self, addr: SocketAddr, x: f32, z: f32, name: String) {
+        self.send(GameCommand::PlaceWaypoint { addr, x, z, name }).await;
+    }
+
+    pub async fn remove_waypoint(&self, addr: SocketAddr, id: u32) {
+        self.send(GameCommand::RemoveWaypoint { addr, id }).await;
+    }
+
+    pub async fn add_ping(&self, ping: f32) {
+        self.send(GameCommand::AddPing { ping }).await;
+    }
+
+    pub async fn add_chat_message(&self, addr: SocketAddr, message: String) {
+        self.send(GameCommand::Chat { addr, message }).await;
+    }
+
+    pub async fn add_team_chat_message(&self, addr: SocketAddr, message: String) {
+        self.send(GameCommand::TeamChat { addr, message }).await;
+    }
+
+    pub async fn apply_telemetry(&self, telemetry: Telemetry) {
+        self.send(GameCommand::Telemetry { telemetry }).await;
+    }
+
+    /// Everything telemetry has reported so far, if anything.
+    pub async fn last_telemetry(&self) -> Option<Telemetry> {
+        self.query(|reply| GameCommand::LastTelemetry { reply }).await.flatten()
+    }
+
+    pub async fn image_added(&self, id: u64) {
+        self.send(GameCommand::ImageAdded { id }).await;
+    }
+
+    /// Shows text heard on the mesh in game chat.
+    pub async fn mesh_message(&self, message: MeshText) {
+        self.send(GameCommand::MeshMessage { message }).await;
+    }
+
+    /// Changes the world clock. Returns `None` if the game task has stopped.
+    pub async fn set_clock(&self, change: ClockChange) -> Option<ClockStatus> {
+        self.query(|reply| GameCommand::SetClock { change, reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn clock(&self) -> Option<ClockStatus> {
+        self.query(|reply| GameCommand::Clock { reply }).await
+    }
+
+    /// Removes the player named (or addressed) `target` and closes their
+    /// connection. Returns their address, or `None` if nobody matched.
+    pub async fn kick(&self, target: String, reason: Option<String>) -> Option<SocketAddr> {
+        let addr = self.query(|reply| GameCommand::Kick { target, reason, reply }).await.flatten()?;
+        // Nobody listening means the connection is already gone.
+        let _ = self.kicks.send(addr);
+        Some(addr)
+    }
+
+    /// Posts `message` in chat as the server.
+    pub async fn announce(&self, message: String) {
+        self.send(GameCommand::Announce { message }).await;
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn players(&self) -> Option<Vec<PlayerInfo>> {
+        self.query(|reply| GameCommand::Players { reply }).await
+    }
+
+    /// Whether `addr` is still in the game. Returns `None` if the game task
+    /// has stopped.
+    pub async fn is_player(&self, addr: SocketAddr) -> Option<bool> {
+        self.query(|reply| GameCommand::IsPlayer { addr, reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn stats(&self) -> Option<GameStats> {
+        self.query(|reply| GameCommand::Stats { reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn teams(&self) -> Option<Vec<TeamScore>> {
+        self.query(|reply| GameCommand::Teams { reply }).await
+    }
+
+    /// Writes online players' sessions so far to the leaderboard, returning
+    /// once everything waiting is in the database. Returns how many, or
+    /// `None` if the game task has stopped.
+    pub async fn save(&self) -> Option<usize> {
+        self.ask(|reply| Command::Save { reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn trees_string(&self) -> Option<String> {
+        self.query(|reply| GameCommand::Trees { reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn init_state_string(&self, who: SocketAddr) -> Option<String> {
+        self.query(|reply| GameCommand::InitState { who, reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn state_string(&self, who: SocketAddr) -> Option<String> {
+        self.query(|reply| GameCommand::State { who, reply }).await
+    }
+
+    /// Returns `None` if the game task has stopped.
+    pub async fn spectator_state_string(&self, follow: Option<String>) -> Option<String> {
+        self.query(|reply| GameCommand::SpectatorState { follow, reply }).await
+    }
+}
@@ -0,0 +1,264 @@
+//! Landing-zone hunt: once the balloon is coming down, its predicted landing
+//! point becomes an objective and players race to it. Arrivals are scored
+//! by order, everyone else by how close they got.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// How close (world units) counts as having reached the objective.
+pub const ARRIVAL_RADIUS: f32 = 5.0;
+/// Points for the first arrival; each later place gets `PLACE_STEP` fewer,
+/// never less than `MIN_ARRIVAL_POINTS`.
+const FIRST_PLACE_POINTS: u32 = 100;
+const PLACE_STEP: u32 = 10;
+const MIN_ARRIVAL_POINTS: u32 = 50;
+/// Players who didn't arrive get up to this much, falling to zero at
+/// `ZERO_POINTS_DISTANCE` from the objective.
+const MAX_APPROACH_POINTS: f32 = 40.0;
+const ZERO_POINTS_DISTANCE: f32 = 400.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Objective {
+    pub round: u64,
+    pub x: f32,
+    pub z: f32,
+    started: Instant,
+}
+
+/// Who is hunting, as of their latest move.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hunter {
+    /// Shown in chat.
+    pub name: String,
+    /// What results are stored under, see `db::player_id`. Players without
+    /// one still score but stay off the leaderboard.
+    pub id: Option<String>,
+    /// Who their points go to, even if they leave before the round ends.
+    pub team: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HuntResult {
+    pub round: u64,
+    /// Display name, for announcing.
+    pub player: String,
+    pub player_id: Option<String>,
+    pub team: Option<String>,
+    /// 1-based arrival order, `None` for players who didn't make it.
+    pub place: Option<u32>,
+    /// Closest the player got to the objective.
+    pub distance: f32,
+    pub score: u32,
+}
+
+/// What happened to the hunt as a result of a call, for announcing in chat.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HuntEvent {
+    Started { round: u64 },
+    Arrived { player: String, place: u32 },
+    Finished { round: u64, results: Vec<HuntResult> },
+}
+
+pub struct Hunt {
+    round_duration: Duration,
+    last_round: u64,
+    objective: Option<Objective>,
+    /// Keyed by connection, so players sharing a name are still told apart.
+    arrivals: Vec<SocketAddr>,
+    closest: HashMap<SocketAddr, (Hunter, f32)>,
+    /// Cleared when a round ends so the same descent doesn't start another;
+    /// re-armed once there is no prediction any more.
+    armed: bool,
+}
+
+impl Hunt {
+    pub fn new(round_duration: Duration) -> Self {
+        Self {
+            round_duration,
+            last_round: 0,
+            objective: None,
+            arrivals: Vec::new(),
+            closest: HashMap::new(),
+            armed: true,
+        }
+    }
+
+    pub fn objective(&self) -> Option<&Objective> {
+        self.objective.as_ref()
+    }
+
+    /// Seconds until the current round times out.
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        let objective = self.objective.as_ref()?;
+        Some(self.round_duration.saturating_sub(now - objective.started))
+    }
+
+    /// Feeds the latest landing prediction: starts a round, or moves the
+    /// objective of the running one as the prediction firms up.
+    pub fn set_prediction(&mut self, prediction: Option<[f32; 2]>, now: Instant) -> Option<HuntEvent> {
+        let Some([x, z]) = prediction else {
+            self.armed = true;
+            return None;
+        };
+        if let Some(objective) = &mut self.objective {
+            objective.x = x;
+            objective.z = z;
+            return None;
+        }
+        if !self.armed {
+            return None;
+        }
+        self.last_round += 1;
+        self.objective = Some(Objective { round: self.last_round, x, z, started: now });
+        Some(HuntEvent::Started { round: self.last_round })
+    }
+
+    /// Tracks how close `who` got and whether this move reached the objective.
+    pub fn player_moved(&mut self, who: SocketAddr, hunter: Hunter, x: f32, z: f32) -> Option<HuntEvent> {
+        let objective = self.objective.as_ref()?;
+        let distance = ((x - objective.x).powi(2) + (z - objective.z).powi(2)).sqrt();
+        let player = hunter.name.clone();
+        let closest = self.closest.entry(who).or_insert((hunter.clone(), f32::INFINITY));
+        *closest = (hunter, closest.1.min(distance));
+
+        if distance > ARRIVAL_RADIUS || self.arrivals.contains(&who) {
+            return None;
+        }
+        self.arrivals.push(who);
+        Some(HuntEvent::Arrived { player, place: self.arrivals.len() as u32 })
+    }
+
+    /// Ends the round once it has timed out or all `online` players arrived.
+    /// Arrivals who have since left still score but don't count towards that.
+    pub fn tick(&mut self, now: Instant, online: impl IntoIterator<Item = SocketAddr>) -> Option<HuntEvent> {
+        let objective = self.objective.as_ref()?;
+        let mut online = online.into_iter().peekable();
+        let everyone_arrived = online.peek().is_some() && online.all(|who| self.arrivals.contains(&who));
+        if now - objective.started < self.round_duration && !everyone_arrived {
+            return None;
+        }
+        Some(self.finish())
+    }
+
+    fn finish(&mut self) -> HuntEvent {
+        let round = self.objective.take().map_or(self.last_round, |o| o.round);
+        self.armed = false;
+        let arrivals = std::mem::take(&mut self.arrivals);
+        let mut closest = std::mem::take(&mut self.closest);
+
+        let mut results: Vec<HuntResult> = arrivals
+            .into_iter()
+            .filter_map(|who| closest.remove(&who))
+            .enumerate()
+            .map(|(i, (hunter, distance))| {
+                let place = i as u32 + 1;
+                let score = FIRST_PLACE_POINTS.saturating_sub(PLACE_STEP * i as u32).max(MIN_ARRIVAL_POINTS);
+                HuntResult { round, player: hunter.name, player_id: hunter.id, team: hunter.team, place: Some(place), distance, score }
+            })
+            .collect();
+
+        let mut others: Vec<(Hunter, f32)> = closest.into_values().collect();
+        others.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.extend(others.into_iter().map(|(hunter, distance)| {
+            let score = (MAX_APPROACH_POINTS * (1.0 - distance / ZERO_POINTS_DISTANCE)).max(0.0).round() as u32;
+            HuntResult { round, player: hunter.name, player_id: hunter.id, team: hunter.team, place: None, distance, score }
+        }));
+
+        HuntEvent::Finished { round, results }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hunter named `name` on its own port, with an id unless it's "guest".
+    fn hunter(name: &str, port: u16) -> (SocketAddr, Hunter) {
+        let id = (name != "guest").then(|| format!("id-{name}"));
+        (SocketAddr::from(([127, 0, 0, 1], port)), Hunter { name: name.to_string(), id, team: None })
+    }
+
+    #[test]
+    fn scores_arrival_order_then_distance() {
+        let mut hunt = Hunt::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert_eq!(hunt.set_prediction(Some([100.0, 0.0]), start), Some(HuntEvent::Started { round: 1 }));
+        let ((alice, a), (bob, b), (carol, c)) = (hunter("alice", 1), hunter("bob", 2), hunter("carol", 3));
+
+        hunt.player_moved(carol, c, 0.0, 0.0);
+        assert_eq!(
+            hunt.player_moved(bob, b.clone(), 99.0, 0.0),
+            Some(HuntEvent::Arrived { player: "bob".into(), place: 1 })
+        );
+        assert_eq!(hunt.player_moved(bob, b, 100.0, 0.0), None, "arrives once");
+        assert_eq!(
+            hunt.player_moved(alice, a, 100.0, 3.0),
+            Some(HuntEvent::Arrived { player: "alice".into(), place: 2 })
+        );
+
+        assert_eq!(hunt.tick(start + Duration::from_secs(30), [alice, bob, carol]), None);
+        let Some(HuntEvent::Finished { round, results }) = hunt.tick(start + Duration::from_secs(60), [alice, bob, carol]) else {
+            panic!("round should time out");
+        };
+        assert_eq!(round, 1);
+        let summary: Vec<_> = results.iter().map(|r| (r.player.as_str(), r.place, r.score)).collect();
+        assert_eq!(summary, [("bob", Some(1), 100), ("alice", Some(2), 90), ("carol", None, 30)]);
+        assert_eq!(results[0].player_id.as_deref(), Some("id-bob"));
+        assert!(hunt.objective().is_none());
+    }
+
+    #[test]
+    fn players_sharing_a_name_score_apart() {
+        let mut hunt = Hunt::new(Duration::from_secs(60));
+        let now = Instant::now();
+        hunt.set_prediction(Some([0.0, 0.0]), now);
+        let ((first, a), (second, b)) = (hunter("guest", 1), hunter("guest", 2));
+        hunt.player_moved(first, a, 0.0, 0.0);
+        hunt.player_moved(second, b, 100.0, 0.0);
+
+        let Some(HuntEvent::Finished { results, .. }) = hunt.tick(now + Duration::from_secs(60), [first, second]) else {
+            panic!("round should time out");
+        };
+        let summary: Vec<_> = results.iter().map(|r| (r.place, r.score, r.player_id.clone())).collect();
+        assert_eq!(summary, [(Some(1), 100, None), (None, 30, None)]);
+    }
+
+    #[test]
+    fn arrivals_who_left_dont_end_the_round() {
+        let mut hunt = Hunt::new(Duration::from_secs(60));
+        let now = Instant::now();
+        hunt.set_prediction(Some([0.0, 0.0]), now);
+        let ((alice, a), (bob, b)) = (hunter("alice", 1), hunter("bob", 2));
+        hunt.player_moved(alice, a, 0.0, 0.0);
+        hunt.player_moved(bob, b.clone(), 100.0, 0.0);
+
+        // Alice arrived and left; Bob is still hunting.
+        assert_eq!(hunt.tick(now, [bob]), None);
+        hunt.player_moved(bob, b, 0.0, 0.0);
+        let Some(HuntEvent::Finished { results, .. }) = hunt.tick(now, [bob]) else {
+            panic!("everyone online arrived");
+        };
+        let summary: Vec<_> = results.iter().map(|r| (r.player.as_str(), r.place)).collect();
+        assert_eq!(summary, [("alice", Some(1)), ("bob", Some(2))]);
+    }
+
+    #[test]
+    fn one_round_per_descent() {
+        let mut hunt = Hunt::new(Duration::from_secs(60));
+        let now = Instant::now();
+        hunt.set_prediction(Some([0.0, 0.0]), now);
+        hunt.set_prediction(Some([10.0, 5.0]), now);
+        assert_eq!(hunt.objective().map(|o| (o.x, o.z)), Some((10.0, 5.0)));
+
+        let (alice, a) = hunter("alice", 1);
+        hunt.player_moved(alice, a, 10.0, 5.0);
+        assert!(matches!(hunt.tick(now, [alice]), Some(HuntEvent::Finished { .. })), "everyone arrived");
+
+        assert_eq!(hunt.set_prediction(Some([10.0, 5.0]), now), None);
+        hunt.set_prediction(None, now);
+        assert_eq!(hunt.set_prediction(Some([0.0, 0.0]), now), Some(HuntEvent::Started { round: 2 }));
+    }
+}
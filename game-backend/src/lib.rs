@@ -0,0 +1,28 @@
+pub mod alerts;
+pub mod api;
+pub mod assets;
+pub mod balloon;
+pub mod clock;
+pub mod config;
+pub mod console;
+pub mod db;
+pub mod flight;
+pub mod gallery;
+pub mod game;
+pub mod hunt;
+pub mod limits;
+pub mod logging;
+pub mod mesh;
+pub mod metrics;
+pub mod noise;
+pub mod outbound;
+pub mod state;
+pub mod teams;
+pub mod telemetry;
+pub mod terrain;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod waypoints;
+pub mod weather;
+pub mod websockets;
+pub mod world;
@@ -0,0 +1,203 @@
+//! Admission control for WebSocket upgrades: concurrent connections and
+//! connection attempts per client IP, and spectator slots.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::config::Config;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Tracked addresses before stale ones are swept.
+const SWEEP_THRESHOLD: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rejection {
+    TooManyConnections,
+    /// Carries how long until the oldest attempt leaves the window.
+    RateLimited(Duration),
+}
+
+#[derive(Default)]
+struct IpState {
+    active: usize,
+    attempts: VecDeque<Instant>,
+}
+
+pub struct ConnectionLimiter {
+    per_ip: Mutex<HashMap<IpAddr, IpState>>,
+    /// Zero disables the cap.
+    max_per_ip: usize,
+    /// Zero disables the rate limit.
+    per_minute: usize,
+}
+
+/// Held for the lifetime of a connection; frees its slot on drop.
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl ConnectionLimiter {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            per_ip: Mutex::new(HashMap::new()),
+            max_per_ip: config.max_connections_per_ip,
+            per_minute: config.connections_per_minute,
+        }
+    }
+
+    /// Counts a connection attempt from `ip` and admits it if within limits.
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr, now: Instant) -> Result<ConnectionPermit, Rejection> {
+        let mut per_ip = self.per_ip.lock().unwrap();
+        if per_ip.len() >= SWEEP_THRESHOLD {
+            per_ip.retain(|_, s| s.active > 0 || s.attempts.back().is_some_and(|t| now.duration_since(*t) < RATE_WINDOW));
+        }
+        let state = per_ip.entry(ip).or_default();
+
+        while state.attempts.front().is_some_and(|t| now.duration_since(*t) >= RATE_WINDOW) {
+            state.attempts.pop_front();
+        }
+        if self.per_minute > 0 && state.attempts.len() >= self.per_minute {
+            let oldest = state.attempts[0];
+            return Err(Rejection::RateLimited(RATE_WINDOW - now.duration_since(oldest)));
+        }
+        state.attempts.push_back(now);
+
+        if self.max_per_ip > 0 && state.active >= self.max_per_ip {
+            return Err(Rejection::TooManyConnections);
+        }
+        state.active += 1;
+        Ok(ConnectionPermit { limiter: self.clone(), ip })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        let mut per_ip = self.limiter.per_ip.lock().unwrap();
+        if let Some(state) = per_ip.get_mut(&self.ip) {
+            state.active -= 1;
+            // Forget idle addresses once their attempts have aged out.
+            if state.active == 0 && state.attempts.back().is_none_or(|t| t.elapsed() >= RATE_WINDOW) {
+                per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Caps concurrent spectators separately from players.
+pub struct SpectatorSlots {
+    active: AtomicUsize,
+    /// Zero disables the cap.
+    max: usize,
+}
+
+/// Held for the lifetime of a spectator connection; frees its slot on drop.
+pub struct SpectatorPermit {
+    slots: Arc<SpectatorSlots>,
+}
+
+impl SpectatorSlots {
+    pub fn new(config: &Config) -> Self {
+        Self { active: AtomicUsize::new(0), max: config.max_spectators }
+    }
+
+    /// A slot if one is free.
+    pub fn try_acquire(self: &Arc<Self>) -> Option<SpectatorPermit> {
+        self.active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                (self.max == 0 || active < self.max).then_some(active + 1)
+            })
+            .ok()?;
+        Some(SpectatorPermit { slots: self.clone() })
+    }
+}
+
+impl Drop for SpectatorPermit {
+    fn drop(&mut self) {
+        self.slots.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Whether a browser `Origin` may open a WebSocket. An empty allow-list
+/// accepts everything; requests without an `Origin` (non-browser clients)
+/// are always accepted since they could send any value anyway.
+pub fn origin_allowed(allowed: &[String], origin: Option<&str>) -> bool {
+    match origin {
+        _ if allowed.is_empty() => true,
+        None => true,
+        Some(origin) => allowed.iter().any(|a| a.eq_ignore_ascii_case(origin.trim_end_matches('/'))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_per_ip: usize, per_minute: usize) -> Arc<ConnectionLimiter> {
+        Arc::new(ConnectionLimiter::new(&Config {
+            max_connections_per_ip: max_per_ip,
+            connections_per_minute: per_minute,
+            ..Config::default()
+        }))
+    }
+
+    #[test]
+    fn caps_concurrent_connections_per_ip() {
+        let limiter = limiter(2, 0);
+        let (a, b): (IpAddr, IpAddr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let now = Instant::now();
+
+        let first = limiter.try_acquire(a, now).unwrap();
+        let _second = limiter.try_acquire(a, now).unwrap();
+        assert_eq!(limiter.try_acquire(a, now).err(), Some(Rejection::TooManyConnections));
+        assert!(limiter.try_acquire(b, now).is_ok());
+
+        drop(first);
+        assert!(limiter.try_acquire(a, now).is_ok());
+    }
+
+    #[test]
+    fn rate_limits_attempts_in_window() {
+        let limiter = limiter(0, 2);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let start = Instant::now();
+
+        drop(limiter.try_acquire(ip, start).unwrap());
+        drop(limiter.try_acquire(ip, start + Duration::from_secs(10)).unwrap());
+        assert_eq!(
+            limiter.try_acquire(ip, start + Duration::from_secs(20)).err(),
+            Some(Rejection::RateLimited(Duration::from_secs(40)))
+        );
+        assert!(limiter.try_acquire(ip, start + RATE_WINDOW).is_ok());
+    }
+
+    #[test]
+    fn caps_spectators() {
+        let slots = Arc::new(SpectatorSlots::new(&Config { max_spectators: 1, ..Config::default() }));
+        let first = slots.try_acquire().unwrap();
+        assert!(slots.try_acquire().is_none());
+        drop(first);
+        assert!(slots.try_acquire().is_some());
+
+        let unlimited = Arc::new(SpectatorSlots::new(&Config { max_spectators: 0, ..Config::default() }));
+        let _held: Vec<_> = (0..10).map(|_| unlimited.try_acquire().unwrap()).collect();
+    }
+
+    #[test]
+    fn origin_allow_list() {
+        let allowed = vec!["https://apex.example".to_string()];
+        assert!(origin_allowed(&[], Some("https://evil.example")));
+        assert!(origin_allowed(&allowed, Some("https://apex.example")));
+        assert!(origin_allowed(&allowed, Some("HTTPS://APEX.EXAMPLE/")));
+        assert!(!origin_allowed(&allowed, Some("https://evil.example")));
+        assert!(!origin_allowed(&allowed, Some("null")));
+        assert!(origin_allowed(&allowed, None));
+    }
+}
@@ -1,11 +1,102 @@
-mod websockets;
-mod state;
-use state::GameState;
-use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use apex_backend::{
+    config::Config,
+    console,
+    db::Db,
+    game, logging,
+    state::GameState,
+    websockets::{self, AppState},
+};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+/// How long shutdown waits for player sessions to reach the database.
+const SHUTDOWN_SAVE_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() {
-    let game_state = Arc::new(Mutex::new(GameState::new()));
-    websockets::run(game_state).await;
+    let config = Config::from_env();
+    logging::init(config.log_format);
+
+    let db = Db::connect(&config.database_url).await.unwrap();
+    let game_state = game::spawn(GameState::with_config(&config), db.clone());
+    let state = AppState::new(game_state.clone(), db, config);
+    let (stop, console_stop) = oneshot::channel();
+    console::spawn(state.clone(), stop);
+
+    tokio::select! {
+        result = serve(state) => result.unwrap(),
+        () = shutdown_signal(console_stop) => info!("shutting down"),
+    }
+    // Whoever is still online keeps what they did this session.
+    match tokio::time::timeout(SHUTDOWN_SAVE_TIMEOUT, game_state.save()).await {
+        Ok(Some(sessions)) => info!(sessions, "saved player sessions"),
+        _ => warn!("could not save player sessions before exiting"),
+    }
+}
+
+async fn serve(state: AppState) -> std::io::Result<()> {
+    let config = state.config.clone();
+    let listener = tokio::net::TcpListener::bind(config.bind).await?;
+
+    if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+        #[cfg(feature = "tls")]
+        {
+            use apex_backend::tls;
+
+            let rustls = tls::load(cert, key).await?;
+            tls::watch(rustls.clone(), cert.clone(), key.clone(), config.tls_reload_interval);
+            if let Some(redirect_bind) = config.http_redirect_bind {
+                let redirect_listener = tokio::net::TcpListener::bind(redirect_bind).await?;
+                info!("redirecting http://{redirect_bind} to HTTPS");
+                tokio::spawn(websockets::serve(redirect_listener, tls::redirect_app(config.bind.port())));
+            }
+            return tls::serve(listener, websockets::router(state), rustls).await;
+        }
+        #[cfg(not(feature = "tls"))]
+        warn!(
+            "ignoring {} and {}: built without the `tls` feature, serving plain HTTP",
+            cert.display(),
+            key.display()
+        );
+    }
+
+    websockets::serve(listener, websockets::router(state)).await
+}
+
+/// Ctrl-C, SIGTERM from a service manager, or Ctrl-C in the admin console.
+async fn shutdown_signal(console_stop: oneshot::Receiver<()>) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("could not listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("could not listen for SIGTERM: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    let console = async {
+        // Dropped without sending when the console is off or closed.
+        if console_stop.await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+        () = console => {}
+    }
 }
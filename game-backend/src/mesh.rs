@@ -0,0 +1,87 @@
+//! Chat bridge to the Meshtastic mesh. The ground station posts text it
+//! hears on the mesh channel and relays game chat starting with
+//! `MESH_PREFIX` over the radio. Every transmission costs airtime, so
+//! outgoing messages are spaced out to respect the LoRa duty cycle.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// Game chat starting with this goes out over the mesh too.
+pub const MESH_PREFIX: &str = "!mesh ";
+/// Longest text relayed to the radio, in characters, leaving room for the
+/// sender's name in a Meshtastic text payload.
+pub const MAX_MESH_TEXT: usize = 180;
+/// Sender names are cut to this many characters either way.
+pub const MAX_MESH_NAME: usize = 32;
+
+/// A text message crossing the bridge, in either direction.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MeshText {
+    /// Node name for messages from the mesh, player name for ones to it.
+    pub from: String,
+    pub text: String,
+}
+
+/// Spaces out transmissions by at least `min_interval`.
+pub struct MeshLimiter {
+    min_interval: Duration,
+    last_sent: Option<Instant>,
+}
+
+impl MeshLimiter {
+    pub fn new(min_interval: Duration) -> Self {
+        Self { min_interval, last_sent: None }
+    }
+
+    /// Whether the radio is free, or how long until it is.
+    pub fn ready(&self, now: Instant) -> Result<(), Duration> {
+        match self.last_sent {
+            Some(last) if now < last + self.min_interval => Err(last + self.min_interval - now),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts the wait for the next transmission. Only called once a message
+    /// actually reached the ground station.
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = Some(now);
+    }
+}
+
+/// Chat text from the radio can't be allowed to break the snapshot format.
+pub fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !matches!(c, ';' | '>') && !c.is_control()).collect::<String>().trim().to_string()
+}
+
+/// `name` cut to `MAX_MESH_NAME` characters.
+pub fn truncate_name(name: &str) -> String {
+    name.chars().take(MAX_MESH_NAME).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaces_out_transmissions() {
+        let mut limiter = MeshLimiter::new(Duration::from_secs(30));
+        let start = Instant::now();
+        assert!(limiter.ready(start).is_ok());
+        assert!(limiter.ready(start + Duration::from_secs(10)).is_ok(), "nothing sent yet");
+        limiter.sent(start);
+        assert_eq!(limiter.ready(start + Duration::from_secs(10)), Err(Duration::from_secs(20)));
+        assert!(limiter.ready(start + Duration::from_secs(30)).is_ok());
+    }
+
+    #[test]
+    fn strips_protocol_characters() {
+        assert_eq!(sanitize(" a;b>c\n"), "abc");
+    }
+
+    #[test]
+    fn truncates_names_by_character() {
+        let name = "é".repeat(40);
+        assert_eq!(truncate_name(&name).chars().count(), MAX_MESH_NAME);
+    }
+}
@@ -0,0 +1,50 @@
+//! Server-wide counters for connection health and backpressure.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::Serialize;
+
+/// Process-wide counters, exposed at `GET /api/metrics`.
+#[derive(Default)]
+pub struct Metrics {
+    pub connections: AtomicU64,
+    pub snapshots_sent: AtomicU64,
+    /// Snapshots replaced by a newer one before a slow client could take them.
+    pub snapshots_dropped: AtomicU64,
+    /// Control frames (pings, closes) dropped because a queue was full of them.
+    pub control_frames_dropped: AtomicU64,
+    pub slow_client_disconnects: AtomicU64,
+    pub oversized_frames: AtomicU64,
+    /// Upgrades refused by the origin check or connection limits.
+    pub rejected_connections: AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub connections: u64,
+    pub snapshots_sent: u64,
+    pub snapshots_dropped: u64,
+    pub control_frames_dropped: u64,
+    pub slow_client_disconnects: u64,
+    pub oversized_frames: u64,
+    pub rejected_connections: u64,
+}
+
+impl Metrics {
+    pub fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let get = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            connections: get(&self.connections),
+            snapshots_sent: get(&self.snapshots_sent),
+            snapshots_dropped: get(&self.snapshots_dropped),
+            control_frames_dropped: get(&self.control_frames_dropped),
+            slow_client_disconnects: get(&self.slow_client_disconnects),
+            oversized_frames: get(&self.oversized_frames),
+            rejected_connections: get(&self.rejected_connections),
+        }
+    }
+}
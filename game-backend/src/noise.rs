@@ -0,0 +1,167 @@
+//! Bit-for-bit port of the frontend's seeded RNG (`noise.js`) and of the
+//! `simplex-noise` 3D implementation it uses, so the server can evaluate the
+//! same terrain the clients render.
+
+const F3: f64 = 1.0 / 3.0;
+const G3: f64 = 1.0 / 6.0;
+
+const GRAD3: [[f64; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+/// `createSeededRandom` from `noise.js`: a string hash feeding mulberry32.
+pub struct SeededRandom {
+    state: i32,
+}
+
+impl SeededRandom {
+    pub fn new(seed: &str) -> Self {
+        // JS strings hash by UTF-16 code unit.
+        let hash = seed
+            .encode_utf16()
+            .fold(0i32, |hash, c| hash.wrapping_shl(5).wrapping_sub(hash).wrapping_add(c as i32));
+        Self { state: if hash == 0 { 1 } else { hash } }
+    }
+
+    /// Next value in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x6D2B79F5);
+        let s = self.state as u32;
+        let mut t = (s ^ (s >> 15)).wrapping_mul(1 | s);
+        t = t.wrapping_add((t ^ (t >> 7)).wrapping_mul(61 | t)) ^ t;
+        (t ^ (t >> 14)) as f64 / 4294967296.0
+    }
+}
+
+/// 3D simplex noise with a permutation table shuffled by a seeded RNG.
+pub struct Noise3 {
+    perm: [u8; 512],
+}
+
+impl Noise3 {
+    pub fn new(random: &mut SeededRandom) -> Self {
+        let mut perm = [0u8; 512];
+        for (i, p) in perm.iter_mut().take(256).enumerate() {
+            *p = i as u8;
+        }
+        for i in 0..255 {
+            let r = i + (random.next_f64() * (256 - i) as f64) as usize;
+            perm.swap(i, r);
+        }
+        for i in 256..512 {
+            perm[i] = perm[i - 256];
+        }
+        Self { perm }
+    }
+
+    pub fn from_seed(seed: &str) -> Self {
+        Self::new(&mut SeededRandom::new(seed))
+    }
+
+    fn corner(&self, gi: usize, x: f64, y: f64, z: f64) -> f64 {
+        let mut t = 0.6 - x * x - y * y - z * z;
+        if t < 0.0 {
+            return 0.0;
+        }
+        let g = GRAD3[self.perm[gi] as usize % 12];
+        t *= t;
+        t * t * (g[0] * x + g[1] * y + g[2] * z)
+    }
+
+    /// Noise value in roughly `[-1, 1]`.
+    pub fn get(&self, x: f64, y: f64, z: f64) -> f64 {
+        let perm = |i: usize| self.perm[i] as usize;
+
+        let s = (x + y + z) * F3;
+        let i = (x + s).floor() as i32;
+        let j = (y + s).floor() as i32;
+        let k = (z + s).floor() as i32;
+        let t = (i + j + k) as f64 * G3;
+        let x0 = x - (i as f64 - t);
+        let y0 = y - (j as f64 - t);
+        let z0 = z - (k as f64 - t);
+
+        let (i1, j1, k1, i2, j2, k2) = if x0 >= y0 {
+            if y0 >= z0 {
+                (1, 0, 0, 1, 1, 0)
+            } else if x0 >= z0 {
+                (1, 0, 0, 1, 0, 1)
+            } else {
+                (0, 0, 1, 1, 0, 1)
+            }
+        } else if y0 < z0 {
+            (0, 0, 1, 0, 1, 1)
+        } else if x0 < z0 {
+            (0, 1, 0, 0, 1, 1)
+        } else {
+            (0, 1, 0, 1, 1, 0)
+        };
+
+        let x1 = x0 - i1 as f64 + G3;
+        let y1 = y0 - j1 as f64 + G3;
+        let z1 = z0 - k1 as f64 + G3;
+        let x2 = x0 - i2 as f64 + 2.0 * G3;
+        let y2 = y0 - j2 as f64 + 2.0 * G3;
+        let z2 = z0 - k2 as f64 + 2.0 * G3;
+        let x3 = x0 - 1.0 + 3.0 * G3;
+        let y3 = y0 - 1.0 + 3.0 * G3;
+        let z3 = z0 - 1.0 + 3.0 * G3;
+
+        let ii = (i & 255) as usize;
+        let jj = (j & 255) as usize;
+        let kk = (k & 255) as usize;
+
+        let n0 = self.corner(ii + perm(jj + perm(kk)), x0, y0, z0);
+        let n1 = self.corner(ii + i1 + perm(jj + j1 + perm(kk + k1)), x1, y1, z1);
+        let n2 = self.corner(ii + i2 + perm(jj + j2 + perm(kk + k2)), x2, y2, z2);
+        let n3 = self.corner(ii + 1 + perm(jj + 1 + perm(kk + 1)), x3, y3, z3);
+
+        32.0 * (n0 + n1 + n2 + n3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference values produced by running the frontend's noise.js and
+    // simplex-noise 4.0.3 under node.
+
+    #[test]
+    fn seeded_random_matches_js() {
+        let mut r = SeededRandom::new("31415988");
+        assert_eq!(r.next_f64(), 0.34348654793575406);
+        assert_eq!(r.next_f64(), 0.30034513608552516);
+        assert_eq!(r.next_f64(), 0.7713663543108851);
+
+        let mut r = SeededRandom::new("cool seed");
+        assert_eq!(r.next_f64(), 0.4565697880461812);
+        assert_eq!(r.next_f64(), 0.5613414535764605);
+    }
+
+    #[test]
+    fn noise_matches_js() {
+        let noise = Noise3::from_seed("31415988");
+        let cases = [
+            ((0.0, 0.0, 0.0), 0.0),
+            ((0.5, -1.25, 0.1), 0.3532954839122942),
+            ((12.3, 4.56, 0.1), -0.005947923869302339),
+            ((-100.7, 33.3, 7.0), 0.012380563094649777),
+        ];
+        for ((x, y, z), expected) in cases {
+            let got = noise.get(x, y, z);
+            assert!((got - expected).abs() < 1e-12, "noise({x}, {y}, {z}) = {got}, expected {expected}");
+        }
+    }
+}
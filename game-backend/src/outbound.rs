@@ -0,0 +1,138 @@
+//! Per-connection bounded send queue. Snapshots are full state, so when a
+//! client falls behind only the newest one is worth keeping.
+
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::extract::ws::Message;
+use tokio::sync::Notify;
+
+use crate::metrics::Metrics;
+
+enum Outgoing {
+    Snapshot(String),
+    Control(Message),
+}
+
+struct Inner {
+    queue: VecDeque<Outgoing>,
+    /// Set while the queue is full, cleared once the writer catches up.
+    behind_since: Option<Instant>,
+    closed: bool,
+}
+
+pub struct Outbound {
+    inner: Mutex<Inner>,
+    notify: Notify,
+    capacity: usize,
+}
+
+impl Outbound {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner { queue: VecDeque::new(), behind_since: None, closed: false }),
+            notify: Notify::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Queues a snapshot; if the queue is full every older snapshot in it is
+    /// dropped in favour of this one.
+    pub fn push_snapshot(&self, snapshot: String, metrics: &Metrics) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.queue.len() >= self.capacity {
+            let before = inner.queue.len();
+            inner.queue.retain(|o| matches!(o, Outgoing::Control(_)));
+            for _ in inner.queue.len()..before {
+                Metrics::incr(&metrics.snapshots_dropped);
+            }
+            inner.behind_since.get_or_insert_with(Instant::now);
+        }
+        if inner.queue.len() >= self.capacity {
+            // Only control frames left and still full: the newest snapshot loses.
+            Metrics::incr(&metrics.snapshots_dropped);
+            return;
+        }
+        inner.queue.push_back(Outgoing::Snapshot(snapshot));
+        drop(inner);
+        self.notify.notify_one();
+    }
+
+    /// Queues a ping or close frame, dropping the oldest queued frame if full.
+    pub fn push_control(&self, message: Message, metrics: &Metrics) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.queue.len() >= self.capacity {
+            inner.queue.pop_front();
+            Metrics::incr(&metrics.control_frames_dropped);
+            inner.behind_since.get_or_insert_with(Instant::now);
+        }
+        inner.queue.push_back(Outgoing::Control(message));
+        drop(inner);
+        self.notify.notify_one();
+    }
+
+    /// How long the queue has been continuously backed up, if it is.
+    pub fn behind_for(&self) -> Option<Duration> {
+        self.inner.lock().unwrap().behind_since.map(|since| since.elapsed())
+    }
+
+    /// Stops the writer once everything queued so far has been sent.
+    pub fn close(&self) {
+        self.inner.lock().unwrap().closed = true;
+        self.notify.notify_one();
+    }
+
+    /// Next frame for the writer, or `None` once closed and drained.
+    pub async fn next(&self) -> Option<(Message, bool)> {
+        loop {
+            {
+                let mut inner = self.inner.lock().unwrap();
+                if let Some(next) = inner.queue.pop_front() {
+                    if inner.queue.is_empty() {
+                        inner.behind_since = None;
+                    }
+                    return Some(match next {
+                        Outgoing::Snapshot(s) => (Message::Text(s.into()), true),
+                        Outgoing::Control(m) => (m, false),
+                    });
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn full_queue_coalesces_to_latest_snapshot() {
+        let metrics = Metrics::default();
+        let out = Outbound::new(3);
+        out.push_snapshot("a".into(), &metrics);
+        out.push_control(Message::Ping(vec![1].into()), &metrics);
+        out.push_snapshot("b".into(), &metrics);
+        assert!(out.behind_for().is_none());
+
+        out.push_snapshot("c".into(), &metrics);
+        assert!(out.behind_for().is_some());
+        assert_eq!(metrics.snapshot().snapshots_dropped, 2);
+
+        assert!(matches!(out.next().await, Some((Message::Ping(_), false))));
+        match out.next().await {
+            Some((Message::Text(t), true)) => assert_eq!(t.as_str(), "c"),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert!(out.behind_for().is_none());
+
+        out.close();
+        assert!(out.next().await.is_none());
+    }
+}
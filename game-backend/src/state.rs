@@ -1,61 +1,481 @@
-use std::{net::SocketAddr, collections::{HashMap, VecDeque}};
+use std::{net::SocketAddr, collections::{HashMap, VecDeque}, time::Instant};
+
+use apex_protocol::{ChatLine, Objective, PlayerPosition, Snapshot, WorldTime};
+use serde::Serialize;
+use tracing::{debug, trace};
+
+use crate::{
+    alerts::{AlertEvent, Alerts},
+    balloon::{Balloon, GeoPoint},
+    clock::{ClockChange, ClockStatus, WorldClock},
+    config::Config,
+    db::unix_ms,
+    flight::{FlightTracker, MissionEvent, MissionEventKind, pressure_altitude},
+    hunt::{Hunt, HuntEvent, HuntResult, Hunter},
+    mesh::{MAX_MESH_TEXT, MESH_PREFIX, MeshLimiter, MeshText, sanitize, truncate_name},
+    teams::{TeamScore, Teams},
+    telemetry::Telemetry,
+    terrain::{TERRAIN_SIZE, Terrain},
+    waypoints::Waypoints,
+    weather::{Weather, WeatherModel},
+    world::World,
+};
 
 const MAX_PING_AGE: usize = 10;
 const MAX_CHAT_MESSAGES: usize = 15; // Maximum number of chat messages to store
+const SYSTEM_SENDER: &str = "Server"; // Sender name for messages the server itself posts
+const GUEST_NAME: &str = "Guest"; // Public label of an unnamed player without a player id, plus their number
+const FEET_PER_METRE: f64 = 3.28084; // The HUD shows BalloonHeight in feet
+const DEFAULT_SPAWN: (f64, f64) = (5.0, 5.0); // Where the frontend used to start everyone
+const OBJECTIVE_EDGE_MARGIN: f64 = 10.0; // Keep objectives reachable inside the world edge
+const MAX_COUNTED_STEP: f64 = 50.0; // Longer jumps are respawns or teleports, not travel
 
 #[derive(Debug, Clone)]
 pub struct Player {
     pub name: Option<String>,
+    /// What results are stored under, see `db::player_id`.
+    pub id: Option<String>,
+    pub x: f32,
+    pub z: f32,
+    /// Unix ms when the player joined.
+    pub joined_at_ms: i64,
+    /// World units walked this session.
+    pub distance: f64,
+    /// Chat messages sent this session.
+    pub chats: u32,
+    pub team: Option<String>,
+    /// `0xrrggbb`, what other players are drawn in.
+    pub color: u32,
+    /// Order of joining, what tells unnamed guests apart.
+    pub number: u32,
+}
+
+/// A finished connection's stats, waiting to be written to the database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerSession {
+    /// The player's id; players without one aren't tracked.
+    pub player: String,
+    pub started_at_ms: i64,
+    pub ended_at_ms: i64,
+    pub distance: f64,
+    pub chats: u32,
+}
+
+/// An online player as the admin console lists them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlayerInfo {
+    pub name: String,
+    pub id: Option<String>,
+    pub addr: SocketAddr,
+    pub team: Option<String>,
     pub x: f32,
     pub z: f32,
+    pub online_secs: u64,
+    pub distance: f64,
+    pub chats: u32,
+}
+
+/// The world at a glance, for the admin console.
+#[derive(Debug, Clone, Serialize)]
+pub struct GameStats {
+    pub seed: u32,
+    pub players: usize,
+    pub avg_ping: f32,
+    pub phase: Option<String>,
+    pub clock: ClockStatus,
+    pub weather: Weather,
+    /// Whether a landing-zone hunt round is running.
+    pub hunting: bool,
+    pub teams: Vec<TeamScore>,
 }
 
 #[derive(Debug, Clone)]
 struct ChatMessage {
     sender_name: String,
     message: String,
+    /// Only this team sees it.
+    team: Option<String>,
 }
 
 pub struct GameState {
     seed: u32,
+    terrain: Terrain,
+    world: World,
+    trees_string: String, // Never changes after generation, so serialize once
     players: HashMap<SocketAddr, Player>,
+    joined: u32, // Players so far, for numbering them
+    balloon: Balloon,
     balloon_height: f32,
     signal_strength: f32,
-    ping: f32,
+    last_telemetry: Option<Telemetry>, // Every field seen so far, newest wins
+    latest_image: Option<u64>, // Gallery id of the newest balloon picture
     avg_ping: f32,
     pings: Vec<f32>,
     chat_messages: VecDeque<ChatMessage>, // Store recent chat messages
+    hunt: Hunt,
+    flight: FlightTracker,
+    clock: WorldClock,
+    weather: WeatherModel,
+    waypoints: Waypoints,
+    teams: Teams,
+    mission_events: Vec<MissionEvent>, // Waiting to be written to the timeline
+    alerts: Alerts,
+    alert_events: Vec<AlertEvent>, // Waiting to be published and recorded
+    mesh: MeshLimiter,
+    mesh_outbox: Vec<MeshText>, // Waiting for the ground station to transmit
+    finished_hunts: Vec<Vec<HuntResult>>, // Waiting to be written to the leaderboard
+    finished_sessions: Vec<PlayerSession>, // Waiting to be written to the leaderboard
+    ranks: HashMap<String, u32>, // All-time leaderboard place by player id, 1-based
+}
+
+impl Default for GameState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl GameState {
     pub fn new() -> Self {
+        Self::with_config(&Config::default())
+    }
+
+    pub fn with_config(config: &Config) -> Self {
+        let seed = config.seed;
+        let terrain = Terrain::new(&seed.to_string());
+        let world = World::generate(&seed.to_string(), &terrain);
+        let trees_string = world.get_trees_string();
         Self {
-            seed: 31415988,
+            seed,
+            terrain,
+            world,
+            trees_string,
             players: HashMap::new(),
+            joined: 0,
+            balloon: Balloon::new(config),
             balloon_height: 0.0,
             signal_strength: 0.0,
-            ping: 0.0,
+            last_telemetry: None,
+            latest_image: None,
             avg_ping: 0.0,
             pings: Vec::new(),
             chat_messages: VecDeque::with_capacity(MAX_CHAT_MESSAGES), // Initialize chat messages
+            hunt: Hunt::new(config.hunt_round),
+            flight: FlightTracker::default(),
+            clock: WorldClock::new(config.clock_mode, config.clock_speed, config.clock_start, unix_ms()),
+            weather: WeatherModel::default(),
+            waypoints: Waypoints::new(config.waypoints_per_player, config.max_waypoints, config.waypoint_ttl),
+            teams: Teams::new(config.teams.clone()),
+            mission_events: Vec::new(),
+            alerts: Alerts::new(config.alert_rules.clone()),
+            alert_events: Vec::new(),
+            mesh: MeshLimiter::new(config.mesh_min_interval),
+            mesh_outbox: Vec::new(),
+            finished_hunts: Vec::new(),
+            finished_sessions: Vec::new(),
+            ranks: HashMap::new(),
         }
     }
-    pub fn add_player(&mut self, addr: SocketAddr, name: Option<String>) {
-        println!("Adding player: {}", addr);
-        self.players.insert(addr, Player { name: name, x: 0.0, z: 0.0 });
+    /// Adds a player on `team` if that names one, otherwise on the smallest.
+    pub fn add_player(&mut self, addr: SocketAddr, name: Option<String>, team: Option<String>, id: Option<String>) {
+        let (x, z) = self.spawn_point();
+        let players = &self.players;
+        let (team, color) = self.teams.assign(team.as_deref(), |t| team_members(players, t));
+        debug!(%addr, ?team, "adding player");
+        self.joined += 1;
+        let number = self.joined;
+        let player = Player { name, id, x, z, joined_at_ms: unix_ms(), distance: 0.0, chats: 0, team, color, number };
+        self.players.insert(addr, player);
     }
     pub fn remove_player(&mut self, addr: SocketAddr) {
-        println!("Removing player: {}", addr);
-        self.players.remove(&addr);
+        debug!(%addr, "removing player");
+        self.waypoints.remove_owner(addr);
+        if let Some(player) = self.players.remove(&addr)
+            && let Some(id) = player.id
+        {
+            self.finished_sessions.push(PlayerSession {
+                player: id,
+                started_at_ms: player.joined_at_ms,
+                ended_at_ms: unix_ms(),
+                distance: player.distance,
+                chats: player.chats,
+            });
+        }
     }
     pub fn update_player(&mut self, addr: SocketAddr, x: f32, z: f32) {
-        if let Some(player) = self.players.get_mut(&addr) {
-            player.x = x;
-            player.z = z;
+        if !x.is_finite() || !z.is_finite() || !Terrain::in_bounds(x as f64, z as f64) {
+            debug!(%addr, x, z, "rejected out of bounds move");
+            return;
+        }
+        let (x, z) = self.world.resolve_collision(x, z);
+        let Some(player) = self.players.get_mut(&addr) else {
+            return;
+        };
+        let step = ((x - player.x) as f64).hypot((z - player.z) as f64);
+        if step <= MAX_COUNTED_STEP {
+            player.distance += step;
+        }
+        player.x = x;
+        player.z = z;
+        let (id, team) = (player.id.clone(), player.team.clone());
+        let hunter = Hunter { name: self.public_name(addr), id, team };
+        if let Some(event) = self.hunt.player_moved(addr, hunter, x, z) {
+            self.announce(event);
+        }
+    }
+
+    /// Puts up a marker for everyone at an in-bounds spot.
+    pub fn place_waypoint(&mut self, addr: SocketAddr, x: f32, z: f32, name: &str) {
+        if !x.is_finite() || !z.is_finite() || !Terrain::in_bounds(x as f64, z as f64) {
+            debug!(%addr, x, z, "rejected out of bounds waypoint");
+            return;
+        }
+        if !self.players.contains_key(&addr) {
+            return;
+        }
+        let owner = self.public_name(addr);
+        if let Some(id) = self.waypoints.place(addr, owner, &sanitize(name), x, z, Instant::now()) {
+            debug!(%addr, id, x, z, "waypoint placed");
+        }
+    }
+
+    /// Takes down one of `addr`'s own markers.
+    pub fn remove_waypoint(&mut self, addr: SocketAddr, id: u32) {
+        if !self.waypoints.remove(addr, id) {
+            debug!(%addr, id, "no such waypoint of this player");
+        }
+    }
+
+    /// What other players see `addr` as, never their address: their name,
+    /// the start of their player id (as on the leaderboard), or "Guest 3".
+    fn public_name(&self, addr: SocketAddr) -> String {
+        let Some(player) = self.players.get(&addr) else {
+            return GUEST_NAME.to_string();
+        };
+        match (&player.name, &player.id) {
+            (Some(name), _) => name.clone(),
+            (None, Some(id)) => format!("Player {}", &id[..4]),
+            (None, None) => format!("{} {}", GUEST_NAME, player.number),
+        }
+    }
+
+    /// Dry ground near the default start, so nobody spawns in a lake.
+    pub fn spawn_point(&self) -> (f32, f32) {
+        let (x, z) = self.terrain.spawn_point_near(DEFAULT_SPAWN.0, DEFAULT_SPAWN.1);
+        (x as f32, z as f32)
+    }
+
+    pub fn terrain(&self) -> &Terrain {
+        &self.terrain
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn get_trees_string(&self) -> String {
+        self.trees_string.clone()
+    }
+
+    pub fn apply_telemetry(&mut self, telemetry: &Telemetry) {
+        if let Some(altitude) = telemetry.altitude {
+            self.balloon_height = (altitude * FEET_PER_METRE).round() as f32;
+        }
+        if let Some(signal) = telemetry.signal {
+            self.signal_strength = signal;
+        }
+        if let (Some(lat), Some(lon), Some(alt)) = (telemetry.latitude, telemetry.longitude, telemetry.altitude) {
+            let now = Instant::now();
+            self.balloon.update(GeoPoint { lat, lon, alt }, now);
+            let prediction = self.balloon.predicted_landing().map(|[x, z]| self.objective_near(x, z));
+            if let Some(event) = self.hunt.set_prediction(prediction, now) {
+                self.announce(event);
+            }
+        }
+        if let Some(altitude) = telemetry.altitude.or(telemetry.pressure.map(pressure_altitude)) {
+            for event in self.flight.update(unix_ms(), altitude) {
+                if event.kind == MissionEventKind::Launch {
+                    self.clock.launched(event.at_ms);
+                }
+                self.add_system_message(event.message());
+                self.mission_events.push(event);
+            }
+        }
+        let events = self.alerts.on_telemetry(telemetry, Instant::now(), unix_ms());
+        self.raise_alerts(events);
+        let merged = self.last_telemetry.get_or_insert_with(Telemetry::default);
+        merged.merge(telemetry);
+        if let Some(weather) = self.weather.update(unix_ms(), telemetry, merged) {
+            debug!(?weather, "weather changed");
+        }
+    }
+
+    fn raise_alerts(&mut self, events: Vec<AlertEvent>) {
+        for event in events {
+            let prefix = if event.firing { "Alert" } else { "Resolved" };
+            self.add_system_message(format!("{}: {}", prefix, event.message));
+            self.alert_events.push(event);
+        }
+    }
+
+    /// Alerts raised or cleared since the last call.
+    pub fn take_alert_events(&mut self) -> Vec<AlertEvent> {
+        std::mem::take(&mut self.alert_events)
+    }
+
+    /// Mission events detected since the last call.
+    pub fn take_mission_events(&mut self) -> Vec<MissionEvent> {
+        std::mem::take(&mut self.mission_events)
+    }
+
+    /// The flight phase, once telemetry has reported an altitude.
+    fn phase(&self) -> Option<String> {
+        let telemetry = self.last_telemetry.as_ref()?;
+        if telemetry.altitude.is_none() && telemetry.pressure.is_none() {
+            return None;
+        }
+        Some(self.flight.phase().to_string())
+    }
+
+    /// Applies an admin change to the world clock and says so in chat.
+    pub fn set_clock(&mut self, change: &ClockChange) -> ClockStatus {
+        let now = unix_ms();
+        self.clock.apply(change, now);
+        let status = self.clock.status(now);
+        let mut notes = Vec::new();
+        if change.time.is_some() {
+            notes.push(format!("set to {}", status.time));
+        }
+        if let Some(speed) = change.speed {
+            notes.push(format!("running at {}x", speed));
+        }
+        match change.frozen {
+            Some(true) => notes.push("frozen".to_string()),
+            Some(false) => notes.push("resumed".to_string()),
+            None => {}
+        }
+        if !notes.is_empty() {
+            self.add_system_message(format!("Time {}", notes.join(", ")));
+        }
+        status
+    }
+
+    fn world_time(&self) -> WorldTime {
+        let (time, at_ms) = self.clock.anchor();
+        WorldTime { seconds: time.secs() as f32, speed: self.clock.rate() as f32, at_ms: at_ms.max(0) as u64 }
+    }
+
+    fn weather_snapshot(&self) -> apex_protocol::Weather {
+        self.weather.weather().into()
+    }
+
+    pub fn clock_status(&self) -> ClockStatus {
+        self.clock.status(unix_ms())
+    }
+
+    pub fn last_telemetry(&self) -> Option<Telemetry> {
+        self.last_telemetry.clone()
+    }
+
+    /// Dry, in-bounds ground closest to a predicted landing point.
+    fn objective_near(&self, x: f32, z: f32) -> [f32; 2] {
+        let limit = TERRAIN_SIZE / 2.0 - OBJECTIVE_EDGE_MARGIN;
+        let (x, z) = (
+            (x as f64).clamp(-limit, limit),
+            (z as f64).clamp(-limit, limit),
+        );
+        let (x, z) = self.terrain.spawn_point_near(x, z);
+        [x as f32, z as f32]
+    }
+
+    /// Time-based game rules, called about once a second.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(event) = self.hunt.tick(now, self.players.keys().copied()) {
+            self.announce(event);
+        }
+        let events = self.alerts.tick(now, unix_ms());
+        self.raise_alerts(events);
+        self.waypoints.expire(now);
+    }
+
+    fn announce(&mut self, event: HuntEvent) {
+        match event {
+            HuntEvent::Started { round } => {
+                self.add_system_message(format!("The balloon is coming down! Race to the landing zone (round {})", round));
+            }
+            HuntEvent::Arrived { player, place } => {
+                self.add_system_message(format!("{} reached the landing zone ({})", player, ordinal(place)));
+            }
+            HuntEvent::Finished { round, results } => {
+                let summary = results.iter()
+                    .map(|r| match r.place {
+                        Some(place) => format!("{} {} {}pts", ordinal(place), r.player, r.score),
+                        None => format!("{} {}pts", r.player, r.score),
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                if summary.is_empty() {
+                    self.add_system_message(format!("Round {} over, nobody joined the hunt", round));
+                    return;
+                }
+                self.add_system_message(format!("Round {} over: {}", round, summary));
+                for result in &results {
+                    if let Some(team) = &result.team {
+                        self.teams.add_score(team, result.score);
+                    }
+                }
+                if self.teams.enabled() {
+                    let standings = self.team_standings().iter()
+                        .map(|t| format!("{} {}", t.name, t.score))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    self.add_system_message(format!("Team scores: {}", standings));
+                }
+                self.finished_hunts.push(results);
+            }
+        }
+    }
+
+    /// Results of hunts that ended since the last call.
+    pub fn take_finished_hunts(&mut self) -> Vec<Vec<HuntResult>> {
+        std::mem::take(&mut self.finished_hunts)
+    }
+
+    /// Sessions of players who left since the last call.
+    pub fn take_finished_sessions(&mut self) -> Vec<PlayerSession> {
+        std::mem::take(&mut self.finished_sessions)
+    }
+
+    /// Takes the latest all-time standings (player ids, best first), telling
+    /// everyone when the lead passes to someone who is online.
+    pub fn set_ranks(&mut self, order: Vec<String>) {
+        let old_leader = self.ranks.iter().find(|(_, rank)| **rank == 1).map(|(id, _)| id.clone());
+        self.ranks = order.into_iter().zip(1..).collect();
+        let Some(leader) = self.ranks.iter().find(|(_, rank)| **rank == 1).map(|(id, _)| id.clone()) else {
+            return;
+        };
+        if old_leader.is_none_or(|old| old == leader) {
+            return;
+        }
+        let online = self.players.iter().find(|(_, p)| p.id.as_ref() == Some(&leader)).map(|(&addr, _)| addr);
+        if let Some(addr) = online {
+            self.add_system_message(format!("{} took the lead on the leaderboard", self.public_name(addr)));
         }
     }
+
+    /// `who`'s all-time leaderboard place, if they have an id and a place.
+    fn rank(&self, who: SocketAddr) -> Option<u32> {
+        let id = self.players.get(&who)?.id.as_ref()?;
+        self.ranks.get(id).copied()
+    }
+
+    /// Points clients at a new gallery picture and announces it in chat.
+    pub fn image_added(&mut self, id: u64) {
+        self.latest_image = Some(id);
+        self.add_system_message(format!("New balloon image #{}", id));
+    }
+
     pub fn calculate_avg_ping(&mut self) {
-        if self.pings.len() > 0 {
+        if !self.pings.is_empty() {
             let sum: f32 = self.pings.iter().sum();
             self.avg_ping = sum / self.pings.len() as f32;
         }
@@ -68,80 +488,291 @@ impl GameState {
         self.calculate_avg_ping();
     }
 
+    /// Removes a player and tells everyone why, e.g. "alice left (timeout)".
+    pub fn player_left(&mut self, addr: SocketAddr, reason: &str) {
+        let name = self.public_name(addr);
+        self.remove_player(addr);
+        self.add_system_message(format!("{} left ({})", name, reason));
+    }
+
+    /// Removes the player whose public name or player id is `target`,
+    /// telling everyone why. Returns their address, for closing the connection.
+    pub fn kick(&mut self, target: &str, reason: Option<&str>) -> Option<SocketAddr> {
+        let addr = self.players.iter()
+            .find(|&(&addr, player)| self.public_name(addr) == target || player.id.as_deref() == Some(target))
+            .map(|(&addr, _)| addr)?;
+        match reason {
+            Some(reason) => self.player_left(addr, &format!("kicked: {}", reason)),
+            None => self.player_left(addr, "kicked"),
+        }
+        Some(addr)
+    }
+
+    pub fn is_player(&self, addr: SocketAddr) -> bool {
+        self.players.contains_key(&addr)
+    }
+
+    /// Everyone online, by name.
+    pub fn players(&self) -> Vec<PlayerInfo> {
+        let now = unix_ms();
+        let mut players: Vec<PlayerInfo> = self.players.iter()
+            .map(|(&addr, player)| PlayerInfo {
+                name: self.public_name(addr),
+                id: player.id.clone(),
+                addr,
+                team: player.team.clone(),
+                x: player.x,
+                z: player.z,
+                online_secs: (now - player.joined_at_ms).max(0) as u64 / 1000,
+                distance: player.distance,
+                chats: player.chats,
+            })
+            .collect();
+        players.sort_by(|a, b| a.name.cmp(&b.name));
+        players
+    }
+
+    pub fn stats(&self) -> GameStats {
+        GameStats {
+            seed: self.seed,
+            players: self.countplayers(),
+            avg_ping: self.avg_ping,
+            phase: self.phase(),
+            clock: self.clock_status(),
+            weather: self.weather.weather(),
+            hunting: self.hunt.objective().is_some(),
+            teams: self.team_standings(),
+        }
+    }
+
+    /// Ends every online player's session so far and starts a new one, so
+    /// their progress reaches the leaderboard without waiting for them to
+    /// leave. Returns how many sessions were closed; players without an id
+    /// aren't tracked.
+    pub fn checkpoint_sessions(&mut self) -> usize {
+        let now = unix_ms();
+        let mut saved = 0;
+        for player in self.players.values_mut() {
+            let Some(id) = &player.id else {
+                continue;
+            };
+            self.finished_sessions.push(PlayerSession {
+                player: id.clone(),
+                started_at_ms: player.joined_at_ms,
+                ended_at_ms: now,
+                distance: player.distance,
+                chats: player.chats,
+            });
+            player.joined_at_ms = now;
+            player.distance = 0.0;
+            player.chats = 0;
+            saved += 1;
+        }
+        saved
+    }
+
+    pub fn add_system_message(&mut self, message: String) {
+        self.push_chat(ChatMessage { sender_name: SYSTEM_SENDER.to_string(), message, team: None });
+    }
+
+    /// Chat only the sender's team sees. Players without a team can't.
+    pub fn add_team_chat_message(&mut self, sender_addr: SocketAddr, message: String) {
+        let sender_name = self.public_name(sender_addr);
+        let Some(player) = self.players.get_mut(&sender_addr) else {
+            return;
+        };
+        let Some(team) = player.team.clone() else {
+            debug!(%sender_addr, "no team to chat with");
+            return;
+        };
+        player.chats += 1;
+        self.push_chat(ChatMessage { sender_name: format!("{} (team)", sender_name), message, team: Some(team) });
+    }
+
+    /// Every team with its points and online members.
+    pub fn team_standings(&self) -> Vec<TeamScore> {
+        self.teams.standings(|t| team_members(&self.players, t))
+    }
+
+    fn push_chat(&mut self, chat_message: ChatMessage) {
+        // Public chat and each team's chat keep their own history, so a busy
+        // team can't push everyone else's messages out.
+        let same_audience = |msg: &ChatMessage| msg.team == chat_message.team;
+        if self.chat_messages.iter().filter(|msg| same_audience(msg)).count() >= MAX_CHAT_MESSAGES
+            && let Some(oldest) = self.chat_messages.iter().position(same_audience)
+        {
+            self.chat_messages.remove(oldest); // Remove the oldest message
+        }
+        self.chat_messages.push_back(chat_message); // Add the new message
+        trace!(message = self.chat_messages.back().unwrap().message, "chat message added");
+    }
+
     // Method to add a chat message
     pub fn add_chat_message(&mut self, sender_addr: SocketAddr, message: String) {
-        let sender_name: String = self.players.get(&sender_addr)
-            .and_then(|p: &Player| p.name.clone())
-            .unwrap_or_else(|| sender_addr.to_string()); // Use address if name is not set
+        let sender_name = self.public_name(sender_addr);
+        if let Some(player) = self.players.get_mut(&sender_addr) {
+            player.chats += 1;
+        }
+        let relay = message
+            .strip_prefix(MESH_PREFIX)
+            .map(|text| MeshText { from: truncate_name(&sender_name), text: text.trim().to_string() });
 
-        let chat_message = ChatMessage { sender_name, message };
+        self.push_chat(ChatMessage { sender_name, message, team: None });
+        if let Some(relay) = relay {
+            self.relay_to_mesh(relay);
+        }
+    }
 
-        if self.chat_messages.len() >= MAX_CHAT_MESSAGES {
-            self.chat_messages.pop_front(); // Remove the oldest message
+    /// Queues chat for the radio if the duty cycle allows, explaining in chat if not.
+    fn relay_to_mesh(&mut self, relay: MeshText) {
+        if relay.text.is_empty() {
+            return;
         }
-        self.chat_messages.push_back(chat_message); // Add the new message
-        println!("Chat message added: {}", self.chat_messages.back().unwrap().message); // Log added message
+        if relay.text.chars().count() > MAX_MESH_TEXT {
+            self.add_system_message(format!("Too long for the mesh (max {} characters), not sent", MAX_MESH_TEXT));
+            return;
+        }
+        match self.mesh.ready(Instant::now()) {
+            Ok(()) => self.mesh_outbox.push(relay),
+            Err(wait) => {
+                self.add_system_message(format!("Mesh radio busy, not sent (try again in {}s)", wait.as_secs() + 1));
+            }
+        }
+    }
+
+    /// Chat heard on the mesh, shown under the node's name.
+    pub fn add_mesh_message(&mut self, message: MeshText) {
+        let (from, text) = (truncate_name(&sanitize(&message.from)), sanitize(&message.text));
+        if text.is_empty() {
+            return;
+        }
+        let sender_name = if from.is_empty() { "mesh".to_string() } else { format!("{} (mesh)", from) };
+        self.push_chat(ChatMessage { sender_name, message: text, team: None });
+    }
+
+    /// Chat waiting to go out over the radio.
+    pub fn take_mesh_outbox(&mut self) -> Vec<MeshText> {
+        std::mem::take(&mut self.mesh_outbox)
+    }
+
+    /// A message from the outbox reached the ground station, so the radio
+    /// is busy for a while.
+    pub fn mesh_sent(&mut self, now: Instant) {
+        self.mesh.sent(now);
     }
 
     pub fn countplayers(&self) -> usize {
         self.players.len()
     }
 
-    pub fn get_state_string(&self, who: SocketAddr) -> String {
-        // Serialize other players' positions
-        let players_string = self.players.iter()
-            .filter(|&(&addr, _)| addr != who) // Exclude the requesting player
-            .map(|(addr, player)| {
-                format!("P[{}]:{:.2},{:.2}",
-                        player.name.clone().unwrap_or_else(|| addr.to_string()), // Use name or address
-                        player.x,
-                        player.z)
-            })
-            .collect::<Vec<String>>()
-            .join(";");
+    /// Where the hunt round's objective is, while one is running.
+    fn objective(&self) -> Option<Objective> {
+        let objective = self.hunt.objective()?;
+        let remaining = self.hunt.remaining(Instant::now()).unwrap_or_default();
+        Some(Objective { x: objective.x, z: objective.z, seconds_left: remaining.as_secs() })
+    }
 
-        // Serialize chat messages
-        let chat_string = self.chat_messages.iter()
-            .map(|msg| format!("{}>{}", msg.sender_name, msg.message)) // Format as Sender>Message
-            .collect::<Vec<String>>()
-            .join(";"); // Join messages with ;
+    pub fn get_state_string(&self, who: SocketAddr) -> String {
+        let mut snapshot = self.snapshot(Some(who));
+        snapshot.rank = self.rank(who);
+        snapshot.encode()
+    }
 
-        // Combine all parts
-        let mut state_parts = vec![
-            format!("Seed:{}", self.seed),
-            format!("BalloonHeight:{}", self.balloon_height),
-            format!("Signal:{}", self.signal_strength),
-            format!("AvgPing:{:.2}", self.avg_ping),
-            format!("Players:{}", self.countplayers().saturating_sub(1)), // Count *other* players
-        ];
+    fn team_of(&self, who: Option<SocketAddr>) -> Option<&str> {
+        self.players.get(&who?)?.team.as_deref()
+    }
 
-        if !players_string.is_empty() {
-            state_parts.push(players_string);
+    /// What a spectator sees: every player, the raw telemetry and, if
+    /// `follow` names an online player, where they are.
+    pub fn get_spectator_state_string(&self, follow: Option<&str>) -> String {
+        let mut snapshot = self.snapshot(None);
+        snapshot.telemetry = self.last_telemetry.as_ref().and_then(|t| serde_json::to_string(t).ok());
+        if let Some(target) = follow
+            && let Some((_, player)) = self.players.iter().find(|&(&addr, _)| self.public_name(addr) == target)
+        {
+            snapshot.follow = Some(PlayerPosition { name: target.to_string(), x: player.x, z: player.z, ..PlayerPosition::default() });
         }
+        snapshot.encode()
+    }
 
-        // Add chat messages if any exist, prefixed with "Chat:"
-        if !chat_string.is_empty() {
-            state_parts.push(format!("Chat:{}", chat_string));
-        }
+    /// The snapshot as seen by `who`, or by a spectator when `None`.
+    fn snapshot(&self, who: Option<SocketAddr>) -> Snapshot {
+        // Other players' positions, excluding the requesting player
+        let others = self.players.iter()
+            .filter(|&(&addr, _)| Some(addr) != who)
+            .map(|(&addr, player)| PlayerPosition {
+                name: self.public_name(addr),
+                x: player.x,
+                z: player.z,
+                color: Some(player.color),
+                team: player.team.clone(),
+            })
+            .collect();
 
-        state_parts.join(";") // Join all parts with ;
-    }
+        // Team chat only reaches that team; spectators have none
+        let team = self.team_of(who);
+        let chat = self.chat_messages.iter()
+            .filter(|msg| msg.team.is_none() || msg.team.as_deref() == team)
+            .map(|msg| ChatLine { sender: msg.sender_name.clone(), message: msg.message.clone() })
+            .collect();
 
-    pub fn get_init_state_string(&self, who: SocketAddr) -> String {
-        let other_players_count = self.players.iter().filter(|&(&addr, _)| addr != who).count();
+        // Spectators aren't players, so they see everyone
+        let players = match who {
+            Some(_) => self.countplayers().saturating_sub(1),
+            None => self.countplayers(),
+        };
 
-        let mut state_str = format!(
-            "Seed:{};BalloonHeight:{};Signal:{};AvgPing:{:.2};Players:{}",
-            self.seed, self.balloon_height, self.signal_strength, self.avg_ping, other_players_count
-        );
-        for (addr, player) in &self.players {
-            if *addr == who {
-                continue;
-            }
-            let name = player.name.clone().unwrap_or_else(|| addr.to_string());
-            state_str.push_str(&format!(";P[{}]:{:.2},{:.2}", name.replace(';', ""), player.x, player.z));
+        Snapshot {
+            seed: self.seed,
+            balloon_height: self.balloon_height,
+            signal: self.signal_strength,
+            avg_ping: self.avg_ping,
+            players, // Count *other* players
+            time: Some(self.world_time()),
+            weather: Some(self.weather_snapshot()),
+            balloon: self.balloon.position_at(Instant::now()),
+            phase: self.phase(),
+            image: self.latest_image,
+            objective: self.objective(),
+            team: team.map(str::to_string),
+            color: who.and_then(|addr| self.players.get(&addr)).map(|p| p.color),
+            teams: self.team_standings().into_iter().map(Into::into).collect(),
+            others,
+            waypoints: self.waypoints.iter()
+                .map(|w| apex_protocol::Waypoint { id: w.id, x: w.x, z: w.z, owner: w.owner_name.clone(), name: w.name.clone() })
+                .collect(),
+            chat,
+            ..Snapshot::default()
         }
+    }
 
-        state_str
+    pub fn get_init_state_string(&self, who: SocketAddr) -> String {
+        // Spawn goes first so the client knows where to start before the seed triggers world setup.
+        let spawn = self.players.get(&who)
+            .map(|p| [p.x, p.z])
+            .unwrap_or_else(|| self.spawn_point().into());
+        let mut snapshot = self.snapshot(Some(who));
+        snapshot.spawn = Some(spawn);
+        snapshot.now_ms = Some(unix_ms().max(0) as u64);
+        snapshot.rank = self.rank(who);
+        snapshot.name = self.players.contains_key(&who).then(|| self.public_name(who));
+        // History isn't replayed on join
+        snapshot.chat.clear();
+        snapshot.encode()
     }
 }
+
+fn team_members(players: &HashMap<SocketAddr, Player>, team: &str) -> usize {
+    players.values().filter(|p| p.team.as_deref() == Some(team)).count()
+}
+
+fn ordinal(n: u32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
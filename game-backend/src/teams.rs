@@ -0,0 +1,176 @@
+//! Teams and player colors. Players join the smallest team unless they ask
+//! for one, and get a shade of its color so teammates still look apart.
+//! Points from game modes add up per team for as long as the server runs.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// Colors handed out in join order when there are no teams.
+const PALETTE: [u32; 8] = [0xe6194b, 0x3cb44b, 0xffe119, 0x4363d8, 0xf58231, 0x911eb4, 0x46f0f0, 0xf032e6];
+/// Brightness of each successive member of a team, relative to its color.
+const SHADES: [f32; 5] = [1.0, 0.75, 1.3, 0.55, 1.6];
+
+/// A team as configured: `name:rrggbb`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Team {
+    pub name: String,
+    pub color: u32,
+}
+
+impl FromStr for Team {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid team {s:?}, expected name:rrggbb");
+        let (name, color) = s.split_once(':').ok_or_else(invalid)?;
+        let name = name.trim();
+        // Names go into snapshots and `/ws?team=` as they are.
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(invalid());
+        }
+        let color = color.trim().trim_start_matches('#');
+        if color.len() != 6 {
+            return Err(invalid());
+        }
+        let color = u32::from_str_radix(color, 16).map_err(|_| invalid())?;
+        Ok(Team { name: name.to_string(), color })
+    }
+}
+
+impl fmt::Display for Team {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{:06x}", self.name, self.color)
+    }
+}
+
+/// `red:ff4040,blue:4080ff`.
+pub fn parse_teams(s: &str) -> Result<Vec<Team>, String> {
+    let teams = s.split(',').filter(|t| !t.trim().is_empty()).map(str::parse).collect::<Result<Vec<Team>, _>>()?;
+    if teams.iter().enumerate().any(|(i, t)| teams[..i].iter().any(|other| other.name == t.name)) {
+        return Err(format!("duplicate team name in {s:?}"));
+    }
+    Ok(teams)
+}
+
+/// A team's standing, as sent to clients.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TeamScore {
+    pub name: String,
+    pub color: u32,
+    pub score: u32,
+    pub players: usize,
+}
+
+impl From<TeamScore> for apex_protocol::TeamScore {
+    fn from(TeamScore { name, color, score, players }: TeamScore) -> Self {
+        Self { name, color, score, players }
+    }
+}
+
+#[derive(Debug)]
+pub struct Teams {
+    teams: Vec<Team>,
+    /// Parallel to `teams`.
+    scores: Vec<u32>,
+    /// Players ever colored from `PALETTE`.
+    joined: usize,
+}
+
+impl Teams {
+    /// No teams means everyone plays for themselves.
+    pub fn new(teams: Vec<Team>) -> Self {
+        Self { scores: vec![0; teams.len()], teams, joined: 0 }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.teams.is_empty()
+    }
+
+    /// Picks a team and color for a new player. `wanted` is honored if it
+    /// names a team, otherwise the team with the fewest `members` (by team
+    /// name) gets them, the first listed winning ties.
+    pub fn assign(&mut self, wanted: Option<&str>, members: impl Fn(&str) -> usize) -> (Option<String>, u32) {
+        let chosen = wanted.and_then(|wanted| self.teams.iter().find(|t| t.name == wanted));
+        let Some(team) = chosen.or_else(|| self.teams.iter().min_by_key(|t| members(&t.name))) else {
+            let color = PALETTE[self.joined % PALETTE.len()];
+            self.joined += 1;
+            return (None, color);
+        };
+        let color = shade(team.color, SHADES[members(&team.name) % SHADES.len()]);
+        (Some(team.name.clone()), color)
+    }
+
+    /// Credits points a member earned in a game mode.
+    pub fn add_score(&mut self, team: &str, points: u32) {
+        if let Some(i) = self.teams.iter().position(|t| t.name == team) {
+            self.scores[i] = self.scores[i].saturating_add(points);
+        }
+    }
+
+    /// Every team in configured order.
+    pub fn standings(&self, members: impl Fn(&str) -> usize) -> Vec<TeamScore> {
+        self.teams
+            .iter()
+            .zip(&self.scores)
+            .map(|(team, &score)| TeamScore { name: team.name.clone(), color: team.color, score, players: members(&team.name) })
+            .collect()
+    }
+}
+
+/// Scales each channel of `rrggbb` by `factor`.
+fn shade(color: u32, factor: f32) -> u32 {
+    [16, 8, 0].iter().fold(0, |out, &shift| {
+        let channel = ((color >> shift) & 0xff) as f32;
+        out | (((channel * factor).round().min(255.0) as u32) << shift)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn red_and_blue() -> Vec<Team> {
+        vec![Team { name: "red".into(), color: 0xff4040 }, Team { name: "blue".into(), color: 0x4080ff }]
+    }
+
+    #[test]
+    fn parses_team_lists() {
+        let teams = parse_teams("red:ff4040, blue:#4080FF").unwrap();
+        assert_eq!(teams, red_and_blue());
+        assert_eq!(teams[1].to_string(), "blue:4080ff");
+        assert_eq!(parse_teams("").unwrap(), []);
+        assert!(parse_teams("red:ff4040,red:00ff00").is_err());
+        assert!(parse_teams("red team:ff4040").is_err());
+        assert!(parse_teams("red:fff").is_err());
+    }
+
+    #[test]
+    fn balances_unless_asked() {
+        let mut teams = Teams::new(red_and_blue());
+        let members = |red, blue| move |team: &str| if team == "red" { red } else { blue };
+        assert_eq!(teams.assign(None, members(0, 0)), (Some("red".into()), 0xff4040));
+        assert_eq!(teams.assign(None, members(1, 0)).0.as_deref(), Some("blue"));
+        assert_eq!(teams.assign(Some("red"), members(1, 0)), (Some("red".into()), shade(0xff4040, 0.75)));
+        assert_eq!(teams.assign(Some("green"), members(2, 1)).0.as_deref(), Some("blue"));
+    }
+
+    #[test]
+    fn without_teams_everyone_gets_a_palette_color() {
+        let mut teams = Teams::new(Vec::new());
+        assert_eq!(teams.assign(Some("red"), |_| 0), (None, PALETTE[0]));
+        assert_eq!(teams.assign(None, |_| 0), (None, PALETTE[1]));
+        assert!(teams.standings(|_| 0).is_empty());
+    }
+
+    #[test]
+    fn scores_add_up_per_team() {
+        let mut teams = Teams::new(red_and_blue());
+        teams.add_score("blue", 30);
+        teams.add_score("blue", 12);
+        teams.add_score("nobody", 99);
+        let standings = teams.standings(|team| if team == "blue" { 2 } else { 0 });
+        assert_eq!(standings[1], TeamScore { name: "blue".into(), color: 0x4080ff, score: 42, players: 2 });
+        assert_eq!(standings[0].score, 0);
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// One telemetry update from the ground station. Every field is optional so
+/// partial packets (e.g. no GPS lock yet) can still be applied.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Telemetry {
+    /// Degrees, WGS84.
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    /// Metres above sea level.
+    pub altitude: Option<f64>,
+    /// Received signal strength in dB.
+    pub signal: Option<f32>,
+    /// Barometric pressure in hPa. Stands in for the altitude when there is
+    /// no GPS fix.
+    pub pressure: Option<f64>,
+    /// Inside the payload box, in °C.
+    pub temperature: Option<f64>,
+    /// Outside air from the external probe, in °C.
+    pub outside_temperature: Option<f64>,
+}
+
+impl Telemetry {
+    /// Overlays the fields present in `update`, keeping the rest.
+    pub fn merge(&mut self, update: &Telemetry) {
+        self.latitude = update.latitude.or(self.latitude);
+        self.longitude = update.longitude.or(self.longitude);
+        self.altitude = update.altitude.or(self.altitude);
+        self.signal = update.signal.or(self.signal);
+        self.pressure = update.pressure.or(self.pressure);
+        self.temperature = update.temperature.or(self.temperature);
+        self.outside_temperature = update.outside_temperature.or(self.outside_temperature);
+    }
+}
@@ -0,0 +1,124 @@
+//! Server-side copy of the frontend's layered height function (`terrain.js`).
+//! Constants mirror `game-frontend/src/lib/config.js` and must be kept in sync.
+
+use crate::noise::Noise3;
+
+pub const TERRAIN_SIZE: f64 = 1000.0;
+
+const NOISE_SCALE_REGION: f64 = 0.015;
+const HEIGHT_MULTIPLIER_REGION: f64 = 10.0;
+const REGION_POWER: f64 = 1.8;
+
+const NOISE_SCALE_LOW_FREQ: f64 = 0.04;
+const HEIGHT_MULTIPLIER_LOW_FREQ: f64 = 8.0;
+
+const NOISE_SCALE_HIGH_FREQ: f64 = 0.08;
+const HEIGHT_MULTIPLIER_HIGH_FREQ: f64 = 2.5;
+
+/// The noise is sampled on a plane slightly above z = 0.
+const NOISE_SLICE: f64 = 0.1;
+
+pub const WATER_LEVEL: f64 = 2.0;
+pub const SAND_LEVEL: f64 = WATER_LEVEL + 0.7;
+pub const GRASS_LEVEL: f64 = SAND_LEVEL + 4.0;
+
+/// How far from the requested point spawn selection looks for dry land.
+const SPAWN_SEARCH_RADIUS: f64 = 100.0;
+const SPAWN_SEARCH_STEP: f64 = 2.0;
+
+pub struct Terrain {
+    high_freq: Noise3,
+    low_freq: Noise3,
+    region: Noise3,
+}
+
+impl Terrain {
+    /// Builds the terrain for the seed string clients receive as `Seed:`.
+    pub fn new(seed: &str) -> Self {
+        Self {
+            high_freq: Noise3::from_seed(seed),
+            low_freq: Noise3::from_seed(&format!("{seed}-low")),
+            region: Noise3::from_seed(&format!("{seed}-region")),
+        }
+    }
+
+    /// Ground height at world position (x, z), as rendered by the clients.
+    pub fn height_at(&self, x: f64, z: f64) -> f64 {
+        let raw_region = self.region.get(x * NOISE_SCALE_REGION, z * NOISE_SCALE_REGION, NOISE_SLICE);
+        let region_normalized = (raw_region + 1.0) / 2.0;
+        let region_height = region_normalized.powf(REGION_POWER) * HEIGHT_MULTIPLIER_REGION;
+
+        let raw_low = self.low_freq.get(x * NOISE_SCALE_LOW_FREQ, z * NOISE_SCALE_LOW_FREQ, NOISE_SLICE);
+        let low_multiplier = HEIGHT_MULTIPLIER_LOW_FREQ * (0.4 + region_normalized * 0.8);
+        let low_height = (raw_low + 1.0) / 2.0 * low_multiplier;
+
+        let raw_high = self.high_freq.get(x * NOISE_SCALE_HIGH_FREQ, z * NOISE_SCALE_HIGH_FREQ, NOISE_SLICE);
+        let high_multiplier = HEIGHT_MULTIPLIER_HIGH_FREQ * (0.6 + region_normalized * 0.6);
+        let high_height = (raw_high + 1.0) / 2.0 * high_multiplier;
+
+        region_height + low_height + high_height
+    }
+
+    pub fn is_water(&self, x: f64, z: f64) -> bool {
+        self.height_at(x, z) < WATER_LEVEL
+    }
+
+    pub fn in_bounds(x: f64, z: f64) -> bool {
+        let half = TERRAIN_SIZE / 2.0;
+        (-half..=half).contains(&x) && (-half..=half).contains(&z)
+    }
+
+    /// Closest dry, in-bounds point to (x, z), searching outwards in rings.
+    /// Falls back to the requested point if everything nearby is water.
+    pub fn spawn_point_near(&self, x: f64, z: f64) -> (f64, f64) {
+        if Self::in_bounds(x, z) && !self.is_water(x, z) {
+            return (x, z);
+        }
+        let mut radius = SPAWN_SEARCH_STEP;
+        while radius <= SPAWN_SEARCH_RADIUS {
+            let steps = (std::f64::consts::TAU * radius / SPAWN_SEARCH_STEP).ceil() as usize;
+            for step in 0..steps {
+                let angle = step as f64 / steps as f64 * std::f64::consts::TAU;
+                let (cx, cz) = (x + radius * angle.cos(), z + radius * angle.sin());
+                if Self::in_bounds(cx, cz) && !self.is_water(cx, cz) {
+                    return (cx, cz);
+                }
+            }
+            radius += SPAWN_SEARCH_STEP;
+        }
+        (x, z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn height_matches_js() {
+        // Reference values from terrain.js `getTerrainHeightAt` under node
+        // with the default server seed.
+        let terrain = Terrain::new("31415988");
+        let cases = [
+            ((0.0, 0.0), 8.001338206362462),
+            ((10.0, -20.0), 6.694838764803109),
+            ((123.45, -67.89), 4.828306085384201),
+            ((-250.0, 400.0), 12.34023428120903),
+            ((499.0, -499.0), 5.19509285907308),
+        ];
+        for ((x, z), expected) in cases {
+            let got = terrain.height_at(x, z);
+            assert!((got - expected).abs() < 1e-9, "height_at({x}, {z}) = {got}, expected {expected}");
+        }
+    }
+
+    #[test]
+    fn spawn_point_is_dry() {
+        let terrain = Terrain::new("31415988");
+        for (x, z) in [(0.0, 0.0), (-300.0, 250.0), (420.0, 10.0)] {
+            let (sx, sz) = terrain.spawn_point_near(x, z);
+            assert!(!terrain.is_water(sx, sz));
+            assert!(Terrain::in_bounds(sx, sz));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Native `wss://` serving with rustls, certificate hot reload and a plain
+//! HTTP listener that redirects to HTTPS.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    Router,
+    http::{HeaderMap, StatusCode, Uri, header},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+
+/// Loads the PEM certificate chain and private key.
+pub async fn load(cert: &Path, key: &Path) -> std::io::Result<RustlsConfig> {
+    // Several providers may be compiled in; pin ring. Fails harmlessly if a
+    // provider has already been installed.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key).await
+}
+
+/// Polls the certificate and key for changes and swaps them into `rustls`
+/// without dropping connections. A bad pair is logged and the previous one
+/// stays in use.
+pub fn watch(rustls: RustlsConfig, cert: PathBuf, key: PathBuf, interval: Duration) {
+    tokio::spawn(async move {
+        let mut seen = (modified(&cert), modified(&key));
+        loop {
+            tokio::time::sleep(interval).await;
+            let now = (modified(&cert), modified(&key));
+            if now == seen {
+                continue;
+            }
+            seen = now;
+            match rustls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => info!(cert = %cert.display(), "reloaded TLS certificate"),
+                Err(e) => warn!("failed to reload TLS certificate, keeping the old one: {e}"),
+            }
+        }
+    });
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Serves `app` over TLS on an already bound listener until the server stops.
+pub async fn serve(listener: TcpListener, app: Router, rustls: RustlsConfig) -> std::io::Result<()> {
+    debug!("listening on {} (tls)", listener.local_addr()?);
+    axum_server::from_tcp_rustls(listener.into_std()?, rustls)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+}
+
+/// Plain HTTP app sending every request to the same path on `https_port`.
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move { redirect(&headers, &uri, https_port) })
+}
+
+fn redirect(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let Some(host) = headers.get(header::HOST).and_then(|h| h.to_str().ok()) else {
+        return (StatusCode::BAD_REQUEST, "missing Host header").into_response();
+    };
+    // Drop the port the request came in on; IPv6 literals keep their brackets.
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if !port.contains(']') => name,
+        _ => host,
+    };
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let location = if https_port == 443 {
+        format!("https://{host}{path}")
+    } else {
+        format!("https://{host}:{https_port}{path}")
+    };
+    Redirect::permanent(&location).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(host: &str, uri: &str, port: u16) -> String {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, host.parse().unwrap());
+        let response = redirect(&headers, &uri.parse().unwrap(), port);
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        response.headers()[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn redirects_to_https_port() {
+        assert_eq!(location("example.com", "/ws?x=1", 443), "https://example.com/ws?x=1");
+        assert_eq!(location("example.com:80", "/", 3443), "https://example.com:3443/");
+        assert_eq!(location("[::1]:8080", "/api", 3443), "https://[::1]:3443/api");
+        assert_eq!(location("[::1]", "/", 443), "https://[::1]/");
+    }
+}
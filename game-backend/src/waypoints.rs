@@ -0,0 +1,154 @@
+//! Markers players place at world positions for everyone to see, e.g. to
+//! split up a landing-site search. Each player keeps a few at a time, the
+//! oldest making way for new ones, and all of them expire. A player's
+//! markers go when they leave, and there is a cap on the total.
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// Longest waypoint name kept, in characters.
+pub const MAX_NAME_CHARS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub id: u32,
+    pub owner: SocketAddr,
+    /// The owner's display name when it was placed.
+    pub owner_name: String,
+    pub name: String,
+    pub x: f32,
+    pub z: f32,
+    placed_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct Waypoints {
+    /// Oldest first.
+    placed: Vec<Waypoint>,
+    next_id: u32,
+    /// Zero turns waypoints off.
+    per_player: usize,
+    /// Across everyone.
+    max: usize,
+    ttl: Duration,
+}
+
+impl Waypoints {
+    pub fn new(per_player: usize, max: usize, ttl: Duration) -> Self {
+        Self { placed: Vec::new(), next_id: 1, per_player, max, ttl }
+    }
+
+    /// Places a waypoint, removing the owner's oldest if they are at the
+    /// limit, and the oldest of all if everyone together is. A blank `name`
+    /// becomes "Waypoint <id>". Returns the new id, or `None` with waypoints
+    /// turned off.
+    pub fn place(&mut self, owner: SocketAddr, owner_name: String, name: &str, x: f32, z: f32, now: Instant) -> Option<u32> {
+        if self.per_player == 0 || self.max == 0 {
+            return None;
+        }
+        let owned = self.placed.iter().filter(|w| w.owner == owner).count();
+        if owned >= self.per_player
+            && let Some(oldest) = self.placed.iter().position(|w| w.owner == owner)
+        {
+            self.placed.remove(oldest);
+        }
+        if self.placed.len() >= self.max {
+            self.placed.remove(0);
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        let name: String = name.chars().take(MAX_NAME_CHARS).collect::<String>().trim().to_string();
+        let name = if name.is_empty() { format!("Waypoint {}", id) } else { name };
+        self.placed.push(Waypoint { id, owner, owner_name, name, x, z, placed_at: now });
+        Some(id)
+    }
+
+    /// Removes waypoint `id` if `owner` placed it.
+    pub fn remove(&mut self, owner: SocketAddr, id: u32) -> bool {
+        let before = self.placed.len();
+        self.placed.retain(|w| !(w.id == id && w.owner == owner));
+        self.placed.len() != before
+    }
+
+    /// Takes down everything `owner` placed, returning how many.
+    pub fn remove_owner(&mut self, owner: SocketAddr) -> usize {
+        let before = self.placed.len();
+        self.placed.retain(|w| w.owner != owner);
+        before - self.placed.len()
+    }
+
+    /// Drops waypoints older than the expiry, returning how many.
+    pub fn expire(&mut self, now: Instant) -> usize {
+        let before = self.placed.len();
+        self.placed.retain(|w| now.duration_since(w.placed_at) < self.ttl);
+        before - self.placed.len()
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Waypoint> {
+        self.placed.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    #[test]
+    fn oldest_makes_way_at_the_limit() {
+        let mut waypoints = Waypoints::new(2, 10, Duration::from_secs(60));
+        let now = Instant::now();
+        let (alice, bob) = (addr(1), addr(2));
+        waypoints.place(alice, "alice".into(), "a", 0.0, 0.0, now);
+        waypoints.place(bob, "bob".into(), "b", 0.0, 0.0, now);
+        waypoints.place(alice, "alice".into(), "c", 0.0, 0.0, now);
+        waypoints.place(alice, "alice".into(), "d", 0.0, 0.0, now);
+        let names: Vec<&str> = waypoints.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn total_is_capped_and_leavers_take_theirs() {
+        let mut waypoints = Waypoints::new(2, 3, Duration::from_secs(60));
+        let now = Instant::now();
+        for (port, name) in [(1, "a"), (2, "b"), (3, "c"), (3, "d")] {
+            waypoints.place(addr(port), String::new(), name, 0.0, 0.0, now);
+        }
+        let names: Vec<&str> = waypoints.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(names, ["b", "c", "d"]);
+
+        assert_eq!(waypoints.remove_owner(addr(3)), 2);
+        assert_eq!(waypoints.iter().count(), 1);
+    }
+
+    #[test]
+    fn names_are_trimmed_or_numbered() {
+        let mut waypoints = Waypoints::new(5, 10, Duration::from_secs(60));
+        let now = Instant::now();
+        let id = waypoints.place(addr(1), "alice".into(), "   ", 1.0, 2.0, now).unwrap();
+        waypoints.place(addr(1), "alice".into(), &"x".repeat(100), 1.0, 2.0, now);
+        let names: Vec<String> = waypoints.iter().map(|w| w.name.clone()).collect();
+        assert_eq!(names, [format!("Waypoint {}", id), "x".repeat(MAX_NAME_CHARS)]);
+        assert_eq!(Waypoints::new(0, 10, Duration::from_secs(60)).place(addr(1), "alice".into(), "a", 0.0, 0.0, now), None);
+    }
+
+    #[test]
+    fn only_owners_remove_and_everything_expires() {
+        let mut waypoints = Waypoints::new(3, 10, Duration::from_secs(60));
+        let now = Instant::now();
+        let id = waypoints.place(addr(1), "alice".into(), "a", 0.0, 0.0, now).unwrap();
+        assert!(!waypoints.remove(addr(2), id));
+        assert!(waypoints.remove(addr(1), id));
+
+        waypoints.place(addr(1), "alice".into(), "b", 0.0, 0.0, now);
+        waypoints.place(addr(2), "bob".into(), "c", 0.0, 0.0, now + Duration::from_secs(30));
+        assert_eq!(waypoints.expire(now + Duration::from_secs(60)), 1);
+        assert_eq!(waypoints.iter().next().unwrap().name, "c");
+    }
+}
@@ -0,0 +1,181 @@
+//! World weather made from the payload's readings: the pressure trend
+//! (reduced to sea level so climbing doesn't look like a storm) brings
+//! clouds and wind, a cold ground brings fog. The ground temperature comes
+//! from the outside probe; the heated payload box says nothing about it.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::telemetry::Telemetry;
+
+/// How far back the pressure trend looks, like the usual three-hour
+/// barometric tendency, and the least history worth calling a trend.
+const TREND_WINDOW_MS: i64 = 3 * 60 * 60 * 1000;
+const MIN_TREND_SPAN_MS: i64 = 10 * 60 * 1000;
+/// Standard atmosphere temperature lapse rate, °C per metre.
+const LAPSE_RATE: f64 = 0.0065;
+/// Top of the troposphere (m), where the lapse rate and with it the
+/// sea-level reduction stop holding.
+const TROPOPAUSE: f64 = 11_000.0;
+/// Sea-level pressure (hPa) around which skies are neither clear nor overcast.
+const NEUTRAL_PRESSURE: f64 = 1013.0;
+/// Ground temperature (°C) below which fog starts forming.
+const FOG_TEMPERATURE: f64 = 8.0;
+const MAX_WIND: f64 = 25.0;
+
+/// What clients render. Values are rounded so small sensor noise doesn't
+/// count as a change.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Weather {
+    /// 0 (clear) to 1 (thick).
+    pub fog: f32,
+    /// 0 (clear sky) to 1 (overcast).
+    pub clouds: f32,
+    /// m/s.
+    pub wind: f32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self { fog: 0.0, clouds: 0.3, wind: 2.0 }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WeatherModel {
+    /// `(at_ms, sea-level hPa)`, oldest first, covering `TREND_WINDOW_MS`.
+    pressures: VecDeque<(i64, f64)>,
+    /// Estimated ground temperature, °C.
+    ground_temperature: Option<f64>,
+    weather: Weather,
+}
+
+impl WeatherModel {
+    pub fn weather(&self) -> Weather {
+        self.weather
+    }
+
+    /// Takes `update` with `merged` (everything known so far) for the
+    /// altitude, returning the new weather if it changed.
+    pub fn update(&mut self, at_ms: i64, update: &Telemetry, merged: &Telemetry) -> Option<Weather> {
+        // Pressure-derived altitudes would cancel out the very trend we want.
+        let altitude = merged.altitude;
+        if let (Some(pressure), Some(altitude)) = (update.pressure, altitude)
+            && altitude < TROPOPAUSE
+        {
+            self.pressures.push_back((at_ms, sea_level_pressure(pressure, altitude)));
+        }
+        while self.pressures.front().is_some_and(|&(t, _)| at_ms - t > TREND_WINDOW_MS) {
+            self.pressures.pop_front();
+        }
+        if let Some(temperature) = update.outside_temperature
+            && altitude.is_none_or(|a| a < TROPOPAUSE)
+        {
+            self.ground_temperature = Some(temperature + altitude.unwrap_or(0.0) * LAPSE_RATE);
+        }
+        let weather = self.compute();
+        if weather == self.weather {
+            return None;
+        }
+        self.weather = weather;
+        Some(weather)
+    }
+
+    /// Sea-level hPa per hour over the window, once it spans long enough.
+    fn trend(&self) -> Option<f64> {
+        let (&(first_at, first), &(last_at, last)) = (self.pressures.front()?, self.pressures.back()?);
+        let span = last_at - first_at;
+        (span >= MIN_TREND_SPAN_MS).then(|| (last - first) / (span as f64 / 3_600_000.0))
+    }
+
+    fn compute(&self) -> Weather {
+        let default = Weather::default();
+        let pressure = self.pressures.back().map(|&(_, p)| p);
+        let trend = self.trend().unwrap_or(0.0);
+
+        let clouds = match pressure {
+            // Low pressure and falling barometers both mean cloud.
+            Some(p) => ((NEUTRAL_PRESSURE - p) / 30.0 + 0.4 - trend * 0.15).clamp(0.0, 1.0),
+            None => default.clouds as f64,
+        };
+        let wind = match pressure {
+            Some(p) => (2.0 + trend.abs() * 3.0 + (NEUTRAL_PRESSURE - p).max(0.0) * 0.2).min(MAX_WIND),
+            None => default.wind as f64,
+        };
+        // Wind clears fog away.
+        let fog = match self.ground_temperature {
+            Some(t) => ((FOG_TEMPERATURE - t) / 12.0).clamp(0.0, 1.0) * (1.0 - wind / 15.0).clamp(0.0, 1.0),
+            None => default.fog as f64,
+        };
+
+        Weather { fog: round_to(fog, 0.05), clouds: round_to(clouds, 0.05), wind: round_to(wind, 0.5) }
+    }
+}
+
+/// Barometric pressure (hPa) reduced from `altitude` metres to sea level,
+/// valid below `TROPOPAUSE`.
+fn sea_level_pressure(pressure: f64, altitude: f64) -> f64 {
+    pressure / (1.0 - altitude / 44_330.0).max(0.01).powf(5.255)
+}
+
+impl From<Weather> for apex_protocol::Weather {
+    fn from(Weather { fog, clouds, wind }: Weather) -> Self {
+        Self { fog, clouds, wind }
+    }
+}
+
+fn round_to(value: f64, step: f64) -> f32 {
+    ((value / step).round() * step) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(pressure: f64, altitude: f64) -> Telemetry {
+        Telemetry { pressure: Some(pressure), altitude: Some(altitude), ..Telemetry::default() }
+    }
+
+    #[test]
+    fn climbing_is_not_a_storm() {
+        let mut model = WeatherModel::default();
+        let first = model.update(0, &reading(1013.25, 0.0), &reading(1013.25, 0.0));
+        assert_eq!(first.map(|w| w.clouds), Some(0.4));
+        // Standard atmosphere at 1000 m: the same sea-level pressure.
+        let climbing = reading(898.75, 1000.0);
+        assert_eq!(model.update(20 * 60 * 1000, &climbing, &climbing), None);
+        // Up in the stratosphere the reduction would read as a deep low.
+        let high = reading(54.7, 20_000.0);
+        assert_eq!(model.update(40 * 60 * 1000, &high, &high), None);
+    }
+
+    #[test]
+    fn falling_pressure_brings_clouds_and_wind() {
+        let mut model = WeatherModel::default();
+        model.update(0, &reading(1013.0, 0.0), &reading(1013.0, 0.0));
+        let weather = model.update(60 * 60 * 1000, &reading(1009.0, 0.0), &reading(1009.0, 0.0)).unwrap();
+        assert_eq!(weather.clouds, 1.0);
+        assert_eq!(weather.wind, 15.0);
+        assert_eq!(weather.fog, 0.0);
+    }
+
+    #[test]
+    fn cold_calm_ground_is_foggy() {
+        let mut model = WeatherModel::default();
+        let cold = Telemetry { outside_temperature: Some(-4.0), ..Telemetry::default() };
+        let weather = model.update(0, &cold, &cold).unwrap();
+        assert_eq!(weather.fog, 0.85);
+
+        // The same reading taken 1 km up means a milder ground.
+        let aloft = Telemetry { altitude: Some(1000.0), ..cold.clone() };
+        assert!(model.update(1000, &cold, &aloft).unwrap().fog < 0.85);
+    }
+
+    #[test]
+    fn payload_box_temperature_is_ignored() {
+        let mut model = WeatherModel::default();
+        let cold_box = Telemetry { temperature: Some(-30.0), ..Telemetry::default() };
+        assert_eq!(model.update(0, &cold_box, &cold_box), None);
+    }
+}
@@ -2,114 +2,424 @@ use axum::{
     Router,
     body::Bytes,
     extract::ws::{Message, Utf8Bytes, WebSocket, WebSocketUpgrade},
-    response::IntoResponse,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
     routing::any,
-    extract::State,
+    extract::{FromRef, Query, State},
 };
 use axum_extra::TypedHeader;
 
 use std::ops::ControlFlow;
-use std::{net::SocketAddr, path::PathBuf, sync::{Arc, Mutex}};
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 use tower_http::{
     services::ServeDir,
     trace::{DefaultMakeSpan, TraceLayer},
 };
 
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use apex_protocol::ClientMessage;
+use serde::Deserialize;
+use tokio::{
+    net::TcpListener,
+    sync::{
+        broadcast::{self, error::TryRecvError},
+        watch,
+    },
+};
 
 use axum::extract::connect_info::ConnectInfo;
 use axum::extract::ws::CloseFrame;
 
 use futures::{sink::SinkExt, stream::StreamExt};
 
-use crate::state::GameState;
+use tokio_tungstenite::tungstenite;
+use tracing::{Instrument, debug, error, info, info_span, trace, warn};
 
-pub async fn run(state: Arc<Mutex<GameState>>) -> (){
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                format!("{}=debug,tower_http=debug", env!("CARGO_CRATE_NAME")).into()
-            }),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+use crate::{
+    api, assets,
+    config::Config,
+    db::{self, Db},
+    gallery::Gallery,
+    game::GameHandle,
+    limits::{ConnectionLimiter, Rejection, SpectatorSlots, origin_allowed},
+    metrics::Metrics,
+    outbound::Outbound,
+};
+
+/// Shared by every request handler.
+#[derive(Clone)]
+pub struct AppState {
+    pub game: GameHandle,
+    pub config: Arc<Config>,
+    pub metrics: Arc<Metrics>,
+    pub limiter: Arc<ConnectionLimiter>,
+    pub spectators: Arc<SpectatorSlots>,
+    /// Disk fallback for frontend files, see `assets::serve`.
+    pub assets: Option<ServeDir>,
+    pub gallery: Arc<Gallery>,
+    pub db: Db,
+}
+
+impl FromRef<AppState> for GameHandle {
+    fn from_ref(state: &AppState) -> Self {
+        state.game.clone()
+    }
+}
+
+impl AppState {
+    pub fn new(game: GameHandle, db: Db, config: Config) -> Self {
+        Self {
+            game,
+            limiter: Arc::new(ConnectionLimiter::new(&config)),
+            spectators: Arc::new(SpectatorSlots::new(&config)),
+            assets: config.assets_dir.as_deref().map(assets::disk),
+            gallery: Arc::new(Gallery::open(&config.gallery_dir, config.max_images)),
+            db,
+            config: Arc::new(config),
+            metrics: Arc::default(),
+        }
+    }
+}
 
-    let assets_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("assets");
+/// Builds the HTTP/WebSocket router without binding anything, so tests can
+/// serve it on an ephemeral port.
+pub fn app(game: GameHandle, db: Db, config: Config) -> Router {
+    router(AppState::new(game, db, config))
+}
 
-    let app = Router::new()
-        .fallback_service(ServeDir::new(assets_dir).append_index_html_on_directories(true))
+/// `app` over state shared with something else, like the admin console.
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .fallback(assets::serve)
         .route("/ws", any(ws_handler))
+        .nest("/api", api::router())
         .layer(
             TraceLayer::new_for_http()
                 .make_span_with(DefaultMakeSpan::default().include_headers(true)),
         )
-        .with_state(state);
+        .with_state(state)
+}
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
+/// Serves `app` on an already bound listener until the server stops.
+pub async fn serve(listener: TcpListener, app: Router) -> std::io::Result<()> {
+    debug!("listening on {}", listener.local_addr()?);
     axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
     .await
-    .unwrap();
+}
+
+#[derive(Deserialize)]
+struct ConnectParams {
+    /// `/ws?spectator=true` watches without joining as a player.
+    #[serde(default)]
+    spectator: bool,
+    /// `/ws?team=name` joins that team instead of the smallest one.
+    team: Option<String>,
+    /// `/ws?player=token` is the browser's own secret, which leaderboard
+    /// results are kept under; see `db::player_id`.
+    player: Option<String>,
 }
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
     user_agent: Option<TypedHeader<headers::UserAgent>>,
+    headers: HeaderMap,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
-    State(state): State<Arc<Mutex<GameState>>>,
-) -> impl IntoResponse {
+    Query(params): Query<ConnectParams>,
+    State(state): State<AppState>,
+) -> Response {
     let user_agent = if let Some(TypedHeader(user_agent)) = user_agent {
         user_agent.to_string()
     } else {
         String::from("Unknown browser")
     };
-    println!("`{user_agent}` at {addr} connected.");
-    ws.on_upgrade(move |socket| handle_socket(socket, addr, state))
+
+    let origin = headers.get(header::ORIGIN).and_then(|o| o.to_str().ok());
+    if !origin_allowed(&state.config.allowed_origins, origin) {
+        warn!(%addr, user_agent, ?origin, "rejected connection: origin not allowed");
+        Metrics::incr(&state.metrics.rejected_connections);
+        return (StatusCode::FORBIDDEN, "origin not allowed").into_response();
+    }
+    let permit = match state.limiter.try_acquire(addr.ip(), Instant::now()) {
+        Ok(permit) => permit,
+        Err(Rejection::TooManyConnections) => {
+            warn!(%addr, user_agent, "rejected connection: too many connections from this address");
+            Metrics::incr(&state.metrics.rejected_connections);
+            return (StatusCode::TOO_MANY_REQUESTS, "too many connections").into_response();
+        }
+        Err(Rejection::RateLimited(retry_after)) => {
+            warn!(%addr, user_agent, "rejected connection: connecting too often");
+            Metrics::incr(&state.metrics.rejected_connections);
+            let retry_after = retry_after.as_secs().max(1).to_string();
+            return (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, retry_after)], "connecting too often")
+                .into_response();
+        }
+    };
+
+    let spectator_permit = if params.spectator {
+        match state.spectators.try_acquire() {
+            Some(permit) => Some(permit),
+            None => {
+                warn!(%addr, user_agent, "rejected spectator: no free slots");
+                Metrics::incr(&state.metrics.rejected_connections);
+                return (StatusCode::SERVICE_UNAVAILABLE, "too many spectators").into_response();
+            }
+        }
+    } else {
+        None
+    };
+
+    // Players are keyed by address, `conn` tells reconnects from the same one
+    // apart. `player` is filled in once the player id is known, to match logs
+    // with leaderboard and session rows.
+    let span = info_span!(
+        "conn",
+        conn = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+        player = tracing::field::Empty,
+        %addr,
+        spectator = params.spectator
+    );
+    span.in_scope(|| info!(user_agent, "connected"));
+    ws.max_frame_size(state.config.max_frame_bytes)
+        .max_message_size(state.config.max_frame_bytes)
+        .on_upgrade(move |socket| {
+            async move {
+                handle_socket(socket, addr, params, state.game, state.config, state.metrics).await;
+                drop(permit);
+                drop(spectator_permit);
+            }
+            .instrument(span)
+        })
+}
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Logs one in `every` moves of a connection, since clients send them many
+/// times a second.
+struct MoveSampler {
+    every: u64,
+    seen: u64,
+}
+
+impl MoveSampler {
+    fn new(every: u64) -> Self {
+        Self { every: every.max(1), seen: 0 }
+    }
+
+    fn sample(&mut self) -> bool {
+        self.seen += 1;
+        self.seen % self.every == 1 || self.every == 1
+    }
+}
+
+/// When a connection last showed signs of life, in milliseconds since it
+/// was accepted. Written by the receive task, checked by the send task.
+struct Liveness {
+    started: Instant,
+    last_seen_ms: AtomicU64,
+    last_input_ms: AtomicU64,
+}
+
+impl Liveness {
+    fn new() -> Self {
+        Self { started: Instant::now(), last_seen_ms: AtomicU64::new(0), last_input_ms: AtomicU64::new(0) }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn seen(&self) {
+        self.last_seen_ms.store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    fn input(&self) {
+        self.last_input_ms.store(self.now_ms(), Ordering::Relaxed);
+    }
+
+    fn silent_for(&self) -> Duration {
+        Duration::from_millis(self.now_ms().saturating_sub(self.last_seen_ms.load(Ordering::Relaxed)))
+    }
+
+    fn idle_for(&self) -> Duration {
+        Duration::from_millis(self.now_ms().saturating_sub(self.last_input_ms.load(Ordering::Relaxed)))
+    }
+}
+
+/// Why the send loop gave up on a connection.
+enum SendEnd {
+    Closed,
+    Timeout(&'static str),
+    /// Removed by an admin, who already told everyone.
+    Kicked,
+}
+
+/// Takes a connection out of the game, announcing `reason` in chat if given.
+/// Spectators were never in it.
+async fn leave(state: &GameHandle, who: SocketAddr, spectator: bool, reason: Option<&'static str>) {
+    match reason {
+        _ if spectator => {}
+        Some(reason) => state.player_left(who, reason).await,
+        None => state.remove_player(who).await,
+    }
 }
 
-async fn handle_socket(mut socket: WebSocket, who: SocketAddr, state: Arc<Mutex<GameState>>) {
-    state.lock().unwrap().add_player(who, None);
+/// Runs one connection. Spectators aren't added to the game: they get the
+/// spectator snapshot instead and may only pick a player to follow.
+async fn handle_socket(
+    mut socket: WebSocket,
+    who: SocketAddr,
+    params: ConnectParams,
+    state: GameHandle,
+    config: Arc<Config>,
+    metrics: Arc<Metrics>,
+) {
+    Metrics::incr(&metrics.connections);
+    let ConnectParams { spectator, team, player } = params;
+    let config_max_frame_bytes = config.max_frame_bytes;
+    let mut moves = MoveSampler::new(config.move_log_sample);
+    // Who a spectator's camera follows, set by the receive task.
+    let (follow_tx, follow_rx) = watch::channel(None::<String>);
+    let follow = spectator.then_some(follow_tx);
+    if !spectator {
+        let id = player.as_deref().and_then(db::player_id);
+        if let Some(id) = &id {
+            tracing::Span::current().record("player", id.as_str());
+        }
+        state.add_player(who, None, team, id).await;
+    }
 
+    let ping_sent = Instant::now();
     if socket
         .send(Message::Ping(Bytes::from_static(&[1, 2, 3])))
         .await
         .is_ok()
     {
-        println!("Pinged {who}...");
+        debug!("pinged");
     } else {
-        println!("Could not send ping {who}!");
-        state.lock().unwrap().remove_player(who);
+        warn!("could not send ping");
+        leave(&state, who, spectator, None).await;
         return;
     }
 
-    if let Some(msg) = socket.recv().await {
-        if let Ok(msg) = msg {
-            if process_message(msg, who, &state).is_break() {
-                state.lock().unwrap().remove_player(who);
+    // A client that never answers the greeting ping is dead already.
+    match tokio::time::timeout(config.heartbeat_timeout, socket.recv()).await {
+        Ok(Some(Ok(msg))) => {
+            if let Message::Pong(_) = msg
+                && !spectator
+            {
+                let rtt_ms = ping_sent.elapsed().as_secs_f32() * 1000.0;
+                state.add_ping(rtt_ms).await;
+            }
+            if process_message(msg, who, &state, &mut moves, follow.as_ref()).await.is_break() {
+                leave(&state, who, spectator, None).await;
                 return;
             }
-        } else {
-            println!("client {who} abruptly disconnected");
-            state.lock().unwrap().remove_player(who);
+        }
+        Ok(Some(Err(_))) => {
+            info!("client abruptly disconnected");
+            leave(&state, who, spectator, None).await;
+            return;
+        }
+        Ok(None) => {}
+        Err(_) => {
+            info!("client never answered the greeting ping");
+            leave(&state, who, spectator, Some("timeout")).await;
             return;
         }
     }
 
+    // World objects go first: the client builds its scene once it sees the seed.
+    let Some(trees) = state.trees_string().await else {
+        return;
+    };
+    let init_state = if spectator {
+        state.spectator_state_string(None).await
+    } else {
+        state.init_state_string(who).await
+    };
+    let Some(init_state) = init_state else {
+        return;
+    };
+    if socket.send(Message::Text(trees.into())).await.is_err()
+        || socket.send(Message::Text(init_state.into())).await.is_err()
+    {
+        warn!("could not send initial state");
+        leave(&state, who, spectator, None).await;
+        return;
+    }
+
     let (mut sender, mut receiver) = socket.split();
+    let liveness = Arc::new(Liveness::new());
+    let outbound = Arc::new(Outbound::new(config.outbound_queue));
+
+    // The only task touching the sink, so a stalled client never blocks the
+    // loop that decides what to send it.
+    let writer_queue = outbound.clone();
+    let writer_metrics = metrics.clone();
+    let mut write_task = tokio::spawn(async move {
+        while let Some((msg, is_snapshot)) = writer_queue.next().await {
+            if sender.send(msg).await.is_err() {
+                return false;
+            }
+            if is_snapshot {
+                Metrics::incr(&writer_metrics.snapshots_sent);
+            }
+        }
+        let _ = sender.flush().await;
+        true
+    }.in_current_span());
 
+    let mut kicks = state.subscribe_kicks();
     let state_sender = state.clone();
-    let sender_who = who.clone();
+    let sender_who = who;
+    let sender_liveness = liveness.clone();
+    let sender_queue = outbound.clone();
+    let sender_metrics = metrics.clone();
     let mut send_task = tokio::spawn(async move {
         // Variable to store the last state string sent
         let mut last_sent_state: Option<String> = None;
+        let mut last_ping = Instant::now();
+
+        let end = loop {
+            if !spectator && kicked(&mut kicks, &state_sender, sender_who).await {
+                break SendEnd::Kicked;
+            }
+            if sender_liveness.silent_for() > config.heartbeat_timeout {
+                break SendEnd::Timeout("timeout");
+            }
+            // Spectators only watch, so they are never idle.
+            if !spectator && !config.idle_timeout.is_zero() && sender_liveness.idle_for() > config.idle_timeout {
+                break SendEnd::Timeout("idle timeout");
+            }
+            if sender_queue.behind_for().is_some_and(|behind| behind > config.slow_client_timeout) {
+                Metrics::incr(&sender_metrics.slow_client_disconnects);
+                break SendEnd::Timeout("too slow");
+            }
+            if last_ping.elapsed() >= config.heartbeat_interval {
+                // The payload carries the send time so the pong yields a round trip.
+                let sent_ms = sender_liveness.now_ms().to_be_bytes();
+                sender_queue.push_control(Message::Ping(Bytes::copy_from_slice(&sent_ms)), &sender_metrics);
+                last_ping = Instant::now();
+            }
 
-        loop {
-            let current_state_string = state_sender.lock().unwrap().get_state_string(sender_who);
+            let current_state_string = if spectator {
+                let target = follow_rx.borrow().clone();
+                state_sender.spectator_state_string(target).await
+            } else {
+                state_sender.state_string(sender_who).await
+            };
+            let Some(current_state_string) = current_state_string else {
+                break SendEnd::Closed;
+            };
 
             let should_send = match &last_sent_state {
                 Some(last) => *last != current_state_string,
@@ -117,112 +427,206 @@ async fn handle_socket(mut socket: WebSocket, who: SocketAddr, state: Arc<Mutex<
             };
 
             if should_send {
-                if sender.send(Message::Text(current_state_string.clone().into())).await.is_err() {
-                    println!("Failed to send state to {sender_who}, closing connection.");
-                    break;
-                }
+                sender_queue.push_snapshot(current_state_string.clone(), &sender_metrics);
                 last_sent_state = Some(current_state_string);
             }
 
-            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-        }
-        let _ = sender
-            .send(Message::Close(Some(CloseFrame {
-                code: axum::extract::ws::close_code::NORMAL,
-                reason: Utf8Bytes::from_static("Server closing send task"),
-            })))
-            .await;
-    });
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        let reason = match end {
+            SendEnd::Closed => "Server closing send task",
+            SendEnd::Timeout(_) => "Connection timed out",
+            SendEnd::Kicked => "Kicked",
+        };
+        let code = match end {
+            SendEnd::Kicked => axum::extract::ws::close_code::POLICY,
+            _ => axum::extract::ws::close_code::NORMAL,
+        };
+        sender_queue.push_control(
+            Message::Close(Some(CloseFrame {
+                code,
+                reason: Utf8Bytes::from_static(reason),
+            })),
+            &sender_metrics,
+        );
+        sender_queue.close();
+        end
+    }.in_current_span());
 
     let state_receiver = state.clone();
+    let receiver_metrics = metrics.clone();
     let mut recv_task = tokio::spawn(async move {
         let mut cnt = 0;
-        while let Some(Ok(msg)) = receiver.next().await {
+        while let Some(msg) = receiver.next().await {
+            let msg = match msg {
+                Ok(msg) => msg,
+                Err(e) => {
+                    if is_oversized(e) {
+                        warn!(limit = config_max_frame_bytes, "frame over the size limit, disconnecting");
+                        Metrics::incr(&receiver_metrics.oversized_frames);
+                    }
+                    break;
+                }
+            };
             cnt += 1;
-            if process_message(msg, who, &state_receiver).is_break() {
+            liveness.seen();
+            match &msg {
+                Message::Text(_) => liveness.input(),
+                Message::Pong(payload) if !spectator => {
+                    if let Ok(sent_ms) = <[u8; 8]>::try_from(payload.as_ref()) {
+                        let rtt_ms = liveness.now_ms().saturating_sub(u64::from_be_bytes(sent_ms));
+                        state_receiver.add_ping(rtt_ms as f32).await;
+                    }
+                }
+                _ => {}
+            }
+            if process_message(msg, who, &state_receiver, &mut moves, follow.as_ref()).await.is_break() {
                 break;
             }
         }
         cnt
-    });
+    }.in_current_span());
 
+    let mut timed_out = None;
     tokio::select! {
         rv_a = (&mut send_task) => {
-            match rv_a {
-                Ok(_) => println!("Send task for {who} finished.", ),
-                Err(a) => println!("Error in send task for {who}: {a:?}")
-            }
+            timed_out = send_end(rv_a);
             recv_task.abort();
+            // Give the writer a moment to deliver the close frame.
+            if tokio::time::timeout(Duration::from_secs(1), &mut write_task).await.is_err() {
+                write_task.abort();
+            }
         },
         rv_b = (&mut recv_task) => {
             match rv_b {
-                Ok(b) => println!("Receive task for {who} finished after {b} messages."),
-                Err(b) => println!("Error in receive task for {who}: {b:?}")
+                Ok(b) => debug!(messages = b, "receive task finished"),
+                Err(b) => error!("receive task failed: {b:?}")
             }
             send_task.abort();
+            write_task.abort();
+        },
+        rv_c = (&mut write_task) => {
+            recv_task.abort();
+            match rv_c {
+                // The queue only closes right before the send task returns,
+                // so its result says why we are done.
+                Ok(true) => timed_out = send_end(send_task.await),
+                Ok(false) => {
+                    debug!("write task lost the connection");
+                    send_task.abort();
+                }
+                Err(c) => {
+                    error!("write task failed: {c:?}");
+                    send_task.abort();
+                }
+            }
+        }
+    }
+
+    leave(&state, who, spectator, timed_out).await;
+    info!("disconnected");
+}
+
+/// Logs how the send task ended and returns the timeout reason, if any.
+fn send_end(result: Result<SendEnd, tokio::task::JoinError>) -> Option<&'static str> {
+    match result {
+        Ok(SendEnd::Timeout(reason)) => {
+            info!(reason, "player removed");
+            Some(reason)
+        }
+        Ok(SendEnd::Closed) => {
+            debug!("send task finished");
+            None
+        }
+        Ok(SendEnd::Kicked) => {
+            info!("player kicked");
+            None
+        }
+        Err(a) => {
+            error!("send task failed: {a:?}");
+            None
         }
     }
+}
+
+/// Whether `who` was kicked since the last check. If kicks were missed by
+/// falling behind, the game is asked whether `who` is still playing.
+async fn kicked(kicks: &mut broadcast::Receiver<SocketAddr>, state: &GameHandle, who: SocketAddr) -> bool {
+    let mut lagged = false;
+    loop {
+        match kicks.try_recv() {
+            Ok(addr) if addr == who => return true,
+            Ok(_) => continue,
+            Err(TryRecvError::Lagged(_)) => lagged = true,
+            Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+        }
+    }
+    lagged && state.is_player(who).await == Some(false)
+}
 
-    state.lock().unwrap().remove_player(who);
-    println!("Websocket context {who} closed.");
+/// Whether a receive error came from the frame/message size limit.
+fn is_oversized(error: axum::Error) -> bool {
+    matches!(
+        error.into_inner().downcast_ref::<tungstenite::Error>(),
+        Some(tungstenite::Error::Capacity(_))
+    )
 }
 
-fn process_message(msg: Message, who: SocketAddr, state: &Arc<Mutex<GameState>>) -> ControlFlow<(), ()> {
+/// Handles one client message. `follow` is only given for spectators, whose
+/// text messages can do nothing but `follow <player>` (or bare `follow` to
+/// stop following).
+async fn process_message(
+    msg: Message,
+    who: SocketAddr,
+    state: &GameHandle,
+    moves: &mut MoveSampler,
+    follow: Option<&watch::Sender<Option<String>>>,
+) -> ControlFlow<(), ()> {
     match msg {
-        Message::Text(t) => {
-            println!(">>> {who} sent str: {t:?}");
-            let parts: Vec<&str> = t.splitn(2, ' ').collect(); // Split into command and the rest
-
-            match parts.as_slice() {
-                // Handle "move x z"
-                ["move", coords_str] => {
-                    let coords: Vec<&str> = coords_str.split_whitespace().collect();
-                    if coords.len() == 2 {
-                        if let (Ok(x), Ok(z)) = (coords[0].parse::<f32>(), coords[1].parse::<f32>()) {
-                            println!(">>> Parsed move command from {who}: x={x}, z={z}");
-                            state.lock().unwrap().update_player(who, x, z);
-                        } else {
-                            println!(">>> Failed to parse move coordinates from {who}: {coords_str:?}");
-                        }
-                    } else {
-                         println!(">>> Invalid move command format from {who}: {t:?}");
-                    }
-                }
-                // Handle "chat message content"
-                ["chat", message_content] => {
-                    if !message_content.trim().is_empty() {
-                        println!(">>> Parsed chat command from {who}: '{message_content}'");
-                        // Add the chat message to the game state
-                        state.lock().unwrap().add_chat_message(who, message_content.to_string());
-                    } else {
-                         println!(">>> Received empty chat message from {who}");
-                    }
-                }
-                // Handle other potential commands or default case
-                _ => {
-                    println!(">>> Received unknown command format from {who}: {t:?}");
+        Message::Text(t) => match (ClientMessage::decode(t.as_str()), follow) {
+            (Ok(ClientMessage::Follow(target)), Some(follow)) => {
+                debug!(?target, "spectator follow target");
+                follow.send_replace(target);
+            }
+            (Ok(_), Some(_)) => debug!(text = t.as_str(), "spectators are read-only, ignoring"),
+            (Ok(ClientMessage::Move { x, z }), None) => {
+                if moves.sample() {
+                    trace!(x, z, sampled_every = moves.every, "move");
                 }
+                state.update_player(who, x, z).await;
             }
-        }
+            (Ok(ClientMessage::Chat(message)), None) => {
+                info!(message, "chat");
+                state.add_chat_message(who, message).await;
+            }
+            (Ok(ClientMessage::TeamChat(message)), None) => {
+                info!(message, "team chat");
+                state.add_team_chat_message(who, message).await;
+            }
+            (Ok(ClientMessage::Mark { x, z, name }), None) => {
+                debug!(x, z, name, "waypoint");
+                state.place_waypoint(who, x, z, name).await;
+            }
+            (Ok(ClientMessage::Unmark(id)), None) => state.remove_waypoint(who, id).await,
+            (Ok(ClientMessage::Follow(_)), None) => debug!("only spectators can follow, ignoring"),
+            (Err(e), _) => debug!(text = t.as_str(), error = %e, "invalid message"),
+        },
         Message::Binary(d) => {
-            println!(">>> {} sent {} bytes: {:?}", who, d.len(), d);
+            debug!(bytes = d.len(), "binary message");
         }
         Message::Close(c) => {
             if let Some(cf) = c {
-                println!(
-                    ">>> {} sent close with code {} and reason `{}`",
-                    who, cf.code, cf.reason
-                );
+                debug!(code = cf.code, reason = cf.reason.as_str(), "client sent close");
             } else {
-                println!(">>> {who} somehow sent close message without CloseFrame");
+                debug!("client sent close without a close frame");
             }
             return ControlFlow::Break(());
         }
         Message::Pong(v) => {
-            println!(">>> {who} sent pong with {v:?}");
+            trace!(payload = ?v, "pong");
         }
         Message::Ping(v) => {
-            println!(">>> {who} sent ping with {v:?}");
+            trace!(payload = ?v, "ping");
         }
     }
     ControlFlow::Continue(())
@@ -0,0 +1,218 @@
+//! Deterministic world objects (trees) generated on the server from the seed,
+//! replacing the scattering the frontend used to do in `terrain.js`.
+//! Constants mirror `game-frontend/src/lib/config.js`.
+
+use std::collections::HashMap;
+
+use apex_protocol::{self as protocol, ServerMessage};
+
+use crate::{
+    noise::SeededRandom,
+    terrain::{GRASS_LEVEL, TERRAIN_SIZE, Terrain},
+};
+
+/// Vertices per side of the frontend's terrain mesh is this plus one.
+const TERRAIN_SEGMENTS: usize = 405; // Math.round(12.8 * Math.sqrt(terrainSize))
+
+const MAX_TREE_COUNT: usize = 45_000; // Math.round(450 * terrainSize² / 100²)
+const TREE_PLACEMENT_THRESHOLD: f64 = GRASS_LEVEL + 1.5;
+const TREE_PLACEMENT_DENSITY: f64 = 0.045;
+const MIN_TREE_DIST: f64 = 1.5;
+pub const TRUNK_RADIUS: f32 = 0.2;
+pub const PLAYER_RADIUS: f32 = 0.4;
+
+/// Size of the cells trees are bucketed into for collision lookups.
+const GRID_CELL: f32 = 4.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tree {
+    pub x: f32,
+    pub y: f64,
+    pub z: f32,
+    pub rotation: f64,
+    pub scale: f64,
+}
+
+pub struct World {
+    trees: Vec<Tree>,
+    grid: HashMap<(i32, i32), Vec<usize>>,
+}
+
+/// `Number.prototype.toFixed` for the values the frontend seeds its RNGs with.
+/// Inputs come from `f32`, so scaling by a power of ten stays exact in `f64`
+/// and ties round away from zero just like JS does.
+fn to_fixed(v: f32, digits: u32) -> String {
+    let scale = 10u64.pow(digits);
+    let scaled = (v.abs() as f64 * scale as f64 + 0.5).floor() as u64;
+    let sign = if v < 0.0 { "-" } else { "" };
+    if digits == 0 {
+        return format!("{sign}{scaled}");
+    }
+    format!(
+        "{sign}{}.{:0width$}",
+        scaled / scale,
+        scaled % scale,
+        width = digits as usize
+    )
+}
+
+fn cell_of(x: f32, z: f32) -> (i32, i32) {
+    ((x / GRID_CELL).floor() as i32, (z / GRID_CELL).floor() as i32)
+}
+
+impl World {
+    /// Places trees exactly as `createTerrain` did: on every mesh vertex high
+    /// enough for grass, with a per-vertex seeded roll against the density.
+    pub fn generate(seed: &str, terrain: &Terrain) -> Self {
+        let segment = TERRAIN_SIZE / TERRAIN_SEGMENTS as f64;
+        let half = TERRAIN_SIZE / 2.0;
+        let min_dist_sq = (MIN_TREE_DIST * MIN_TREE_DIST) as f32;
+
+        let mut world = World { trees: Vec::new(), grid: HashMap::new() };
+
+        'outer: for iy in 0..=TERRAIN_SEGMENTS {
+            // three.js stores mesh positions as f32.
+            let z = (iy as f64 * segment - half) as f32;
+            for ix in 0..=TERRAIN_SEGMENTS {
+                let x = (ix as f64 * segment - half) as f32;
+                let height = terrain.height_at(x as f64, z as f64);
+                if height < TREE_PLACEMENT_THRESHOLD {
+                    continue;
+                }
+
+                let pos_seed = format!("{seed}-placement-{}-{}", to_fixed(x, 2), to_fixed(z, 2));
+                if SeededRandom::new(&pos_seed).next_f64() >= TREE_PLACEMENT_DENSITY {
+                    continue;
+                }
+                if world.nearest_sq(x, z, 1) < min_dist_sq {
+                    continue;
+                }
+                if world.trees.len() >= MAX_TREE_COUNT {
+                    break 'outer;
+                }
+
+                let i = world.trees.len();
+                let mut variation = SeededRandom::new(&format!(
+                    "{seed}-variation-{i}-{}-{}",
+                    to_fixed(x, 1),
+                    to_fixed(z, 1)
+                ));
+                let rotation = variation.next_f64() * std::f64::consts::TAU;
+                let scale = 0.8 + variation.next_f64() * 0.4;
+
+                world.grid.entry(cell_of(x, z)).or_default().push(i);
+                world.trees.push(Tree { x, y: height, z, rotation, scale });
+            }
+        }
+
+        world
+    }
+
+    pub fn trees(&self) -> &[Tree] {
+        &self.trees
+    }
+
+    /// Squared distance to the closest tree within `reach` grid cells.
+    fn nearest_sq(&self, x: f32, z: f32, reach: i32) -> f32 {
+        let (cx, cz) = cell_of(x, z);
+        let mut best = f32::INFINITY;
+        for dx in -reach..=reach {
+            for dz in -reach..=reach {
+                for &i in self.grid.get(&(cx + dx, cz + dz)).into_iter().flatten() {
+                    let tree = &self.trees[i];
+                    best = best.min((tree.x - x).powi(2) + (tree.z - z).powi(2));
+                }
+            }
+        }
+        best
+    }
+
+    /// Pushes a player standing at (x, z) out of any tree trunk it overlaps.
+    pub fn resolve_collision(&self, mut x: f32, mut z: f32) -> (f32, f32) {
+        let (cx, cz) = cell_of(x, z);
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                for &i in self.grid.get(&(cx + dx, cz + dz)).into_iter().flatten() {
+                    let tree = &self.trees[i];
+                    let min_dist = TRUNK_RADIUS * tree.scale as f32 + PLAYER_RADIUS;
+                    let (ox, oz) = (x - tree.x, z - tree.z);
+                    let dist = (ox * ox + oz * oz).sqrt();
+                    if dist >= min_dist {
+                        continue;
+                    }
+                    if dist > f32::EPSILON {
+                        x = tree.x + ox / dist * min_dist;
+                        z = tree.z + oz / dist * min_dist;
+                    } else {
+                        x = tree.x + min_dist;
+                    }
+                }
+            }
+        }
+        (x, z)
+    }
+
+    /// `Trees:x,z,rotation,scale;...` sent once to each client on join.
+    pub fn get_trees_string(&self) -> String {
+        let trees = self.trees.iter()
+            .map(|t| protocol::Tree { x: t.x, z: t.z, rotation: t.rotation as f32, scale: t.scale as f32 })
+            .collect();
+        ServerMessage::Trees(trees).encode()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_fixed_matches_js() {
+        assert_eq!(to_fixed(-433.33334, 2), "-433.33");
+        assert_eq!(to_fixed(0.125, 2), "0.13");
+        assert_eq!(to_fixed(-0.125, 2), "-0.13");
+        assert_eq!(to_fixed(2.5, 0), "3");
+        assert_eq!(to_fixed(-500.0, 1), "-500.0");
+        assert_eq!(to_fixed(-0.001, 2), "-0.00");
+        assert_eq!(to_fixed(0.05, 1), "0.1");
+    }
+
+    #[test]
+    fn trees_match_js() {
+        // Reference output of terrain.js `createTerrain` placement under node
+        // with the default server seed.
+        let terrain = Terrain::new("31415988");
+        let world = World::generate("31415988", &terrain);
+        let trees = world.trees();
+        assert_eq!(trees.len(), 2820);
+
+        let expected = [
+            (0, -433.3333435058594, 10.623275786161365, -500.0, 0.41241565666464053, 0.8309120558202268),
+            (1, -171.6049346923828, 11.74967749838733, -500.0, 2.2008837352531416, 0.8469288253225387),
+            (1410, -53.08641815185547, 13.470214559331811, -6.172839641571045, 2.091115937383763, 1.0530656661838294),
+            (2819, 490.1234436035156, 11.145372088515623, 500.0, 1.3211170787434547, 1.1789896809495986),
+        ];
+        for (i, x, y, z, rotation, scale) in expected {
+            let tree = &trees[i];
+            assert_eq!(tree.x as f64, x);
+            assert_eq!(tree.z as f64, z);
+            assert!((tree.y - y).abs() < 1e-9);
+            assert!((tree.rotation - rotation).abs() < 1e-12);
+            assert!((tree.scale - scale).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn collision_pushes_player_out_of_trunk() {
+        let terrain = Terrain::new("31415988");
+        let world = World::generate("31415988", &terrain);
+        let tree = &world.trees()[1410];
+        let (x, z) = world.resolve_collision(tree.x + 0.1, tree.z);
+        let dist = ((x - tree.x).powi(2) + (z - tree.z).powi(2)).sqrt();
+        assert!(dist >= TRUNK_RADIUS * tree.scale as f32 + PLAYER_RADIUS - 1e-4);
+        assert!((z - tree.z).abs() < 1e-4);
+
+        // Open ground is left alone.
+        let open = (tree.x + 1.2, tree.z + 1.2);
+        assert_eq!(world.resolve_collision(open.0, open.1), open);
+    }
+}
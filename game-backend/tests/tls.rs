@@ -0,0 +1,115 @@
+#![cfg(feature = "tls")]
+
+use std::{net::SocketAddr, path::{Path, PathBuf}, sync::Arc, time::Duration};
+
+use apex_backend::{config::Config, db::Db, game, state::GameState, tls, websockets};
+use futures::StreamExt;
+use rcgen::generate_simple_self_signed;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    TlsConnector,
+    client::TlsStream,
+    rustls::{ClientConfig, RootCertStore, pki_types::{CertificateDer, ServerName}},
+};
+use tokio_tungstenite::{client_async, tungstenite::Message};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Writes a fresh self-signed `localhost` certificate and key into `dir`,
+/// returning the certificate in DER form for the client's trust store.
+fn write_cert(dir: &Path) -> CertificateDer<'static> {
+    let certified = generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("cert.pem"), certified.cert.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), certified.signing_key.serialize_pem()).unwrap();
+    certified.cert.der().clone()
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("apex-tls-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn spawn_tls_server(dir: &Path, reload: Duration) -> SocketAddr {
+    let (cert, key) = (dir.join("cert.pem"), dir.join("key.pem"));
+    let rustls = tls::load(&cert, &key).await.unwrap();
+    tls::watch(rustls.clone(), cert, key, reload);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Config::default();
+    let db = Db::in_memory().await;
+    let app = websockets::app(game::spawn(GameState::with_config(&config), db.clone()), db, config);
+    tokio::spawn(tls::serve(listener, app, rustls));
+    addr
+}
+
+async fn tls_connect(server: SocketAddr, trusted: CertificateDer<'static>) -> std::io::Result<TlsStream<TcpStream>> {
+    let mut roots = RootCertStore::empty();
+    roots.add(trusted).unwrap();
+    let config = ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let stream = TcpStream::connect(server).await?;
+    TlsConnector::from(Arc::new(config))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+}
+
+#[tokio::test]
+async fn serves_websocket_over_tls() {
+    let dir = temp_dir("ws");
+    let cert = write_cert(&dir);
+    let server = spawn_tls_server(&dir, Duration::from_secs(60)).await;
+
+    let stream = tls_connect(server, cert).await.unwrap();
+    let (mut ws, _) = client_async("wss://localhost/ws", stream).await.unwrap();
+    let first = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                return text.to_string();
+            }
+        }
+    })
+    .await
+    .unwrap();
+    assert!(first.starts_with("Trees:"));
+}
+
+#[tokio::test]
+async fn reloads_certificate_on_change() {
+    let dir = temp_dir("reload");
+    let old = write_cert(&dir);
+    let server = spawn_tls_server(&dir, Duration::from_millis(50)).await;
+    assert!(tls_connect(server, old.clone()).await.is_ok());
+
+    let new = write_cert(&dir);
+    let mut reloaded = false;
+    for _ in 0..40 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        if tls_connect(server, new.clone()).await.is_ok() {
+            reloaded = true;
+            break;
+        }
+    }
+    assert!(reloaded, "new certificate was never served");
+    assert!(tls_connect(server, old).await.is_err());
+}
+
+#[tokio::test]
+async fn http_listener_redirects_to_https() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(websockets::serve(listener, tls::redirect_app(3443)));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /ws?x=1 HTTP/1.1\r\nHost: example.com:3000\r\nConnection: close\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 308"), "{response}");
+    assert!(response.to_lowercase().contains("location: https://example.com:3443/ws?x=1"), "{response}");
+}
@@ -0,0 +1,994 @@
+use std::{net::SocketAddr, time::Duration};
+
+use apex_backend::{
+    alerts::AlertRule, clock::ClockMode, config::Config, db::{self, Db}, game, state::GameState, teams::parse_teams, terrain::Terrain, websockets, world::World,
+};
+use futures::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_tungstenite::{
+    MaybeTlsStream, WebSocketStream, connect_async,
+    tungstenite::{self, Message, client::IntoClientRequest},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(3);
+/// What test servers take telemetry with, see `post_telemetry`.
+const STATION_TOKEN: &str = "station-secret";
+/// What tests that play in teams set `APEX_TEAMS` to.
+const TEAMS: &str = "red:ff4040,blue:4080ff";
+
+async fn spawn_server() -> SocketAddr {
+    spawn_server_with(Config { station_token: Some(STATION_TOKEN.into()), ..Config::default() }).await
+}
+
+async fn spawn_server_with(config: Config) -> SocketAddr {
+    spawn_game(config).await.0
+}
+
+/// A server plus a handle to its game, for driving it the way the admin
+/// console does.
+async fn spawn_game(config: Config) -> (SocketAddr, game::GameHandle) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let db = Db::in_memory().await;
+    let game = game::spawn(GameState::with_config(&config), db.clone());
+    let app = websockets::app(game.clone(), db, config);
+    tokio::spawn(websockets::serve(listener, app));
+    (addr, game)
+}
+
+struct Client {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    /// The address the server knows this client by.
+    addr: SocketAddr,
+    /// What other players see this client as; empty for spectators.
+    name: String,
+    /// The world objects the server sent after the handshake.
+    trees: String,
+    /// The first state string the server sent after the world objects.
+    init_state: String,
+}
+
+impl Client {
+    async fn connect(server: SocketAddr) -> Client {
+        Self::connect_to(format!("ws://{server}/ws")).await
+    }
+
+    async fn spectate(server: SocketAddr) -> Client {
+        Self::connect_to(format!("ws://{server}/ws?spectator=true")).await
+    }
+
+    async fn connect_to(url: String) -> Client {
+        let (ws, _) = connect_async(url).await.unwrap();
+        let addr = match ws.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream.local_addr().unwrap(),
+            _ => unreachable!("plain ws connection"),
+        };
+        let mut client = Client { ws, addr, name: String::new(), trees: String::new(), init_state: String::new() };
+        // Reading drives the pong reply to the server's greeting ping.
+        client.trees = client.wait_for(|_| true).await;
+        client.init_state = client.wait_for(|_| true).await;
+        client.name = field(&client.init_state, "Name").unwrap_or_default().to_string();
+        client
+    }
+
+    async fn send(&mut self, text: &str) {
+        self.ws.send(Message::Text(text.into())).await.unwrap();
+    }
+
+    /// Reads state strings until one satisfies `pred`, panicking on timeout.
+    async fn wait_for(&mut self, pred: impl Fn(&str) -> bool) -> String {
+        let read = async {
+            while let Some(msg) = self.ws.next().await {
+                if let Message::Text(text) = msg.unwrap()
+                    && pred(&text)
+                {
+                    return text.to_string();
+                }
+            }
+            panic!("connection closed while waiting for state");
+        };
+        tokio::time::timeout(TIMEOUT, read)
+            .await
+            .expect("timed out waiting for matching state")
+    }
+}
+
+/// Minimal HTTP/1.1 request, returning the status code and body.
+async fn request(server: SocketAddr, method: &str, path: &str, body: &[u8]) -> (u16, String) {
+    request_with(server, method, path, "", body).await
+}
+
+/// Like `request`, with `headers` (each ending in `\r\n`) added.
+async fn request_with(server: SocketAddr, method: &str, path: &str, headers: &str, body: &[u8]) -> (u16, String) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = TcpStream::connect(server).await.unwrap();
+    let head = format!(
+        "{method} {path} HTTP/1.1\r\nHost: {server}\r\n{headers}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(body).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, b)| b.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+async fn post_json(server: SocketAddr, path: &str, body: &str) -> u16 {
+    request(server, "POST", path, body.as_bytes()).await.0
+}
+
+/// Posts telemetry the way the ground station does, with `STATION_TOKEN`.
+async fn post_telemetry(server: SocketAddr, body: &str) -> u16 {
+    let station = format!("Authorization: Bearer {STATION_TOKEN}\r\n");
+    request_with(server, "POST", "/api/telemetry", &station, body.as_bytes()).await.0
+}
+
+fn field<'a>(state: &'a str, key: &str) -> Option<&'a str> {
+    state
+        .split(';')
+        .find_map(|part| part.strip_prefix(key)?.strip_prefix(':'))
+}
+
+#[tokio::test]
+async fn join_receives_initial_state() {
+    let server = spawn_server().await;
+    let client = Client::connect(server).await;
+
+    assert_eq!(field(&client.init_state, "Seed"), Some("31415988"));
+    assert_eq!(field(&client.init_state, "BalloonHeight"), Some("0"));
+    assert_eq!(field(&client.init_state, "Signal"), Some("0"));
+    assert!(field(&client.init_state, "AvgPing").is_some());
+    assert_eq!(field(&client.init_state, "Players"), Some("0"));
+
+    let (x, z) = field(&client.init_state, "Spawn")
+        .and_then(|v| v.split_once(','))
+        .expect("spawn point");
+    let (x, z): (f64, f64) = (x.parse().unwrap(), z.parse().unwrap());
+    assert!(!Terrain::new("31415988").is_water(x, z));
+}
+
+#[tokio::test]
+async fn join_receives_world_objects() {
+    let server = spawn_server().await;
+    let client = Client::connect(server).await;
+
+    let trees = client.trees.strip_prefix("Trees:").expect("trees message");
+    let parsed: Vec<Vec<f32>> = trees
+        .split(';')
+        .map(|t| t.split(',').map(|v| v.parse().unwrap()).collect())
+        .collect();
+    assert_eq!(parsed.len(), 2820);
+    assert!(parsed.iter().all(|t| t.len() == 4));
+}
+
+#[tokio::test]
+async fn moves_into_trees_are_pushed_out() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    let world = World::generate("31415988", &Terrain::new("31415988"));
+    let tree = &world.trees()[0];
+    alice.send(&format!("move {} {}", tree.x, tree.z)).await;
+
+    let key = format!("P[{}]", alice.name);
+    let state = bob.wait_for(|s| field(s, &key).is_some()).await;
+    let (x, z) = field(&state, &key).unwrap().split_once(',').unwrap();
+    let (x, z): (f32, f32) = (x.parse().unwrap(), z.parse().unwrap());
+    let dist = ((x - tree.x).powi(2) + (z - tree.z).powi(2)).sqrt();
+    assert!(dist > 0.5, "player left inside trunk at distance {dist}");
+}
+
+#[tokio::test]
+async fn out_of_bounds_move_is_rejected() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("move 5000 0").await;
+    alice.send("move NaN 0").await;
+    alice.send("move 7 8").await;
+    let key = format!("P[{}]", alice.name);
+    bob.wait_for(|s| field(s, &key) == Some("7.00,8.00")).await;
+}
+
+#[tokio::test]
+async fn move_is_visible_to_other_players() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("move 12.5 -3.25").await;
+    let key = format!("P[{}]", alice.name);
+    let state = bob
+        .wait_for(|s| field(s, &key) == Some("12.50,-3.25"))
+        .await;
+    assert_eq!(field(&state, "Players"), Some("1"));
+
+    // Snapshots never include the receiving player itself.
+    let own = format!("P[{}]", bob.name);
+    let state = alice.wait_for(|s| field(s, &own).is_some()).await;
+    assert!(field(&state, &key).is_none());
+}
+
+#[tokio::test]
+async fn malformed_move_is_ignored() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("move 1.0").await;
+    alice.send("move a b").await;
+    alice.send("move 4 5").await;
+    let key = format!("P[{}]", alice.name);
+    bob.wait_for(|s| field(s, &key) == Some("4.00,5.00")).await;
+}
+
+#[tokio::test]
+async fn chat_is_broadcast() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("chat hello there").await;
+    let expected = format!("{}>hello there", alice.name);
+    let state = bob.wait_for(|s| s.contains(&expected)).await;
+    let (_, chat) = state.split_once(";Chat:").expect("chat section");
+    assert_eq!(chat, expected);
+
+    // The sender sees its own message too.
+    alice.wait_for(|s| s.contains(&expected)).await;
+}
+
+#[tokio::test]
+async fn empty_chat_is_dropped() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("chat    ").await;
+    alice.send("chat marker").await;
+    let state = bob.wait_for(|s| s.contains(">marker")).await;
+    let (_, chat) = state.split_once(";Chat:").unwrap();
+    assert_eq!(chat.split(';').count(), 1);
+}
+
+/// Waypoint parts of a state string, as `(id, rest)`.
+fn waypoints(state: &str) -> Vec<(u32, String)> {
+    state
+        .split(';')
+        .filter_map(|part| part.strip_prefix("W["))
+        .filter_map(|part| part.split_once("]:"))
+        .map(|(id, rest)| (id.parse().unwrap(), rest.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn waypoints_are_shared_limited_and_owned() {
+    let server = spawn_server_with(Config { waypoints_per_player: 2, ..Config::default() }).await;
+    let token = "alice-0123456789abcdef";
+    let mut alice = Client::connect_to(format!("ws://{server}/ws?player={token}")).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send("mark 10 20 crater;rim").await;
+    let state = bob.wait_for(|s| waypoints(s).len() == 1).await;
+    let (first, rest) = waypoints(&state).remove(0);
+    // Labelled like the leaderboard, never with the address.
+    let id = db::player_id(token).unwrap();
+    assert_eq!(rest, format!("10.00,20.00,Player {}>craterrim", &id[..4]));
+
+    // Other players can't take it down.
+    bob.send(&format!("unmark {first}")).await;
+    alice.send("mark 1 1").await;
+    alice.send("mark 2 2 ridge").await;
+    let state = bob.wait_for(|s| waypoints(s).iter().any(|(_, w)| w.ends_with(">ridge"))).await;
+    let marks = waypoints(&state);
+    assert_eq!(marks.len(), 2, "the oldest made way: {state}");
+    assert!(marks.iter().all(|&(id, _)| id != first));
+    assert!(marks.iter().any(|(id, w)| w.ends_with(&format!(">Waypoint {id}"))));
+
+    alice.send(&format!("unmark {}", marks[0].0)).await;
+    bob.wait_for(|s| waypoints(s).len() == 1).await;
+
+    alice.send("mark 99999 0 nowhere").await;
+    alice.send("chat done").await;
+    let state = bob.wait_for(|s| s.contains(">done")).await;
+    assert_eq!(waypoints(&state).len(), 1);
+
+    bob.send("mark 3 3 mine").await;
+    let mine = format!(",{}>mine", bob.name);
+    assert!(bob.name.starts_with("Guest "), "{}", bob.name);
+    alice.wait_for(|s| waypoints(s).iter().any(|(_, w)| w.ends_with(&mine))).await;
+
+    // Leaving takes a player's markers with them.
+    drop(alice);
+    bob.wait_for(|s| matches!(&waypoints(s)[..], [(_, only)] if only.ends_with(&mine))).await;
+}
+
+#[tokio::test]
+async fn waypoints_expire() {
+    let server = spawn_server_with(Config { waypoint_ttl: Duration::from_millis(500), ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+
+    alice.send("mark 5 5 here").await;
+    alice.wait_for(|s| waypoints(s).len() == 1).await;
+    alice.wait_for(|s| waypoints(s).is_empty()).await;
+}
+
+#[tokio::test]
+async fn players_are_balanced_into_colored_teams() {
+    let server = spawn_server_with(Config { teams: parse_teams(TEAMS).unwrap(), ..Config::default() }).await;
+    let alice = Client::connect(server).await;
+    let bob = Client::connect(server).await;
+    let mut carol = Client::connect_to(format!("ws://{server}/ws?team=blue")).await;
+
+    assert_eq!(field(&alice.init_state, "Team"), Some("red"));
+    assert_eq!(field(&alice.init_state, "Color"), Some("ff4040"));
+    assert_eq!(field(&bob.init_state, "Team"), Some("blue"));
+    // Carol asked for the bigger team and gets a shade of its color.
+    assert_eq!(field(&carol.init_state, "Team"), Some("blue"));
+    assert_eq!(field(&carol.init_state, "Color"), Some("3060bf"));
+
+    let state = carol.wait_for(|s| field(s, "Players") == Some("2")).await;
+    assert_eq!(field(&state, &format!("C[{}]", alice.name)), Some("ff4040,red"));
+    assert_eq!(field(&state, &format!("C[{}]", bob.name)), Some("4080ff,blue"));
+    assert_eq!(field(&state, "TeamScore[blue]"), Some("4080ff,0,2"));
+}
+
+#[tokio::test]
+async fn team_chat_stays_in_the_team() {
+    let server = spawn_server_with(Config { teams: parse_teams(TEAMS).unwrap(), ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+    let mut carol = Client::connect_to(format!("ws://{server}/ws?team=red")).await;
+
+    carol.send("teamchat flank left").await;
+    let expected = format!("{} (team)>flank left", carol.name);
+    alice.wait_for(|s| s.contains(&expected)).await;
+
+    carol.send("chat done").await;
+    let state = bob.wait_for(|s| s.contains(">done")).await;
+    assert!(!state.contains("flank left"), "{state}");
+}
+
+#[tokio::test]
+async fn team_chat_does_not_push_out_public_chat() {
+    let server = spawn_server_with(Config { teams: parse_teams(TEAMS).unwrap(), ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+
+    alice.send("chat hello everyone").await;
+    for i in 0..20 {
+        alice.send(&format!("teamchat note {i}")).await;
+    }
+    let state = alice.wait_for(|s| s.contains(">note 19")).await;
+    assert!(state.contains(">hello everyone"), "{state}");
+    assert!(!state.contains(">note 4;"), "{state}");
+}
+
+#[tokio::test]
+async fn disconnect_removes_player() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    let key = format!("P[{}]", alice.name);
+    alice.send("move 1 1").await;
+    bob.wait_for(|s| field(s, &key).is_some()).await;
+
+    alice.ws.close(None).await.unwrap();
+    let state = bob
+        .wait_for(|s| field(s, "Players") == Some("0"))
+        .await;
+    assert!(field(&state, &key).is_none());
+}
+
+#[tokio::test]
+async fn messages_apply_in_send_order() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    for i in 0..5 {
+        alice.send(&format!("chat msg{i}")).await;
+        alice.send(&format!("move {i} {i}")).await;
+    }
+    let key = format!("P[{}]", alice.name);
+    let state = bob
+        .wait_for(|s| s.contains(">msg4") && field(s, &key) == Some("4.00,4.00"))
+        .await;
+    let (_, chat) = state.split_once(";Chat:").unwrap();
+    let order: Vec<&str> = chat.split(';').map(|m| m.rsplit('>').next().unwrap()).collect();
+    assert_eq!(order, ["msg0", "msg1", "msg2", "msg3", "msg4"]);
+}
+
+#[tokio::test]
+async fn telemetry_places_balloon_in_world() {
+    let server = spawn_server().await;
+    let mut client = Client::connect(server).await;
+    assert!(field(&client.init_state, "Balloon").is_none());
+
+    let status = post_telemetry(server, r#"{"latitude": 45.0, "longitude": 9.0, "altitude": 1000.0, "signal": -80}"#).await;
+    assert_eq!(status, 204);
+
+    // Without an anchor the first fix is the world origin.
+    let state = client.wait_for(|s| field(s, "Balloon").is_some()).await;
+    assert_eq!(field(&state, "Balloon"), Some("0.00,0.00,0.00"));
+    assert_eq!(field(&state, "BalloonHeight"), Some("3281"));
+    assert_eq!(field(&state, "Signal"), Some("-80"));
+
+    assert_eq!(post_telemetry(server, "not json").await, 400);
+}
+
+#[tokio::test]
+async fn telemetry_needs_the_station_token() {
+    let server = spawn_server().await;
+    let mut client = Client::connect(server).await;
+    let reading = br#"{"altitude": 1000.0}"#;
+    assert_eq!(request(server, "POST", "/api/telemetry", reading).await.0, 401);
+    let wrong = "Authorization: Bearer guess\r\n";
+    assert_eq!(request_with(server, "POST", "/api/telemetry", wrong, reading).await.0, 401);
+    assert_eq!(post_telemetry(server, r#"{"altitude": 1000.0}"#).await, 204);
+    client.wait_for(|s| field(s, "BalloonHeight") == Some("3281")).await;
+
+    // Without a configured token nobody can feed telemetry.
+    let server = spawn_server_with(Config::default()).await;
+    let station = format!("Authorization: Bearer {STATION_TOKEN}\r\n");
+    assert_eq!(request_with(server, "POST", "/api/telemetry", &station, reading).await.0, 404);
+}
+
+#[tokio::test]
+async fn weather_follows_the_readings() {
+    let server = spawn_server().await;
+    let mut client = Client::connect(server).await;
+    assert_eq!(field(&client.init_state, "Weather"), Some("0.00,0.30,2.0"));
+
+    // A cold ground reading, taken at sea level and then 1 km up.
+    assert_eq!(post_telemetry(server, r#"{"outside_temperature": -4.0, "altitude": 0.0}"#).await, 204);
+    client.wait_for(|s| field(s, "Weather") == Some("0.85,0.30,2.0")).await;
+    assert_eq!(post_telemetry(server, r#"{"outside_temperature": -4.0, "altitude": 1000.0}"#).await, 204);
+    client.wait_for(|s| field(s, "Weather") == Some("0.40,0.30,2.0")).await;
+}
+
+#[tokio::test]
+async fn unresponsive_client_times_out() {
+    let server = spawn_server_with(Config {
+        heartbeat_interval: Duration::from_millis(100),
+        heartbeat_timeout: Duration::from_millis(500),
+        idle_timeout: Duration::ZERO,
+        ..Config::default()
+    })
+    .await;
+    // Alice stops reading after joining, so she never answers pings.
+    let alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    let expected = format!("Server>{} left (timeout)", alice.name);
+    let state = bob.wait_for(|s| s.contains(&expected)).await;
+    assert_eq!(field(&state, "Players"), Some("0"));
+}
+
+#[tokio::test]
+async fn idle_player_is_removed() {
+    let server = spawn_server_with(Config {
+        idle_timeout: Duration::from_millis(300),
+        ..Config::default()
+    })
+    .await;
+    let alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    // Bob keeps playing while Alice idles.
+    let expected = format!("Server>{} left (idle timeout)", alice.name);
+    let mut state = None;
+    for i in 0..30 {
+        bob.send(&format!("move {i} 1")).await;
+        let wait = bob.wait_for(|s| s.contains(&expected));
+        if let Ok(found) = tokio::time::timeout(Duration::from_millis(100), wait).await {
+            state = Some(found);
+            break;
+        }
+    }
+    let state = state.expect("idle player was not removed");
+    assert_eq!(field(&state, "Players"), Some("0"));
+}
+
+#[tokio::test]
+async fn oversized_frame_closes_connection() {
+    let server = spawn_server_with(Config { max_frame_bytes: 1024, ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect(server).await;
+
+    alice.send(&format!("chat {}", "x".repeat(4096))).await;
+    let closed = async {
+        while let Some(Ok(msg)) = alice.ws.next().await {
+            if let Message::Close(_) = msg {
+                break;
+            }
+        }
+    };
+    tokio::time::timeout(TIMEOUT, closed).await.expect("connection left open");
+
+    let state = bob.wait_for(|s| field(s, "Players") == Some("0")).await;
+    assert!(!state.contains("xxxx"));
+
+    let (status, body) = request(server, "GET", "/api/metrics", b"").await;
+    assert_eq!(status, 200);
+    assert!(body.contains(r#""oversized_frames":1"#), "metrics: {body}");
+}
+
+#[tokio::test]
+async fn admins_kick_players() {
+    let (server, game) = spawn_game(Config::default()).await;
+    let mut alice = Client::connect(server).await;
+    let mut bob = Client::connect_to(format!("ws://{server}/ws?player=bob-0123456789abcdef")).await;
+
+    let names: Vec<String> = game.players().await.unwrap().into_iter().map(|p| p.name).collect();
+    assert_eq!(names.len(), 2);
+    assert!(names.contains(&alice.name));
+    assert_eq!(game.kick("nobody".into(), None).await, None);
+
+    assert_eq!(game.kick(alice.name.clone(), Some("spamming".into())).await, Some(alice.addr));
+    let closed = async {
+        while let Some(Ok(msg)) = alice.ws.next().await {
+            if let Message::Close(frame) = msg {
+                return frame.map(|f| f.reason.to_string());
+            }
+        }
+        None
+    };
+    let reason = tokio::time::timeout(TIMEOUT, closed).await.expect("connection left open");
+    assert_eq!(reason.as_deref(), Some("Kicked"));
+
+    let expected = format!("Server>{} left (kicked: spamming)", alice.name);
+    let state = bob.wait_for(|s| s.contains(&expected)).await;
+    assert_eq!(field(&state, "Players"), Some("0"));
+    assert!(!state.contains(&alice.addr.ip().to_string()), "{state}");
+
+    game.announce("Landing in five minutes".into()).await;
+    bob.wait_for(|s| s.contains("Server>Landing in five minutes")).await;
+    assert_eq!(game.save().await, Some(1));
+    assert_eq!(game.stats().await.unwrap().players, 1);
+
+    let id = db::player_id("bob-0123456789abcdef").unwrap();
+    assert_eq!(game.kick(id, None).await, Some(bob.addr));
+}
+
+/// Attempts an upgrade with an optional `Origin`, returning the HTTP status
+/// of a refused handshake or 101 on success.
+async fn upgrade_status(server: SocketAddr, origin: Option<&str>) -> u16 {
+    upgrade_request_status(server, "/ws", origin).await
+}
+
+async fn upgrade_status_at(server: SocketAddr, path: &str) -> u16 {
+    upgrade_request_status(server, path, None).await
+}
+
+async fn upgrade_request_status(server: SocketAddr, path: &str, origin: Option<&str>) -> u16 {
+    let mut request = format!("ws://{server}{path}").into_client_request().unwrap();
+    if let Some(origin) = origin {
+        request.headers_mut().insert("Origin", origin.parse().unwrap());
+    }
+    match connect_async(request).await {
+        Ok((_ws, response)) => response.status().as_u16(),
+        Err(tungstenite::Error::Http(response)) => response.status().as_u16(),
+        Err(e) => panic!("handshake failed: {e}"),
+    }
+}
+
+#[tokio::test]
+async fn disallowed_origin_is_rejected() {
+    let server = spawn_server_with(Config {
+        allowed_origins: vec!["https://apex.example".to_string()],
+        ..Config::default()
+    })
+    .await;
+
+    assert_eq!(upgrade_status(server, Some("https://evil.example")).await, 403);
+    assert_eq!(upgrade_status(server, Some("https://apex.example")).await, 101);
+
+    let (_, body) = request(server, "GET", "/api/metrics", b"").await;
+    assert!(body.contains(r#""rejected_connections":1"#), "metrics: {body}");
+}
+
+#[tokio::test]
+async fn connections_per_ip_are_capped() {
+    let server = spawn_server_with(Config { max_connections_per_ip: 2, ..Config::default() }).await;
+    let alice = Client::connect(server).await;
+    let _bob = Client::connect(server).await;
+    assert_eq!(upgrade_status(server, None).await, 429);
+
+    // Closing a connection frees its slot.
+    drop(alice);
+    let mut admitted = false;
+    for _ in 0..30 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if upgrade_status(server, None).await == 101 {
+            admitted = true;
+            break;
+        }
+    }
+    assert!(admitted, "slot was never released");
+}
+
+#[tokio::test]
+async fn connection_rate_is_limited() {
+    let server = spawn_server_with(Config { connections_per_minute: 2, ..Config::default() }).await;
+    assert_eq!(upgrade_status(server, None).await, 101);
+    assert_eq!(upgrade_status(server, None).await, 101);
+    assert_eq!(upgrade_status(server, None).await, 429);
+}
+
+#[tokio::test]
+async fn flight_events_are_announced_and_recorded() {
+    let server = spawn_server().await;
+    let mut alice = Client::connect(server).await;
+
+    for altitude in [100.0, 200.0, 5000.0, 4000.0] {
+        post_telemetry(server, &format!(r#"{{"altitude": {altitude}}}"#)).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let state = alice.wait_for(|s| s.contains("Burst at 4000 m")).await;
+    assert!(state.contains("Server>Launch detected at 200 m"));
+    assert!(state.contains("Server>Maximum altitude 5000 m"));
+    assert_eq!(field(&state, "Phase"), Some("burst"));
+
+    let mut body = String::new();
+    for _ in 0..20 {
+        body = request(server, "GET", "/api/events", b"").await.1;
+        if body.contains("burst") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let kinds: Vec<String> = serde_json::from_str::<Vec<serde_json::Value>>(&body)
+        .unwrap()
+        .iter()
+        .map(|e| e["kind"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(kinds, ["launch", "max_altitude", "burst"], "{body}");
+
+    let events: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    let launched_at = events[0]["at_ms"].as_i64().unwrap();
+    let body = request(server, "GET", &format!("/api/events?since={launched_at}&limit=1"), b"").await.1;
+    assert!(body.contains("max_altitude") && !body.contains("burst"), "{body}");
+}
+
+/// Opens a server-sent events stream, returning once the headers are in.
+async fn subscribe_events(server: SocketAddr, path: &str, headers: &str) -> TcpStream {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = TcpStream::connect(server).await.unwrap();
+    stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: {server}\r\n{headers}\r\n").as_bytes()).await.unwrap();
+    let mut head = [0u8; 512];
+    let n = stream.read(&mut head).await.unwrap();
+    assert!(String::from_utf8_lossy(&head[..n]).contains("text/event-stream"));
+    stream
+}
+
+/// Reads an event stream until `needle` shows up, returning everything read.
+async fn read_events_until(stream: &mut TcpStream, needle: &str) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let mut streamed = String::new();
+    tokio::time::timeout(TIMEOUT, async {
+        let mut buf = [0u8; 1024];
+        while !streamed.contains(needle) {
+            let n = stream.read(&mut buf).await.unwrap();
+            streamed.push_str(&String::from_utf8_lossy(&buf[..n]));
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("{needle:?} never streamed"));
+    streamed
+}
+
+#[tokio::test]
+async fn telemetry_alerts_fire_and_clear() {
+    let rules: Vec<AlertRule> = serde_json::from_str(
+        r#"[{"name": "cold payload", "field": "temperature", "when": "below", "value": 0, "hysteresis": 2}]"#,
+    )
+    .unwrap();
+    let server = spawn_server_with(Config { alert_rules: rules, station_token: Some(STATION_TOKEN.into()), ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+
+    let mut events = subscribe_events(server, "/api/alerts/stream", "").await;
+
+    for temperature in [1.0, -1.0, 1.0, 3.0] {
+        post_telemetry(server, &format!(r#"{{"temperature": {temperature}}}"#)).await;
+    }
+    let state = alice.wait_for(|s| s.contains("Resolved: cold payload cleared")).await;
+    assert_eq!(state.matches("Alert: cold payload: temperature -1.0 below 0").count(), 1);
+
+    let streamed = read_events_until(&mut events, "event: cleared").await;
+    assert!(streamed.contains("event: alert"), "{streamed}");
+
+    let mut body = String::new();
+    for _ in 0..20 {
+        body = request(server, "GET", "/api/alerts", b"").await.1;
+        if body.contains("cleared") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let history: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    let firing: Vec<bool> = history.iter().map(|a| a["firing"].as_bool().unwrap()).collect();
+    assert_eq!(firing, [false, true], "newest first: {body}");
+}
+
+#[tokio::test]
+async fn chat_is_bridged_to_the_mesh() {
+    let server = spawn_server_with(Config { bridge_token: Some("bridge-secret".into()), ..Config::default() }).await;
+    let mut alice = Client::connect(server).await;
+    let me = alice.name.clone();
+    let bridge = "Authorization: Bearer bridge-secret\r\n";
+    let post = |body: &'static str| request_with(server, "POST", "/api/mesh/messages", bridge, body.as_bytes());
+
+    let hello = r#"{"from": "Gonzo;", "text": "hello from the field"}"#;
+    assert_eq!(post_json(server, "/api/mesh/messages", hello).await, 401);
+    assert_eq!(post(hello).await.0, 204);
+    alice.wait_for(|s| s.contains("Gonzo (mesh)>hello from the field")).await;
+    assert_eq!(post(r#"{"from": "Gonzo", "text": " "}"#).await.0, 400);
+    assert_eq!(request(server, "GET", "/api/mesh/outbox", b"").await.0, 401);
+
+    // Nothing was transmitted without a bridge listening, so the radio
+    // isn't held busy for it.
+    alice.send("chat !mesh anyone there").await;
+    alice.wait_for(|s| s.contains("anyone there")).await;
+    let mut outbox = subscribe_events(server, "/api/mesh/outbox", bridge).await;
+    alice.send("chat !mesh heading north").await;
+    let streamed = read_events_until(&mut outbox, "heading north").await;
+    assert!(streamed.contains(&format!(r#"{{"from":"{me}","text":"heading north"}}"#)), "{streamed}");
+
+    // The duty cycle keeps the radio quiet for a while after each message.
+    alice.send("chat !mesh again").await;
+    alice.wait_for(|s| s.contains("Mesh radio busy")).await;
+}
+
+#[tokio::test]
+async fn mesh_bridge_is_off_without_a_token() {
+    let server = spawn_server().await;
+    assert_eq!(post_json(server, "/api/mesh/messages", r#"{"from": "Gonzo", "text": "hi"}"#).await, 404);
+    assert_eq!(request(server, "GET", "/api/mesh/outbox", b"").await.0, 404);
+}
+
+#[tokio::test]
+async fn admins_set_and_freeze_the_world_clock() {
+    let config = Config { admin_token: Some("secret".into()), clock_mode: ClockMode::Mission, ..Config::default() };
+    let server = spawn_server_with(config).await;
+    let mut client = Client::connect(server).await;
+    let time = field(&client.init_state, "Time").unwrap();
+    assert!(time.starts_with("21600.0,0,"), "mission clock waits for launch: {time}");
+
+    let change = br#"{"time":"18:30","frozen":true}"#;
+    assert_eq!(request(server, "PUT", "/api/admin/time", change).await.0, 401);
+    let wrong = "Authorization: Bearer guess\r\n";
+    assert_eq!(request_with(server, "PUT", "/api/admin/time", wrong, change).await.0, 401);
+    let admin = "Authorization: Bearer secret\r\n";
+    let bad_speed = br#"{"speed":-1}"#;
+    assert_eq!(request_with(server, "PUT", "/api/admin/time", admin, bad_speed).await.0, 400);
+
+    let (status, body) = request_with(server, "PUT", "/api/admin/time", admin, change).await;
+    assert_eq!(status, 200, "{body}");
+    assert!(body.contains(r#""time":"18:30:00""#), "{body}");
+    let state = client.wait_for(|s| s.contains("Server>Time set to 18:30:00, frozen")).await;
+    assert!(field(&state, "Time").unwrap().starts_with("66600.0,0,"));
+
+    let (status, body) = request(server, "GET", "/api/time", b"").await;
+    assert_eq!(status, 200);
+    assert!(body.contains(r#""frozen":true"#), "{body}");
+}
+
+#[tokio::test]
+async fn admin_api_is_off_without_a_token() {
+    let server = spawn_server().await;
+    let client = Client::connect(server).await;
+    let time = field(&client.init_state, "Time").unwrap();
+    assert_eq!(time.split(',').nth(1), Some("1"), "real time runs at 1x");
+    assert!(field(&client.init_state, "Now").is_some(), "the first snapshot carries the server clock");
+
+    let admin = "Authorization: Bearer anything\r\n";
+    assert_eq!(request_with(server, "PUT", "/api/admin/time", admin, br#"{"frozen":true}"#).await.0, 404);
+}
+
+#[tokio::test]
+async fn spectators_watch_without_playing() {
+    let server = spawn_server_with(Config { max_spectators: 1, station_token: Some(STATION_TOKEN.into()), ..Config::default() }).await;
+    let mut spectator = Client::spectate(server).await;
+    assert!(field(&spectator.init_state, "Spawn").is_none());
+    assert_eq!(field(&spectator.init_state, "Players"), Some("0"));
+
+    let alice = Client::connect(server).await;
+    let me = alice.name.clone();
+    assert_eq!(field(&alice.init_state, "Players"), Some("0"), "spectator counted as a player");
+    assert!(!alice.init_state.contains("P["), "spectator shown as a player");
+    spectator.wait_for(|s| field(s, "Players") == Some("1") && s.contains(&format!("P[{me}]"))).await;
+
+    // Spectators can't act, only follow someone.
+    spectator.send("chat hi").await;
+    spectator.send(&format!("follow {me}")).await;
+    let state = spectator.wait_for(|s| field(s, "Follow").is_some()).await;
+    assert!(field(&state, "Follow").unwrap().starts_with(&format!("{me},")));
+    assert!(!state.contains(">hi"));
+
+    post_telemetry(server, r#"{"altitude": 1234.0}"#).await;
+    let state = spectator.wait_for(|s| field(s, "Telemetry").is_some()).await;
+    assert!(field(&state, "Telemetry").unwrap().contains(r#""altitude":1234.0"#));
+
+    assert_eq!(upgrade_status_at(server, "/ws?spectator=true").await, 503);
+    assert_eq!(upgrade_status_at(server, "/ws").await, 101, "players have their own limit");
+}
+
+#[tokio::test]
+async fn frontend_is_served_from_the_assets_dir() {
+    let dir = std::env::temp_dir().join(format!("apex-assets-test-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("_app/immutable")).unwrap();
+    std::fs::write(dir.join("index.html"), "<h1>apex</h1>").unwrap();
+    std::fs::write(dir.join("_app/immutable/app.js"), "plain").unwrap();
+    std::fs::write(dir.join("_app/immutable/app.js.gz"), "gzipped").unwrap();
+    let server = spawn_server_with(Config { assets_dir: Some(dir.clone()), ..Config::default() }).await;
+
+    let (status, body) = request(server, "GET", "/", b"").await;
+    assert_eq!(status, 200);
+    assert_eq!(body, "<h1>apex</h1>");
+
+    let (status, body) = request_with(server, "GET", "/_app/immutable/app.js", "Accept-Encoding: gzip\r\n", b"").await;
+    assert_eq!((status, body.as_str()), (200, "gzipped"));
+    let head = raw_response_head(server, "/_app/immutable/app.js").await;
+    assert!(head.contains("cache-control: public, max-age=31536000, immutable"), "{head}");
+
+    assert_eq!(request(server, "GET", "/missing.js", b"").await.0, 404);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// Status line and headers of a GET, lowercased.
+async fn raw_response_head(server: SocketAddr, path: &str) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let mut stream = TcpStream::connect(server).await.unwrap();
+    let head = format!("GET {path} HTTP/1.1\r\nHost: {server}\r\nConnection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await.unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response).to_lowercase();
+    response.split_once("\r\n\r\n").map(|(h, _)| h.to_string()).unwrap_or(response)
+}
+
+#[tokio::test]
+async fn uploaded_image_is_stored_and_announced() {
+    let dir = std::env::temp_dir().join(format!("apex-gallery-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let server = spawn_server_with(Config { gallery_dir: dir.clone(), station_token: Some(STATION_TOKEN.into()), ..Config::default() }).await;
+    let mut client = Client::connect(server).await;
+
+    // A payload straight out of the codec.
+    let input = dir.with_extension("png");
+    let compressed = dir.with_extension("bin");
+    image::RgbImage::from_pixel(32, 32, image::Rgb([200, 40, 40])).save(&input).unwrap();
+    image_compression::compress_image(input.to_str().unwrap(), compressed.to_str().unwrap()).unwrap();
+    let payload = std::fs::read(&compressed).unwrap();
+
+    post_telemetry(server, r#"{"latitude": 45.0, "longitude": 9.0, "altitude": 1000.0}"#).await;
+    assert_eq!(request(server, "POST", "/api/images", &payload).await.0, 401);
+    let station = format!("Authorization: Bearer {STATION_TOKEN}\r\n");
+    let (status, body) = request_with(server, "POST", "/api/images", &station, &payload).await;
+    assert_eq!(status, 201, "{body}");
+    assert!(body.contains(r#""id":1"#) && body.contains(r#""colour":false"#), "{body}");
+    assert!(body.contains(r#""altitude":1000.0"#), "{body}");
+
+    let state = client.wait_for(|s| field(s, "Image") == Some("1")).await;
+    assert!(state.contains("Server>New balloon image #1"));
+
+    let palette = "c82828,000000,000000,000000,000000,000000,000000,000000";
+    let (status, body) = request_with(server, "POST", &format!("/api/images?palette={palette}"), &station, &payload).await;
+    assert_eq!(status, 201, "{body}");
+    assert!(body.contains(r#""colour":true"#), "{body}");
+
+    let (status, list) = request(server, "GET", "/api/images", b"").await;
+    assert_eq!(status, 200);
+    assert!(list.find(r#""id":2"#) < list.find(r#""id":1"#), "newest first: {list}");
+
+    let (status, png) = request(server, "GET", "/api/images/2", b"").await;
+    assert_eq!(status, 200);
+    assert!(png.starts_with("\u{fffd}PNG"));
+    assert_eq!(request(server, "GET", "/api/images/9", b"").await.0, 404);
+
+    assert_eq!(request_with(server, "POST", "/api/images", &station, b"not zlib").await.0, 400);
+    assert_eq!(request_with(server, "POST", "/api/images?palette=nope", &station, &payload).await.0, 400);
+}
+
+#[tokio::test]
+async fn landing_zone_hunt_is_scored_and_persisted() {
+    let server = spawn_server_with(Config {
+        geo_anchor: Some("45.0,9.0,0.0".parse().unwrap()),
+        station_token: Some(STATION_TOKEN.into()),
+        teams: parse_teams(TEAMS).unwrap(),
+        ..Config::default()
+    })
+    .await;
+    let token = "alice-0123456789abcdef";
+    let mut alice = Client::connect_to(format!("ws://{server}/ws?player={token}")).await;
+
+    // Two fixes falling fast straight down put the landing zone at the origin.
+    post_telemetry(server, r#"{"latitude": 45.0, "longitude": 9.0, "altitude": 1000.0}"#).await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    post_telemetry(server, r#"{"latitude": 45.0, "longitude": 9.0, "altitude": 900.0}"#).await;
+
+    let state = alice.wait_for(|s| field(s, "Objective").is_some()).await;
+    assert!(state.contains("Race to the landing zone (round 1)"));
+    let objective: Vec<f32> = field(&state, "Objective").unwrap().split(',').map(|v| v.parse().unwrap()).collect();
+    assert_eq!(objective[..2], [0.0, 0.0]);
+
+    alice.send("move 0.5 0.5").await;
+    let me = alice.name.clone();
+    alice.wait_for(|s| s.contains(&format!("Server>{me} reached the landing zone (1st)"))).await;
+    // Everyone online has arrived, so the round closes on the next tick and
+    // the new standings come back to the winner.
+    let state = alice.wait_for(|s| field(s, "Rank") == Some("1")).await;
+    assert!(state.contains("Round 1 over"));
+    assert!(field(&state, "Objective").is_none());
+    // Alice is on the first team, which gets her points.
+    assert!(state.contains("Server>Team scores: red 100, blue 0"), "{state}");
+    assert_eq!(field(&state, "TeamScore[red]"), Some("ff4040,100,1"));
+
+    // Results are kept under a hash of the token, never the address.
+    let id = db::player_id(token).unwrap();
+    let mut body = String::new();
+    for _ in 0..20 {
+        body = request(server, "GET", "/api/hunt/leaderboard", b"").await.1;
+        if body.contains(&id) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(body.contains(&format!(r#""player":"{id}","rounds":1,"wins":1,"best_place":1,"total_score":100"#)), "{body}");
+    assert!(!body.contains(&me) && !body.contains(token), "{body}");
+}
+
+#[tokio::test]
+async fn session_stats_reach_leaderboard() {
+    let (server, game) = spawn_game(Config::default()).await;
+    let token = "alice-0123456789abcdef";
+    let mut alice = Client::connect_to(format!("ws://{server}/ws?player={token}")).await;
+    let _guest = Client::connect(server).await;
+    let me = db::player_id(token).unwrap();
+    let spawn = alice.init_state.clone();
+    let (x, z) = field(&spawn, "Spawn").unwrap().split_once(',').unwrap();
+    let (x, z): (f32, f32) = (x.parse().unwrap(), z.parse().unwrap());
+
+    alice.send(&format!("move {} {}", x + 3.0, z + 4.0)).await;
+    alice.send("chat hello").await;
+    alice.wait_for(|s| s.contains("hello")).await;
+
+    // Saving returns once the sessions are written; the guest has none.
+    assert_eq!(game.save().await, Some(1));
+    let body = request(server, "GET", "/api/leaderboard?sort=distance&window=24h", b"").await.1;
+    let rows: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(rows.as_array().unwrap().len(), 1, "{body}");
+    let row = &rows[0];
+    assert_eq!(row["player"], me.as_str(), "{body}");
+    // Trees may push the move aside a little, but it was about 5 units.
+    assert!((row["distance"].as_f64().unwrap() - 5.0).abs() < 2.0, "{body}");
+    assert_eq!(row["chats"], 1, "{body}");
+
+    // Leaving adds the rest of the session.
+    alice.send("chat bye").await;
+    alice.wait_for(|s| s.contains("bye")).await;
+    drop(alice);
+    let mut chats = 1;
+    for _ in 0..20 {
+        let body = request(server, "GET", "/api/leaderboard", b"").await.1;
+        chats = serde_json::from_str::<serde_json::Value>(&body).unwrap()[0]["chats"].as_i64().unwrap();
+        if chats == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(chats, 2);
+
+    assert_eq!(request(server, "GET", "/api/leaderboard?sort=height", b"").await.0, 400);
+    assert_eq!(request(server, "GET", "/api/leaderboard?window=1y", b"").await.0, 400);
+}
@@ -1,15 +1,30 @@
-import { writable, readable } from 'svelte/store';
+import { writable, readable, get } from 'svelte/store';
 import { browser } from '$app/environment';
 
 let socket = null;
+let lastMove = null; // { x, z } last sent, where new waypoints go
 
 const _isConnected = writable(false);
 const _seed = writable(null); 
+const _spawnPoint = writable(null); // { x, z } chosen by the server on dry land
+const _worldTrees = writable(null); // { x, z, rotation, scale }[] generated by the server
 const _balloonHeight = writable(0);
+const _balloonPosition = writable(null); // { x, y, z } in world units, mapped from GPS by the server
+const _objective = writable(null); // { x, z, secondsLeft } while a landing-zone hunt round runs
+const _latestImage = writable(null); // URL of the newest balloon picture in the server gallery
+const _flightPhase = writable(null); // on_pad, ascent, float, burst, descent or landed
+const _worldTime = writable(null); // { seconds, speed, atMs }: the server's time of day at Unix time atMs, advancing at speed
+// The server's clock as of performance.now() value `at`, from the first snapshot.
+let serverClock = null;
+const _weather = writable(null); // { fog, clouds, wind }: fog and clouds 0..1, wind in m/s
+const _rank = writable(null); // All-time leaderboard place, once this player has one
+const _team = writable(null); // { name, color } this player is on, color as 0xrrggbb
+const _teamScores = writable([]); // { name, color, score, players }[] in the server's order
 const _signalStrength = writable(0);
 const _avgPing = writable(0.0);
 const _playerCount = writable(0);
 const _otherPlayers = writable({}); 
+const _waypoints = writable([]); // { id, x, z, owner, name }[] placed by players, oldest first
 const _chatMessages = writable([]); // Store for chat messages { sender: string, message: string }[]
 const _lastError = writable(null);
 
@@ -20,9 +35,42 @@ export const seed = readable(_seed.value, (set) => {
     _seed.subscribe(set); 
     return () => { }; 
 });
+export const spawnPoint = readable(_spawnPoint.value, (set) => {
+    return _spawnPoint.subscribe(set);
+});
+export const worldTrees = readable(_worldTrees.value, (set) => {
+    return _worldTrees.subscribe(set);
+});
 export const balloonHeight = readable(_balloonHeight.value, (set) => {
     return _balloonHeight.subscribe(set);
 });
+export const balloonPosition = readable(_balloonPosition.value, (set) => {
+    return _balloonPosition.subscribe(set);
+});
+export const objective = readable(_objective.value, (set) => {
+    return _objective.subscribe(set);
+});
+export const latestImage = readable(_latestImage.value, (set) => {
+    return _latestImage.subscribe(set);
+});
+export const flightPhase = readable(_flightPhase.value, (set) => {
+    return _flightPhase.subscribe(set);
+});
+export const worldTime = readable(_worldTime.value, (set) => {
+    return _worldTime.subscribe(set);
+});
+export const weather = readable(_weather.value, (set) => {
+    return _weather.subscribe(set);
+});
+export const rank = readable(_rank.value, (set) => {
+    return _rank.subscribe(set);
+});
+export const team = readable(_team.value, (set) => {
+    return _team.subscribe(set);
+});
+export const teamScores = readable(_teamScores.value, (set) => {
+    return _teamScores.subscribe(set);
+});
 export const signalStrength = readable(_signalStrength.value, (set) => {
     return _signalStrength.subscribe(set);
 });
@@ -35,6 +83,9 @@ export const playerCount = readable(_playerCount.value, (set) => {
 export const otherPlayers = readable(_otherPlayers.value, (set) => {
     return _otherPlayers.subscribe(set);
 });
+export const waypoints = readable(_waypoints.value, (set) => {
+    return _waypoints.subscribe(set);
+});
 export const chatMessages = readable(_chatMessages.value, (set) => { // Export readable chat store
     return _chatMessages.subscribe(set);
 });
@@ -43,6 +94,17 @@ export const lastError = readable(_lastError.value, (set) => {
 });
 
 
+function parseTreesMessage(message) {
+    const trees = message.substring('Trees:'.length).split(';')
+        .map(part => {
+            const [x, z, rotation, scale] = part.split(',').map(parseFloat);
+            return { x, z, rotation, scale };
+        })
+        .filter(t => !isNaN(t.x) && !isNaN(t.z) && !isNaN(t.rotation) && !isNaN(t.scale));
+    console.log("[networkStore] Received trees:", trees.length);
+    _worldTrees.set(trees);
+}
+
 function parseServerMessage(message) {
     let rawChatData = '';
     let otherData = message;
@@ -60,16 +122,22 @@ function parseServerMessage(message) {
     const parts = otherData.split(';');
     const data = {};
     const playersData = {};
+    const waypointsData = [];
+    const teamsData = [];
+    let teamName = null;
+    let teamColor = null;
+    let sawObjective = false;
 
     parts.forEach(part => {
         if (!part) return; // Skip empty parts
         let key = '';
         let value = '';
 
-        const playerSeparatorIndex = part.indexOf(']:');
+        // Names may contain ']:' themselves, values never do.
+        const playerSeparatorIndex = part.lastIndexOf(']:');
 
         // Note: Removed chatSeparatorIndex check here as chat data is already extracted
-        if (part.startsWith('P[') && playerSeparatorIndex !== -1) {
+        if ((part.startsWith('P[') || part.startsWith('C[')) && playerSeparatorIndex !== -1) {
             key = part.substring(0, playerSeparatorIndex + 1);
             value = part.substring(playerSeparatorIndex + 2);
         } else {
@@ -94,9 +162,67 @@ function parseServerMessage(message) {
                 _seed.set(value);
                 data.seed = value;
                 break;
+            case 'Spawn': {
+                const [x, z] = value.split(',').map(parseFloat);
+                if (!isNaN(x) && !isNaN(z)) {
+                    _spawnPoint.set({ x, z });
+                }
+                break;
+            }
             case 'BalloonHeight':
                 _balloonHeight.set(parseInt(value, 10));
                 break;
+            case 'Balloon': {
+                const [x, y, z] = value.split(',').map(parseFloat);
+                if (!isNaN(x) && !isNaN(y) && !isNaN(z)) {
+                    _balloonPosition.set({ x, y, z });
+                }
+                break;
+            }
+            case 'Objective': {
+                const [x, z, secondsLeft] = value.split(',').map(parseFloat);
+                if (!isNaN(x) && !isNaN(z)) {
+                    sawObjective = true;
+                    _objective.set({ x, z, secondsLeft });
+                }
+                break;
+            }
+            case 'Image':
+                _latestImage.set(`/api/images/${parseInt(value, 10)}`);
+                break;
+            case 'Phase':
+                _flightPhase.set(value);
+                break;
+            case 'Now': {
+                const nowMs = parseFloat(value);
+                if (!isNaN(nowMs)) {
+                    serverClock = { nowMs, at: performance.now() };
+                }
+                break;
+            }
+            case 'Time': {
+                const [seconds, speed, atMs] = value.split(',').map(parseFloat);
+                if (!isNaN(seconds) && !isNaN(speed) && !isNaN(atMs)) {
+                    _worldTime.set({ seconds, speed, atMs });
+                }
+                break;
+            }
+            case 'Weather': {
+                const [fog, clouds, wind] = value.split(',').map(parseFloat);
+                if (!isNaN(fog) && !isNaN(clouds) && !isNaN(wind)) {
+                    _weather.set({ fog, clouds, wind });
+                }
+                break;
+            }
+            case 'Rank':
+                _rank.set(parseInt(value, 10));
+                break;
+            case 'Team':
+                teamName = value;
+                break;
+            case 'Color':
+                teamColor = parseInt(value, 16);
+                break;
             case 'Signal':
                 _signalStrength.set(parseInt(value, 10));
                 break;
@@ -121,6 +247,38 @@ function parseServerMessage(message) {
                     } else {
                         console.warn(`[networkStore] Invalid coordinates ${playerId}:`, value);
                     }
+                } else if (key.startsWith('C[') && key.endsWith(']')) {
+                    // Follows the P[] part of the same player: rrggbb[,team]
+                    const playerId = key.substring(2, key.length - 1);
+                    const commaIndex = value.indexOf(',');
+                    const color = parseInt(commaIndex === -1 ? value : value.substring(0, commaIndex), 16);
+                    if (playersData[playerId] && !isNaN(color)) {
+                        playersData[playerId].color = color;
+                        playersData[playerId].team = commaIndex === -1 ? null : value.substring(commaIndex + 1);
+                    }
+                } else if (key.startsWith('TeamScore[') && key.endsWith(']')) {
+                    const [color, score, players] = value.split(',');
+                    teamsData.push({
+                        name: key.substring('TeamScore['.length, key.length - 1),
+                        color: parseInt(color, 16),
+                        score: parseInt(score, 10),
+                        players: parseInt(players, 10)
+                    });
+                } else if (key.startsWith('W[') && key.endsWith(']')) {
+                    // x,z,owner>name: the name may contain commas and '>'.
+                    const id = parseInt(key.substring(2, key.length - 1), 10);
+                    const [x, z] = value.split(',', 2).map(parseFloat);
+                    const label = value.split(',').slice(2).join(',');
+                    const separatorIndex = label.indexOf('>');
+                    if (!isNaN(id) && !isNaN(x) && !isNaN(z) && separatorIndex !== -1) {
+                        waypointsData.push({
+                            id, x, z,
+                            owner: label.substring(0, separatorIndex),
+                            name: label.substring(separatorIndex + 1)
+                        });
+                    } else {
+                        console.warn("[networkStore] Invalid waypoint:", part);
+                    }
                 } else {
                     console.warn("[networkStore] Unhandled key:", key);
                 }
@@ -156,18 +314,51 @@ function parseServerMessage(message) {
 
     console.log("[networkStore] playermaxxing:", JSON.stringify(playersData));
     _otherPlayers.set(playersData);
+    _waypoints.set(waypointsData);
+    _teamScores.set(teamsData);
+    _team.set(teamName === null && teamColor === null ? null : { name: teamName, color: teamColor });
+    if (!sawObjective) {
+        _objective.set(null); // The round ended
+    }
+}
+
+
+// A random secret this browser keeps, so the leaderboards can tell its
+// results apart from everyone else's across visits.
+function playerToken() {
+    let token = localStorage.getItem('apexPlayer');
+    if (!token) {
+        const bytes = crypto.getRandomValues(new Uint8Array(16));
+        token = Array.from(bytes, (b) => b.toString(16).padStart(2, '0')).join('');
+        localStorage.setItem('apexPlayer', token);
+    }
+    return token;
 }
 
+// The server's Unix ms time right now, going by the local monotonic clock
+// since the first snapshot so a skewed system clock doesn't matter.
+export function serverNowMs() {
+    return serverClock ? serverClock.nowMs + (performance.now() - serverClock.at) : Date.now();
+}
 
 export function initializeWebSocket() {
     if (!browser || socket) {
         console.log("Either server|| alr connected");
         return;
     }
-    const wsUrl = `ws://localhost:3000/ws`;
-    //a
-    //const wsUrl = `ws://${window.location.host}/ws`;
-    socket = new WebSocket(wsUrl);
+    // Pages served over HTTPS can only open wss://, which the backend serves
+    // itself when configured with a certificate.
+    const wsUrl = import.meta.env.VITE_WS_URL
+        ?? (window.location.protocol === 'https:'
+            ? `wss://${window.location.host}/ws`
+            : `ws://localhost:3000/ws`);
+    const params = new URLSearchParams({ player: playerToken() });
+    // A ?team=name on the page picks a team; otherwise the server balances.
+    const wantedTeam = new URLSearchParams(window.location.search).get('team');
+    if (wantedTeam) {
+        params.set('team', wantedTeam);
+    }
+    socket = new WebSocket(`${wsUrl}?${params}`);
 
     socket.onopen = () => {
         console.log("We ball");
@@ -176,6 +367,10 @@ export function initializeWebSocket() {
     };
 
     socket.onmessage = (event) => {
+        if (event.data.startsWith('Trees:')) {
+            parseTreesMessage(event.data);
+            return;
+        }
         parseServerMessage(event.data);
     };
 
@@ -190,7 +385,20 @@ export function initializeWebSocket() {
         _isConnected.set(false);
         _chatMessages.set([]); // Clear chat on disconnect
         _otherPlayers.set({});
+        _waypoints.set([]);
+        lastMove = null;
         _seed.set(null);
+        _spawnPoint.set(null);
+        _worldTrees.set(null);
+        _balloonPosition.set(null);
+        _latestImage.set(null);
+        _objective.set(null);
+        _rank.set(null);
+        _team.set(null);
+        _teamScores.set([]);
+        _flightPhase.set(null);
+        _worldTime.set(null);
+        _weather.set(null);
         socket = null;
         
         
@@ -200,6 +408,7 @@ export function initializeWebSocket() {
 export function sendMove(x, z) {
     if (socket && socket.readyState === WebSocket.OPEN) {
         const message = `move ${x.toFixed(2)} ${z.toFixed(2)}`;
+        lastMove = { x, z };
         
         socket.send(message);
     } else {
@@ -217,6 +426,28 @@ export function sendChatMessage(messageContent) {
     }
 }
 
+export function sendTeamChatMessage(messageContent) {
+    if (socket && socket.readyState === WebSocket.OPEN && messageContent.trim()) {
+        socket.send(`teamchat ${messageContent}`);
+    } else {
+        console.warn("Cannot send team chat message. WebSocket not open or message empty.");
+    }
+}
+
+// Marks where the player stands for everyone, optionally named.
+export function placeWaypoint(name = '') {
+    const at = lastMove ?? get(_spawnPoint); // Not moved yet
+    if (socket && socket.readyState === WebSocket.OPEN && at) {
+        socket.send(`mark ${at.x.toFixed(2)} ${at.z.toFixed(2)} ${name.trim()}`.trim());
+    }
+}
+
+export function removeWaypoint(id) {
+    if (socket && socket.readyState === WebSocket.OPEN) {
+        socket.send(`unmark ${id}`);
+    }
+}
+
 export function closeWebSocket() {
     if (socket) {
         socket.close();
@@ -32,7 +32,9 @@ export function getTerrainHeightAt(x, z) {
     return finalHeight;
 }
 
-export function createTerrain(scene, mainSeed) {
+// serverTrees: { x, z, rotation, scale }[] from the backend. When present the
+// local placement below is skipped so every client sees the same forest.
+export function createTerrain(scene, mainSeed, serverTrees = null) {
     const textureLoader = new THREE.TextureLoader();
     const basePath = '/assets/grass0/';
     const grassBaseColor = textureLoader.load(basePath + '4K_BaseColor.jpg');
@@ -72,7 +74,7 @@ export function createTerrain(scene, mainSeed) {
         } else {
             tempColor.set("#FFFFFF");
 
-            if (height >= config.treePlacementThreshold) {
+            if (!serverTrees && height >= config.treePlacementThreshold) {
                 const posSeed = `${placementRngSeedBase}-${x.toFixed(2)}-${z.toFixed(2)}`;
                 const placementRng = createSeededRandom(posSeed);
                 if (placementRng() < config.treePlacementDensity) {
@@ -116,6 +118,11 @@ export function createTerrain(scene, mainSeed) {
     scene.add(terrainMesh);
 
     createWater(scene);
+    if (serverTrees) {
+        for (const tree of serverTrees) {
+            treePositions.push({ x: tree.x, y: getTerrainHeightAt(tree.x, tree.z), z: tree.z, rotation: tree.rotation, scale: tree.scale });
+        }
+    }
     createTrees(scene, treePositions, mainSeed);
 
     return { terrainMesh, waterMesh, trunkInstanceMesh, leavesInstanceMesh };
@@ -161,8 +168,8 @@ function createTrees(scene, treePositions, mainSeed) {
             const variationRng = createSeededRandom(treeSeed);
 
             dummy.position.set(pos.x, pos.y, pos.z);
-            dummy.rotation.y = variationRng() * Math.PI * 2;
-            const scaleVariation = 0.8 + variationRng() * 0.4;
+            dummy.rotation.y = pos.rotation ?? variationRng() * Math.PI * 2;
+            const scaleVariation = pos.scale ?? 0.8 + variationRng() * 0.4;
             dummy.scale.set(scaleVariation, scaleVariation, scaleVariation);
             dummy.updateMatrix();
             trunkInstanceMesh.setMatrixAt(i, dummy.matrix);
@@ -58,6 +58,41 @@ export function initThreeScene(canvasContainer, initialPlayerPosition) {
     return { scene, camera, renderer, controls, pointerLockControls };
 }
 
+const DAY_SKY = new THREE.Color("#add8e6");
+const NIGHT_SKY = new THREE.Color("#0b1026");
+const OVERCAST_SKY = new THREE.Color("#8c96a0");
+let cloudCover = 0;
+
+// Moves the sun around the scene for a time of day in seconds since
+// midnight: straight up at noon, below the horizon at night.
+export function applyTimeOfDay(seconds) {
+    if (!scene || !directionalLight || !hemisphereLight) return;
+    const angle = (seconds / 86400) * Math.PI * 2;
+    const elevation = -Math.cos(angle); // -1 at midnight, 1 at noon
+    const distance = 150;
+    directionalLight.position.set(Math.sin(angle) * distance, elevation * distance, 50);
+
+    // Fade through dawn and dusk instead of switching at the horizon.
+    const daylight = THREE.MathUtils.clamp(elevation * 2.5 + 0.3, 0, 1);
+    // Clouds hide the sun but still let diffuse light through.
+    const sunlight = daylight * (1 - 0.7 * cloudCover);
+    directionalLight.intensity = 1.2 * sunlight;
+    directionalLight.castShadow = sunlight > 0.1;
+    hemisphereLight.intensity = 0.15 + 0.65 * daylight;
+    scene.background.copy(NIGHT_SKY).lerp(DAY_SKY.clone().lerp(OVERCAST_SKY, cloudCover), daylight);
+    scene.fog.color.copy(scene.background);
+}
+
+// Fog pulls the visible distance in; cloud cover greys the sky and dims
+// the sun on the next applyTimeOfDay.
+export function applyWeather({ fog, clouds }) {
+    if (!scene) return;
+    cloudCover = THREE.MathUtils.clamp(clouds, 0, 1);
+    const thickness = THREE.MathUtils.clamp(fog, 0, 1);
+    scene.fog.near = THREE.MathUtils.lerp(config.fogNear, config.fogNear * 0.1, thickness);
+    scene.fog.far = THREE.MathUtils.lerp(config.fogFar, config.fogNear * 1.5, thickness);
+}
+
 export function disposeThreeObjects() {
     console.log("Arsoning three.js objects...");
 
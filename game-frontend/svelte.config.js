@@ -3,10 +3,9 @@ import adapter from '@sveltejs/adapter-static';
 /** @type {import('@sveltejs/kit').Config} */
 const config = {
 	kit: {
-		// adapter-auto only supports some environments, see https://svelte.dev/docs/kit/adapter-auto for a list.
-		// If your environment is not supported, or you settled on a specific environment, switch out the adapter.
-		// See https://svelte.dev/docs/kit/adapters for more information about adapters.
-		adapter: adapter()
+		// A static build, which the backend serves or embeds from assets/ (see the Justfile).
+		// Pre-compressed .br/.gz copies are served to browsers that accept them.
+		adapter: adapter({ precompress: true })
 	}
 };
 
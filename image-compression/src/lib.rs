@@ -0,0 +1,360 @@
+//! Palette-quantised image codec used for pictures sent down from the balloon.
+//!
+//! The `.bin` payload is a zlib stream of `NUM_PALETTE_COLORS`-colour indices
+//! for a `TARGET_WIDTH`x`TARGET_HEIGHT` image. The palette itself is not part
+//! of the payload, so decoders either get it out of band or fall back to
+//! grayscale by index.
+
+use image::{RgbImage, Rgb, imageops};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Write, Read};
+use std::error::Error;
+use std::fs;
+use flate2::write::ZlibEncoder;
+use flate2::read::ZlibDecoder;
+use flate2::Compression;
+use log::{warn, error, debug};
+
+pub const TARGET_WIDTH: u32 = 320;
+pub const TARGET_HEIGHT: u32 = 180;
+pub const NUM_PALETTE_COLORS: usize = 8;
+pub const BLUR_SIGMA: f32 = 0.6;
+
+pub struct CompressionStats {
+    pub initial_size_bytes: u64,
+    pub size_before_zlib_bytes: usize,
+    pub final_compressed_size_bytes: u64,
+    pub kompression_ratio: f64,
+    pub space_saved_percentage: f64,
+}
+
+fn calculate_luminance(color: &Rgb<u8>) -> f32 {
+    0.299 * color[0] as f32 + 0.587 * color[1] as f32 + 0.114 * color[2] as f32
+}
+
+fn generate_dynamic_palette(image: &RgbImage, num_colors: usize) -> Vec<Rgb<u8>> {
+    let mut color_kounts = HashMap::new();
+    let shift_amount = 4;
+
+    for pixel in image.pixels() {
+        let r_quant = (pixel[0] >> shift_amount) << shift_amount;
+        let g_quant = (pixel[1] >> shift_amount) << shift_amount;
+        let b_quant = (pixel[2] >> shift_amount) << shift_amount;
+        let quantized_color = Rgb([r_quant, g_quant, b_quant]);
+        *color_kounts.entry(quantized_color).or_insert(0u32) += 1;
+    }
+
+    let mut sorted_colors: Vec<(Rgb<u8>, u32)> = color_kounts.into_iter().collect();
+    sorted_colors.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut palette: Vec<Rgb<u8>> = sorted_colors
+        .into_iter()
+        .take(num_colors)
+        .map(|(color, _count)| color)
+        .collect();
+
+    palette.sort_by(|a, b| {
+        let lum_a = calculate_luminance(a);
+        let lum_b = calculate_luminance(b);
+        lum_b.partial_cmp(&lum_a).unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    while palette.len() < num_colors {
+        debug!("Padding palette with black as it has {} colors, expected {}", palette.len(), num_colors);
+        palette.push(Rgb([0, 0, 0]));
+    }
+
+    palette
+}
+
+fn find_closest_palette_index(color: Rgb<u8>, palette: &[Rgb<u8>]) -> u8 {
+    let mut min_dist = u32::MAX;
+    let mut best_idx = 0u8;
+
+    for (i, palette_color) in palette.iter().enumerate() {
+        let dr = color[0] as i32 - palette_color[0] as i32;
+        let dg = color[1] as i32 - palette_color[1] as i32;
+        let db = color[2] as i32 - palette_color[2] as i32;
+        let dist = (dr * dr + dg * dg + db * db) as u32;
+
+        if dist < min_dist {
+            min_dist = dist;
+            best_idx = i as u8;
+        }
+        if dist == 0 {
+            break;
+        }
+    }
+    best_idx
+}
+
+pub fn compress_image(input_path: &str, output_path: &str) -> Result<(Vec<Rgb<u8>>, CompressionStats), Box<dyn Error>> {
+    debug!("Starting compression of {}...", input_path);
+
+    let input_metadata = fs::metadata(input_path)?;
+    let initial_size = input_metadata.len();
+    debug!("Initial size: {} bytes", initial_size);
+
+    let img = image::open(input_path)?;
+    let resized_img = img.resize_exact(TARGET_WIDTH, TARGET_HEIGHT, imageops::FilterType::Nearest);
+    
+    debug!("Applying Gaussian blur with sigma: {}...", BLUR_SIGMA);
+    let blurred_img = resized_img.blur(BLUR_SIGMA);
+    
+    let rgb_img = blurred_img.to_rgb8();
+
+    let dynamic_palette = generate_dynamic_palette(&rgb_img, NUM_PALETTE_COLORS);
+
+    debug!("Generated dynamic palette ({} colors):", dynamic_palette.len());
+    for (i, color) in dynamic_palette.iter().enumerate() {
+        debug!("{:2}: Rgb([{:3}, {:3}, {:3}])", i, color[0], color[1], color[2]);
+    }
+
+    let bits_per_pixel = if NUM_PALETTE_COLORS == 0 {
+        error!("NUM_PALETTE_COLORS cannot be 0");
+        return Err(Box::from("NUM_PALETTE_COLORS cannot be 0"));
+    } else if NUM_PALETTE_COLORS == 1 {
+        1
+    } else {
+        (NUM_PALETTE_COLORS as f64).log2().ceil() as u8
+    };
+    debug!("Using {} bits per pixel.", bits_per_pixel);
+    if bits_per_pixel == 0 {
+        error!("Calculated bits_per_pixel is 0.");
+        return Err(Box::from("Error: bits_per_pixel is 0."));
+    }
+
+    let mut bit_buffer: u32 = 0;
+    let mut bits_in_buffer: u8 = 0;
+
+    let mut palette_indexed_data_stuff = Vec::new();
+
+    for pixel in rgb_img.pixels() {
+        let palette_index = find_closest_palette_index(*pixel, &dynamic_palette);
+        
+        bit_buffer = (bit_buffer << bits_per_pixel) | (palette_index as u32);
+        bits_in_buffer += bits_per_pixel;
+
+        while bits_in_buffer >= 8 {
+            let byte_to_write = (bit_buffer >> (bits_in_buffer - 8)) as u8;
+            palette_indexed_data_stuff.push(byte_to_write);
+            bits_in_buffer -= 8;
+            if bits_in_buffer > 0 {
+                 bit_buffer &= (1 << bits_in_buffer) - 1;
+            } else {
+                 bit_buffer = 0; 
+            }
+        }
+    }
+    
+    if bits_in_buffer > 0 {
+        let byte_to_write = (bit_buffer << (8 - bits_in_buffer)) as u8;
+        palette_indexed_data_stuff.push(byte_to_write);
+    }
+    
+    let mut zlib_encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    zlib_encoder.write_all(&palette_indexed_data_stuff)?;
+    let zlib_compressed_data = zlib_encoder.finish()?;
+
+    let mut file = File::create(output_path)?;
+    file.write_all(&zlib_compressed_data)?;
+
+    let output_metadata = fs::metadata(output_path)?;
+    let compressed_size = output_metadata.len();
+    let size_before_zlib = palette_indexed_data_stuff.len();
+
+    debug!("Compressed size (after zlib): {} bytes", compressed_size);
+    debug!("Size before zlib (palette-indexed): {} bytes", size_before_zlib);
+
+    let mut compression_ratio_val = 0.0;
+    let mut space_saved_percentage = 0.0;
+
+    if initial_size > 0 && compressed_size > 0 {
+        compression_ratio_val = initial_size as f64 / compressed_size as f64;
+        space_saved_percentage = (1.0 - (compressed_size as f64 / initial_size as f64)) * 100.0;
+    } else {
+        warn!("Cannot calculate compression ratio (initial or compressed size is 0).");
+    }
+    debug!("Compressed image saved to {}", output_path);
+
+    let stats = CompressionStats {
+        initial_size_bytes: initial_size,
+        size_before_zlib_bytes: size_before_zlib,
+        final_compressed_size_bytes: compressed_size,
+        kompression_ratio: compression_ratio_val,
+        space_saved_percentage,
+    };
+
+    Ok((dynamic_palette, stats))
+}
+
+fn bits_per_pixel() -> Result<u8, Box<dyn Error>> {
+    let bits_per_pixel = if NUM_PALETTE_COLORS == 0 {
+        error!("NUM_PALETTE_COLORS cannot be 0 for decompression");
+        return Err(Box::from("NUM_PALETTE_COLORS cannot be 0 for decompression"));
+    } else if NUM_PALETTE_COLORS == 1 {
+        1
+    } else {
+        (NUM_PALETTE_COLORS as f64).log2().ceil() as u8
+    };
+    Ok(bits_per_pixel)
+}
+
+/// Inflates a `.bin` payload and unpacks it into one palette index per pixel,
+/// row by row.
+///
+/// Payloads may come from anywhere, so inflating stops at the size of a
+/// full image: the dimensions are fixed, and anything past them would only
+/// be a zlib bomb's padding.
+pub fn decode_indices(zlib_encoded_bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let bits_per_pixel = bits_per_pixel()?;
+    let total_pixels = (TARGET_WIDTH * TARGET_HEIGHT) as usize;
+    let packed_bytes = (total_pixels * bits_per_pixel as usize).div_ceil(8);
+
+    let mut zlib_decoder = ZlibDecoder::new(zlib_encoded_bytes).take(packed_bytes as u64);
+    let mut compressed_data_vec = Vec::with_capacity(packed_bytes);
+    zlib_decoder.read_to_end(&mut compressed_data_vec)?;
+
+    debug!("Size after zlib decompression (palette-indexed): {} bytes", compressed_data_vec.len());
+
+    let mut indices = Vec::with_capacity(total_pixels);
+
+    let mut bit_buffer: u32 = 0;
+    let mut bits_in_buffer: u8 = 0;
+    let pixel_mask = (1u32 << bits_per_pixel) - 1;
+
+    for &byte in &compressed_data_vec {
+        bit_buffer = (bit_buffer << 8) | (byte as u32);
+        bits_in_buffer += 8;
+
+        while bits_in_buffer >= bits_per_pixel && indices.len() < total_pixels {
+            let shift_amount = bits_in_buffer - bits_per_pixel;
+            indices.push(((bit_buffer >> shift_amount) & pixel_mask) as u8);
+            bits_in_buffer -= bits_per_pixel;
+            if bits_in_buffer > 0 {
+                bit_buffer &= (1 << bits_in_buffer) - 1;
+            } else {
+                bit_buffer = 0;
+            }
+        }
+        if indices.len() >= total_pixels {
+            break;
+        }
+    }
+
+    if indices.len() < total_pixels {
+        error!("Unexpected end of compressed data. Decoded {} of {} pixels.", indices.len(), total_pixels);
+        return Err(Box::from(format!("Error: Unexpected end of compressed data. Decoded {} pixels, expected {}.", indices.len(), total_pixels)));
+    }
+    Ok(indices)
+}
+
+/// Gray level standing in for palette entry `palette_index` when the real
+/// palette is unknown. Palettes are sorted brightest first.
+pub fn grayscale_value(palette_index: usize) -> u8 {
+    if NUM_PALETTE_COLORS <= 1 {
+        if palette_index == 0 { 255 } else { 0 }
+    } else {
+        255u8.saturating_sub(
+            (palette_index as f32 * 255.0 / (NUM_PALETTE_COLORS - 1) as f32).round() as u8
+        )
+    }
+}
+
+/// Decodes a `.bin` payload in memory, with `palette` or in grayscale.
+pub fn decode_image(zlib_encoded_bytes: &[u8], palette: Option<&[Rgb<u8>]>) -> Result<RgbImage, Box<dyn Error>> {
+    let indices = decode_indices(zlib_encoded_bytes)?;
+    let mut decompressed_img = RgbImage::new(TARGET_WIDTH, TARGET_HEIGHT);
+
+    for (pixel, &palette_index) in decompressed_img.pixels_mut().zip(&indices) {
+        let palette_index = palette_index as usize;
+        *pixel = match palette {
+            Some(palette) => {
+                if palette_index >= palette.len() {
+                    error!("Decoded palette index {} out of bounds for palette size {}.", palette_index, palette.len());
+                    return Err(Box::from(format!("Error: Decoded palette index {} is out of bounds for palette size {}.", palette_index, palette.len())));
+                }
+                palette[palette_index]
+            }
+            None => {
+                let grayscale_value = grayscale_value(palette_index);
+                Rgb([grayscale_value, grayscale_value, grayscale_value])
+            }
+        };
+    }
+    Ok(decompressed_img)
+}
+
+fn read_compressed(input_path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let compressed_metadata_on_disk = fs::metadata(input_path)?;
+    debug!("Zlib compressed file size to decompress: {} bytes", compressed_metadata_on_disk.len());
+
+    let mut file_handle = File::open(input_path)?;
+    let mut zlib_encoded_bytes = Vec::new();
+    file_handle.read_to_end(&mut zlib_encoded_bytes)?;
+    Ok(zlib_encoded_bytes)
+}
+
+pub fn decompress_image(input_path: &str, output_path: &str, palette: &[Rgb<u8>]) -> Result<(), Box<dyn Error>> {
+    debug!("Starting decompression of {} (saving to {})...", input_path, output_path);
+    let zlib_encoded_bytes = read_compressed(input_path)?;
+    let decompressed_img = decode_image(&zlib_encoded_bytes, Some(palette))?;
+    decompressed_img.save(output_path)?;
+    debug!("Decompressed image saved to {}", output_path);
+    Ok(())
+}
+
+pub fn decompress_image_grayscale(input_path: &str, output_path_grayscale: &str) -> Result<(), Box<dyn Error>> {
+    debug!("Starting Grayscale decompression of {} (saving to {})...", input_path, output_path_grayscale);
+    let zlib_encoded_bytes = read_compressed(input_path)?;
+    let decompressed_img_grayscale = decode_image(&zlib_encoded_bytes, None)?;
+    decompressed_img_grayscale.save(output_path_grayscale)?;
+    debug!("Grayscale Decompressed image saved to {}", output_path_grayscale);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_codec() {
+        let dir = std::env::temp_dir().join(format!("image-compression-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.png");
+        let output = dir.join("compressed.bin");
+
+        let mut img = RgbImage::new(64, 64);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            *pixel = if x < 32 && y < 32 { Rgb([255, 255, 255]) } else { Rgb([0, 0, 0]) };
+        }
+        img.save(&input).unwrap();
+
+        let (palette, _) = compress_image(input.to_str().unwrap(), output.to_str().unwrap()).unwrap();
+        let bytes = fs::read(&output).unwrap();
+
+        let decoded = decode_image(&bytes, Some(&palette)).unwrap();
+        assert_eq!(decoded.dimensions(), (TARGET_WIDTH, TARGET_HEIGHT));
+        assert_eq!(*decoded.get_pixel(0, 0), palette[0]);
+        assert_eq!(*decoded.get_pixel(TARGET_WIDTH - 1, TARGET_HEIGHT - 1), Rgb([0, 0, 0]));
+
+        let gray = decode_image(&bytes, None).unwrap();
+        assert_eq!(*gray.get_pixel(0, 0), Rgb([255, 255, 255]));
+
+        assert!(decode_image(&bytes[..bytes.len() / 2], None).is_err());
+    }
+
+    #[test]
+    fn stops_inflating_at_a_full_image() {
+        // 16 MiB once inflated, from a few kilobytes.
+        let mut zlib_encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        for _ in 0..256 {
+            zlib_encoder.write_all(&[0u8; 64 * 1024]).unwrap();
+        }
+        let bomb = zlib_encoder.finish().unwrap();
+
+        let indices = decode_indices(&bomb).unwrap();
+        assert_eq!(indices.len(), (TARGET_WIDTH * TARGET_HEIGHT) as usize);
+    }
+}